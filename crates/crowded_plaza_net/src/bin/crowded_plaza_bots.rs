@@ -0,0 +1,98 @@
+//! Connects scripted [`BotClient`]s to a server and reports how their matches went, to try a
+//! server end to end without opening the game.
+//!
+//! ```text
+//! cargo run --release -p crowded_plaza_net --bin crowded_plaza_bots -- [--server ws://127.0.0.1:7878]
+//!     [--count 4] [--matches 1]
+//! ```
+use crowded_plaza_net::{BotClient, DEFAULT_PORT};
+use crowded_plaza_sim::MatchEvent;
+use std::time::Duration;
+
+// helper: the value of `--name value`, parsed
+fn flag<T: std::str::FromStr>(name: &str) -> Option<T> {
+    let flag = format!("--{}", name);
+    let value = std::env::args().skip_while(|arg| *arg != flag).nth(1)?;
+    match value.parse() {
+        Ok(value) => Some(value),
+        Err(_) => panic!("invalid value `{}` for {}", value, flag),
+    }
+}
+
+fn main() {
+    // the connections log the server messages they cannot read
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+    let url = flag("server").unwrap_or(format!("ws://127.0.0.1:{}", DEFAULT_PORT));
+    let count: usize = flag("count").unwrap_or(4);
+    let matches: usize = flag("matches").unwrap_or(1);
+
+    let mut bots: Vec<BotClient> = (0..count)
+        .map(|index| {
+            BotClient::connect(&url, &format!("bot-{}", index))
+                .unwrap_or_else(|e| panic!("{}: {}", url, e))
+        })
+        .collect();
+
+    // until every bot saw enough matches end, or lost its connection
+    let mut reported = vec![0; count];
+    loop {
+        for (index, bot) in bots.iter_mut().enumerate() {
+            bot.update();
+            let ended = bot
+                .events
+                .iter()
+                .filter(|event| **event == MatchEvent::TimeUp)
+                .count();
+            if ended > reported[index] {
+                reported[index] = ended;
+                report(index, bot);
+            }
+        }
+        let done = bots.iter().zip(reported.iter()).all(|(bot, reported)| {
+            *reported >= matches || bot.rejected || !bot.connection().is_open()
+        });
+        if done {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+
+    for (index, bot) in bots.iter().enumerate() {
+        if bot.rejected {
+            println!("bot-{}: every seat was taken", index);
+        } else if reported[index] < matches {
+            println!("bot-{}: disconnected", index);
+        }
+    }
+}
+
+// a line per bot and match: where it ended up and what the connection carried
+fn report(index: usize, bot: &BotClient) {
+    let (faction, snapshot) = match (bot.faction(), &bot.snapshot) {
+        (Some(faction), Some(snapshot)) => (faction, snapshot),
+        _ => return,
+    };
+    let mut counts = std::collections::BTreeMap::new();
+    for actor in snapshot
+        .actors
+        .iter()
+        .filter(|actor| !actor.faction.is_neutral())
+    {
+        *counts.entry(actor.faction).or_insert(0) += 1;
+    }
+    let own_count = counts.get(&faction).copied().unwrap_or(0);
+    let rank = 1 + counts.values().filter(|count| **count > own_count).count();
+    let bytes = bot.connection().bytes_received();
+    println!(
+        "bot-{0} seat {1}: #{2} with {3} actors, {4} snapshots, {5} KB received, {6} bytes per snapshot",
+        index,
+        bot.seat.unwrap_or(0),
+        rank,
+        own_count,
+        bot.snapshots_received,
+        bytes / 1024,
+        bytes / bot.snapshots_received.max(1) as usize
+    );
+}
@@ -0,0 +1,163 @@
+//! Plays a [`Lockstep`] match without a server: one peer hosts, the others join it, and every
+//! peer steers its pawn toward the nearest neutral actor. A desync writes a report to the
+//! current directory.
+//!
+//! ```text
+//! cargo run --release -p crowded_plaza_net --bin crowded_plaza_lockstep -- [--port 7879]
+//!     [--player_count 2] [--input_delay 6] [--checksum_interval 60] [--actor_count 2000] ...
+//! cargo run --release -p crowded_plaza_net --bin crowded_plaza_lockstep -- --join ws://host:7879
+//!     [--name Ann] [--desync_at 600]
+//! ```
+//!
+//! The host decides the config, map and settings, any config field can be given as a flag and
+//! `--map` reads a map file.
+//! `--desync_at` nudges an actor on this peer only, to try the report.
+use bevy::prelude::*;
+use crowded_plaza_net::{Lockstep, LockstepHost, LockstepPeer, LockstepSettings, Snapshot};
+use crowded_plaza_sim::{Faction, GameConfig, Heading, PlazaMap, TICK_SECONDS};
+use std::time::{Duration, Instant};
+
+// next to the server's
+const PORT: u16 = 7879;
+// ticks between two steering decisions
+const DECISION_TICKS: u64 = 30;
+
+// helper: the value of `--name value`, parsed
+fn flag<T: std::str::FromStr>(name: &str) -> Option<T> {
+    let flag = format!("--{}", name);
+    let value = std::env::args().skip_while(|arg| *arg != flag).nth(1)?;
+    match value.parse() {
+        Ok(value) => Some(value),
+        Err(_) => panic!("invalid value `{}` for {}", value, flag),
+    }
+}
+
+fn main() {
+    // the peers log who takes a seat, and what they ignore
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+    let name: String = flag("name").unwrap_or_default();
+    let mut peer = match flag::<String>("join") {
+        Some(url) => {
+            eprintln!("Waiting for {} to start the match", url);
+            LockstepPeer::join(&url, &name).unwrap_or_else(|e| panic!("{}: {}", url, e))
+        }
+        None => host(&name),
+    };
+    let desync_at: Option<u64> = flag("desync_at");
+
+    let tick = Duration::from_secs_f32(TICK_SECONDS);
+    let mut next_tick = Instant::now();
+    while !peer.lockstep.is_over() {
+        if peer.lockstep.tick() % DECISION_TICKS == 0 {
+            steer(&mut peer.lockstep);
+        }
+        if desync_at == Some(peer.lockstep.tick()) {
+            let world = peer.lockstep.world();
+            if let Some(mut tr) = world.query::<&mut Transform>().iter_mut(world).next() {
+                tr.translation.x += 0.001;
+            }
+        }
+        peer.update();
+
+        if let Some(report) = peer.lockstep.desync() {
+            let path = report.file_name();
+            std::fs::write(&path, report.to_json()).expect("cannot write desync report");
+            eprintln!("Out of step after tick {}, wrote {}", report.tick, path);
+            std::process::exit(1);
+        }
+        if let Some(seat) = peer.left {
+            let name = peer
+                .names
+                .iter()
+                .find(|(faction, _)| *faction == Faction::Player(seat))
+                .map_or("A peer", |(_, name)| name.as_str());
+            eprintln!("{} left after tick {}", name, peer.lockstep.tick());
+            std::process::exit(1);
+        }
+
+        next_tick += tick;
+        let now = Instant::now();
+        if next_tick > now {
+            std::thread::sleep(next_tick - now);
+        } else {
+            next_tick = now;
+        }
+    }
+    println!(
+        "Match over after {} ticks, {} checksums matched",
+        peer.lockstep.tick(),
+        peer.lockstep.checksums_matched()
+    );
+}
+
+// helper: wait for the other peers and start the match
+fn host(name: &str) -> LockstepPeer {
+    let mut config = match flag::<String>("config") {
+        Some(path) => {
+            let bytes = std::fs::read(&path).expect("cannot read config file");
+            GameConfig::from_ron(&bytes).unwrap_or_else(|e| panic!("{}: {}", path, e))
+        }
+        None => GameConfig {
+            player_count: 2,
+            ..Default::default()
+        },
+    };
+    if let Some(path) = flag::<String>("map") {
+        let bytes = std::fs::read(&path).expect("cannot read map file");
+        config.map = PlazaMap::from_ron(&bytes).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    }
+    for field in GameConfig::FIELDS {
+        if let Some(value) = flag::<String>(field) {
+            config
+                .set_field(field, &value)
+                .unwrap_or_else(|e| panic!("{}", e));
+        }
+    }
+    if let Err(e) = config.validate() {
+        panic!("{}", e);
+    }
+    let defaults = LockstepSettings::default();
+    let settings = LockstepSettings {
+        input_delay: flag("input_delay").unwrap_or(defaults.input_delay),
+        checksum_interval: flag("checksum_interval").unwrap_or(defaults.checksum_interval),
+    };
+    if settings.checksum_interval == 0 {
+        panic!("--checksum_interval must be at least 1");
+    }
+
+    let port: u16 = flag("port").unwrap_or(PORT);
+    let host = LockstepHost::bind(("0.0.0.0", port))
+        .unwrap_or_else(|e| panic!("cannot listen on port {}: {}", port, e));
+    eprintln!(
+        "Waiting on ws://0.0.0.0:{} for {} more peers",
+        port,
+        config.player_count - 1
+    );
+    host.start(name, config, settings)
+        .unwrap_or_else(|e| panic!("{}", e))
+}
+
+// helper: head for the nearest neutral actor
+fn steer(lockstep: &mut Lockstep) {
+    let tick = lockstep.tick();
+    let faction = Faction::Player(lockstep.seat());
+    let snapshot = Snapshot::capture(lockstep.world(), tick, 0.0);
+    let pawn = match snapshot.pawn(faction) {
+        Some(pawn) => Vec2::from(pawn.position),
+        None => return,
+    };
+    let target = snapshot
+        .actors
+        .iter()
+        .filter(|actor| actor.faction.is_neutral())
+        .map(|actor| Vec2::from(actor.position))
+        .min_by(|a, b| {
+            a.distance_squared(pawn)
+                .total_cmp(&b.distance_squared(pawn))
+        });
+    if let Some(target) = target {
+        lockstep.steer(Heading::from_direction(target - pawn));
+    }
+}
@@ -0,0 +1,67 @@
+//! Runs the multiplayer [`Server`], matches one after the other until it is stopped.
+//!
+//! ```text
+//! cargo run --release -p crowded_plaza_net --bin crowded_plaza_server -- [--port 7878]
+//!     [--config game.config.ron] [--map fountain_square.map.ron] [--snapshot_interval 3]
+//!     [--restart_delay 5] [--player_count 8] [--actor_count 2000] ...
+//! ```
+//!
+//! Any config field can be given as a flag and overrides the config file, and a map file replaces
+//! its map. Without a config file there are 8 seats.
+use crowded_plaza_net::{Server, ServerSettings, DEFAULT_PORT};
+use crowded_plaza_sim::{GameConfig, PlazaMap};
+
+// helper: the value of `--name value`, parsed
+fn flag<T: std::str::FromStr>(name: &str) -> Option<T> {
+    let flag = format!("--{}", name);
+    let value = std::env::args().skip_while(|arg| *arg != flag).nth(1)?;
+    match value.parse() {
+        Ok(value) => Some(value),
+        Err(_) => panic!("invalid value `{}` for {}", value, flag),
+    }
+}
+
+fn main() {
+    // the server logs who takes and leaves a seat, and what it ignores
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+    let mut config = match flag::<String>("config") {
+        Some(path) => {
+            let bytes = std::fs::read(&path).expect("cannot read config file");
+            GameConfig::from_ron(&bytes).unwrap_or_else(|e| panic!("{}: {}", path, e))
+        }
+        None => GameConfig {
+            player_count: 8,
+            ..Default::default()
+        },
+    };
+    if let Some(path) = flag::<String>("map") {
+        let bytes = std::fs::read(&path).expect("cannot read map file");
+        config.map = PlazaMap::from_ron(&bytes).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    }
+    for field in GameConfig::FIELDS {
+        if let Some(value) = flag::<String>(field) {
+            config
+                .set_field(field, &value)
+                .unwrap_or_else(|e| panic!("{}", e));
+        }
+    }
+    if let Err(e) = config.validate() {
+        panic!("{}", e);
+    }
+    let defaults = ServerSettings::default();
+    let settings = ServerSettings {
+        snapshot_interval: flag("snapshot_interval").unwrap_or(defaults.snapshot_interval),
+        restart_delay: flag("restart_delay").unwrap_or(defaults.restart_delay),
+    };
+    if settings.snapshot_interval == 0 {
+        panic!("--snapshot_interval must be at least 1");
+    }
+
+    let port = flag("port").unwrap_or(DEFAULT_PORT);
+    let mut server = Server::bind(("0.0.0.0", port), config, settings)
+        .unwrap_or_else(|e| panic!("cannot listen on port {}: {}", port, e));
+    eprintln!("Listening on ws://0.0.0.0:{}", port);
+    server.run();
+}
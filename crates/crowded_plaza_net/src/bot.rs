@@ -0,0 +1,100 @@
+use bevy::prelude::*;
+use crowded_plaza_sim::{Faction, Heading, MatchEvent};
+
+use crate::{ClientMessage, Connection, NetError, ServerMessage, Snapshot};
+
+// snapshot ticks between two steering decisions
+const DECISION_TICKS: u64 = 30;
+
+/// A scripted player for trying out a server: joins, then every half second steers its pawn
+/// toward the nearest neutral actor.
+pub struct BotClient {
+    connection: Connection,
+    /// Given by the server once it took us in.
+    pub seat: Option<u32>,
+    /// The latest snapshot of the current match.
+    pub snapshot: Option<Snapshot>,
+    pub snapshots_received: u32,
+    /// Every match event seen so far.
+    pub events: Vec<MatchEvent>,
+    /// Whether every seat was taken.
+    pub rejected: bool,
+    last_decision: Option<u64>,
+}
+
+impl BotClient {
+    /// Connect to the server at `url` and ask for a seat.
+    pub fn connect(url: &str, name: &str) -> Result<Self, NetError> {
+        let mut connection = Connection::connect(url)?;
+        connection.send(&ClientMessage::Join {
+            name: name.to_string(),
+        });
+        Ok(BotClient {
+            connection,
+            seat: None,
+            snapshot: None,
+            snapshots_received: 0,
+            events: Vec::new(),
+            rejected: false,
+            last_decision: None,
+        })
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    pub fn faction(&self) -> Option<Faction> {
+        self.seat.map(Faction::Player)
+    }
+
+    /// Handle what the server sent since the last call, and steer if it's time to.
+    pub fn update(&mut self) {
+        for message in self.connection.receive() {
+            match message {
+                ServerMessage::Welcome { seat } => self.seat = Some(seat),
+                ServerMessage::Full => self.rejected = true,
+                ServerMessage::MatchStart { .. } => {
+                    self.snapshot = None;
+                    self.last_decision = None;
+                }
+                ServerMessage::Roster { .. } => {}
+                ServerMessage::Snapshot(snapshot) => {
+                    self.snapshots_received += 1;
+                    self.snapshot = Some(snapshot);
+                }
+                ServerMessage::Event { event } => self.events.push(event),
+            }
+        }
+
+        let (faction, snapshot) = match (self.faction(), &self.snapshot) {
+            (Some(faction), Some(snapshot)) => (faction, snapshot),
+            _ => return,
+        };
+        if matches!(self.last_decision, Some(tick) if snapshot.tick < tick + DECISION_TICKS) {
+            return;
+        }
+        let pawn = match snapshot.pawn(faction) {
+            Some(pawn) => Vec2::from(pawn.position),
+            None => return,
+        };
+        let target = snapshot
+            .actors
+            .iter()
+            .filter(|actor| actor.faction.is_neutral())
+            .map(|actor| Vec2::from(actor.position))
+            .min_by(|a, b| {
+                a.distance_squared(pawn)
+                    .total_cmp(&b.distance_squared(pawn))
+            });
+        self.last_decision = Some(snapshot.tick);
+        if let Some(target) = target {
+            let heading = Heading::from_direction(target - pawn);
+            // bots don't predict, any count of ticks will do
+            self.connection.send(&ClientMessage::Steer {
+                heading: heading.0,
+                tick: snapshot.tick,
+            });
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use crate::{decode, encode, ClientMessage, NetError, ServerMessage, SnapshotDecoder};
+use bevy::log::warn;
+
+// helper: whether a socket error only means a nonblocking socket has to try again later
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn is_would_block(error: &tungstenite::Error) -> bool {
+    matches!(error, tungstenite::Error::Io(e) if e.kind() == std::io::ErrorKind::WouldBlock)
+}
+
+// helper: a text message, a match start sets up decoding the snapshots of that match
+fn read_text(
+    text: &str,
+    snapshots: &mut Option<SnapshotDecoder>,
+) -> Result<ServerMessage, NetError> {
+    let message = decode(text)?;
+    if let ServerMessage::MatchStart { config, .. } = &message {
+        *snapshots = Some(SnapshotDecoder::new(config.action_region));
+    }
+    Ok(message)
+}
+
+// helper: a binary message, always a snapshot
+fn read_binary(
+    bytes: &[u8],
+    snapshots: &mut Option<SnapshotDecoder>,
+) -> Result<ServerMessage, NetError> {
+    let decoder = snapshots
+        .as_mut()
+        .ok_or_else(|| NetError::Malformed("snapshot before the match started".to_string()))?;
+    decoder
+        .decode(bytes)
+        .map(ServerMessage::Snapshot)
+        .map_err(|e| NetError::Malformed(e.to_string()))
+}
+
+/// A client's WebSocket connection to the server. Never blocks after connecting: sends are
+/// queued and [`receive`](Connection::receive) returns what arrived so far.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct Connection {
+    socket: tungstenite::WebSocket<tungstenite::stream::MaybeTlsStream<std::net::TcpStream>>,
+    open: bool,
+    bytes_received: usize,
+    snapshots: Option<SnapshotDecoder>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Connection {
+    /// Connect to a `ws://` url, blocks until the handshake is done.
+    pub fn connect(url: &str) -> Result<Self, NetError> {
+        use tungstenite::stream::MaybeTlsStream;
+
+        let (mut socket, _) =
+            tungstenite::connect(url).map_err(|e| NetError::Connect(e.to_string()))?;
+        if let MaybeTlsStream::Plain(stream) = socket.get_mut() {
+            stream
+                .set_nonblocking(true)
+                .and_then(|_| stream.set_nodelay(true))
+                .map_err(|e| NetError::Connect(e.to_string()))?;
+        }
+        Ok(Connection {
+            socket,
+            open: true,
+            bytes_received: 0,
+            snapshots: None,
+        })
+    }
+
+    pub fn send(&mut self, message: &ClientMessage) {
+        if !self.open {
+            return;
+        }
+        let message = tungstenite::Message::text(encode(message));
+        if let Err(e) = self.socket.send(message) {
+            self.open = is_would_block(&e);
+        }
+    }
+
+    /// Messages received since the last call, in order. Snapshots are acknowledged.
+    pub fn receive(&mut self) -> Vec<ServerMessage> {
+        let mut messages = Vec::new();
+        while self.open {
+            let message = match self.socket.read() {
+                Ok(tungstenite::Message::Text(text)) => {
+                    self.bytes_received += text.len();
+                    read_text(&text, &mut self.snapshots)
+                }
+                Ok(tungstenite::Message::Binary(bytes)) => {
+                    self.bytes_received += bytes.len();
+                    read_binary(&bytes, &mut self.snapshots)
+                }
+                Ok(tungstenite::Message::Close(_)) => {
+                    self.open = false;
+                    continue;
+                }
+                Ok(_) => continue,
+                Err(e) => {
+                    self.open = is_would_block(&e);
+                    break;
+                }
+            };
+            match message {
+                Ok(message) => {
+                    if let ServerMessage::Snapshot(snapshot) = &message {
+                        self.send(&ClientMessage::Ack {
+                            tick: snapshot.tick,
+                        });
+                    }
+                    messages.push(message);
+                }
+                Err(e) => warn!("Ignoring server message: {}", e),
+            }
+        }
+        messages
+    }
+
+    /// Whether the connection is still up, or at least not known to be closed.
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Payload bytes of every message received so far.
+    pub fn bytes_received(&self) -> usize {
+        self.bytes_received
+    }
+}
+
+/// A client's WebSocket connection to the server. Never blocks after connecting: sends are
+/// queued and [`receive`](Connection::receive) returns what arrived so far.
+#[cfg(target_arch = "wasm32")]
+pub struct Connection {
+    socket: web_sys::WebSocket,
+    // text messages are `Ok`, binary ones `Err`
+    inbox: std::rc::Rc<std::cell::RefCell<Vec<Result<String, Vec<u8>>>>>,
+    // messages sent before the socket opened
+    outbox: Vec<String>,
+    bytes_received: usize,
+    snapshots: Option<SnapshotDecoder>,
+    // keeps the callback alive as long as the socket
+    _on_message: wasm_bindgen::closure::Closure<dyn FnMut(web_sys::MessageEvent)>,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Connection {
+    /// Start connecting to a `ws://` or `wss://` url, messages sent meanwhile are queued.
+    pub fn connect(url: &str) -> Result<Self, NetError> {
+        use wasm_bindgen::JsCast;
+
+        let socket =
+            web_sys::WebSocket::new(url).map_err(|e| NetError::Connect(format!("{:?}", e)))?;
+        let inbox = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let on_message = {
+            let inbox = inbox.clone();
+            wasm_bindgen::closure::Closure::wrap(Box::new(move |event: web_sys::MessageEvent| {
+                let data = event.data();
+                if let Some(text) = data.as_string() {
+                    inbox.borrow_mut().push(Ok(text));
+                } else if let Ok(buffer) = data.dyn_into::<js_sys::ArrayBuffer>() {
+                    inbox
+                        .borrow_mut()
+                        .push(Err(js_sys::Uint8Array::new(&buffer).to_vec()));
+                }
+            })
+                as Box<dyn FnMut(web_sys::MessageEvent)>)
+        };
+        socket.set_binary_type(web_sys::BinaryType::Arraybuffer);
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        Ok(Connection {
+            socket,
+            inbox,
+            outbox: Vec::new(),
+            bytes_received: 0,
+            snapshots: None,
+            _on_message: on_message,
+        })
+    }
+
+    pub fn send(&mut self, message: &ClientMessage) {
+        self.outbox.push(encode(message));
+        if self.socket.ready_state() == web_sys::WebSocket::OPEN {
+            for text in self.outbox.drain(..) {
+                let _ = self.socket.send_with_str(&text);
+            }
+        }
+    }
+
+    /// Messages received since the last call, in order. Snapshots are acknowledged.
+    pub fn receive(&mut self) -> Vec<ServerMessage> {
+        if self.socket.ready_state() == web_sys::WebSocket::OPEN {
+            for text in self.outbox.drain(..) {
+                let _ = self.socket.send_with_str(&text);
+            }
+        }
+        let mut messages = Vec::new();
+        let received: Vec<_> = self.inbox.borrow_mut().drain(..).collect();
+        for data in received {
+            let message = match data {
+                Ok(text) => {
+                    self.bytes_received += text.len();
+                    read_text(&text, &mut self.snapshots)
+                }
+                Err(bytes) => {
+                    self.bytes_received += bytes.len();
+                    read_binary(&bytes, &mut self.snapshots)
+                }
+            };
+            match message {
+                Ok(message) => {
+                    if let ServerMessage::Snapshot(snapshot) = &message {
+                        self.send(&ClientMessage::Ack {
+                            tick: snapshot.tick,
+                        });
+                    }
+                    messages.push(message);
+                }
+                Err(e) => warn!("Ignoring server message: {}", e),
+            }
+        }
+        messages
+    }
+
+    /// Whether the connection is still up, or at least not known to be closed.
+    pub fn is_open(&self) -> bool {
+        self.socket.ready_state() <= web_sys::WebSocket::OPEN
+    }
+
+    /// Payload bytes of every message received so far.
+    pub fn bytes_received(&self) -> usize {
+        self.bytes_received
+    }
+}
@@ -0,0 +1,102 @@
+use crowded_plaza_sim::TICK_SECONDS;
+use std::collections::VecDeque;
+
+use crate::Snapshot;
+
+// further off than this, playback jumps instead of catching up
+const MAX_DRIFT_TICKS: f64 = 60.0;
+// how much faster or slower than real time playback may run to get back to the delay
+const MAX_RATE_CHANGE: f64 = 0.1;
+
+/// Plays the snapshots back a little behind the latest one, so there is a snapshot on both sides
+/// of the time drawn even when some arrive late or not at all. Drawing moves every actor from
+/// the earlier snapshot to the later one.
+pub struct SnapshotBuffer {
+    delay_ticks: f64,
+    // by tick, the first one at or before `render_tick`
+    snapshots: VecDeque<Snapshot>,
+    render_tick: Option<f64>,
+}
+
+impl SnapshotBuffer {
+    /// Play back `delay` seconds behind the latest snapshot, a couple of snapshot intervals and
+    /// the usual jitter.
+    pub fn new(delay: f32) -> Self {
+        SnapshotBuffer {
+            delay_ticks: (delay / TICK_SECONDS) as f64,
+            snapshots: VecDeque::new(),
+            render_tick: None,
+        }
+    }
+
+    /// Snapshots older than the time drawn are dropped.
+    pub fn push(&mut self, snapshot: Snapshot) {
+        if matches!(self.render_tick, Some(render_tick) if (snapshot.tick as f64) < render_tick) {
+            return;
+        }
+        let index = self
+            .snapshots
+            .partition_point(|buffered| buffered.tick < snapshot.tick);
+        match self.snapshots.get(index) {
+            Some(buffered) if buffered.tick == snapshot.tick => {}
+            _ => self.snapshots.insert(index, snapshot),
+        }
+    }
+
+    /// Forget every snapshot, for the next match.
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.render_tick = None;
+    }
+
+    /// The latest snapshot received.
+    pub fn latest(&self) -> Option<&Snapshot> {
+        self.snapshots.back()
+    }
+
+    /// The tick drawn, between two ticks most of the time.
+    pub fn render_tick(&self) -> Option<f64> {
+        self.render_tick
+    }
+
+    /// Move the time drawn on by `seconds`, a bit faster or slower to keep the delay. It never
+    /// goes past the latest snapshot.
+    pub fn advance(&mut self, seconds: f32) {
+        let latest = match self.snapshots.back() {
+            Some(latest) => latest.tick as f64,
+            None => return,
+        };
+        let target = latest - self.delay_ticks;
+        let render_tick = match self.render_tick {
+            Some(render_tick) if (target - render_tick).abs() <= MAX_DRIFT_TICKS => {
+                let rate =
+                    1.0 + ((target - render_tick) * 0.05).clamp(-MAX_RATE_CHANGE, MAX_RATE_CHANGE);
+                render_tick + (seconds / TICK_SECONDS) as f64 * rate
+            }
+            _ => target,
+        };
+        let first = self
+            .snapshots
+            .front()
+            .map_or(0.0, |first| first.tick as f64);
+        let render_tick = render_tick.min(latest).max(first);
+        self.render_tick = Some(render_tick);
+
+        while self.snapshots.len() > 1 && self.snapshots[1].tick as f64 <= render_tick {
+            self.snapshots.pop_front();
+        }
+    }
+
+    /// The snapshots to draw between and how far between them, from 0 to 1. Once playback caught
+    /// up with the latest snapshot, both are the latest.
+    pub fn sample(&self) -> Option<(&Snapshot, &Snapshot, f32)> {
+        let render_tick = self.render_tick?;
+        let from = self.snapshots.front()?;
+        let to = match self.snapshots.get(1) {
+            Some(to) => to,
+            None => return Some((from, from, 1.0)),
+        };
+        let fraction = (render_tick - from.tick as f64) / (to.tick - from.tick) as f64;
+        Some((from, to, fraction.clamp(0.0, 1.0) as f32))
+    }
+}
@@ -0,0 +1,56 @@
+//! Multiplayer for Crowded Plaza: an authoritative [`Server`] running the simulation of
+//! `crowded_plaza_sim`, and the WebSocket [`Connection`] clients use to play on it.
+//!
+//! Clients send their steering, the server ticks the match and sends snapshots of every actor.
+//! Messages are JSON text, see [`ClientMessage`] and [`ServerMessage`], except for snapshots:
+//! those are quantized binary deltas against the last snapshot the client acknowledged, see
+//! [`SnapshotEncoder`].
+//!
+//! ```text
+//! < {"type":"join","name":"Ann"}
+//! > {"type":"welcome","seat":1}
+//! > {"type":"match_start","seed":42,"tick":312,"config":{...},"names":[...]}
+//! < {"type":"steer","heading":16384,"tick":97}
+//! > (binary snapshot of tick 315)
+//! < {"type":"ack","tick":315}
+//! > {"type":"event","event":{"PawnEliminated":{"faction":{"Bot":2}}}}
+//! ```
+//!
+//! Clients move their own pawn right away with a [`PawnPredictor`], corrected by the snapshots,
+//! and draw the other actors a little in the past between two snapshots of a [`SnapshotBuffer`].
+//! A [`SimulatedLink`] tries both over a bad connection without a network.
+//!
+//! Without a server, peers can play in [`Lockstep`] instead: each one simulates the whole match
+//! and they only exchange their steering, see [`LockstepHost`] and [`LockstepPeer`]. Checksums
+//! of the state find the peers that went out of step.
+#[cfg(not(target_arch = "wasm32"))]
+mod bot;
+mod connection;
+mod interpolation;
+mod lockstep;
+#[cfg(not(target_arch = "wasm32"))]
+mod peer;
+mod prediction;
+mod protocol;
+#[cfg(not(target_arch = "wasm32"))]
+mod server;
+mod simulated_link;
+mod snapshot;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::{
+    bot::BotClient,
+    peer::{LockstepHost, LockstepPeer},
+    server::{Server, ServerSettings},
+};
+pub use crate::{
+    connection::Connection,
+    interpolation::SnapshotBuffer,
+    lockstep::{DesyncReport, Lockstep, LockstepSettings, PeerMessage},
+    prediction::{InputTracker, PawnPredictor},
+    protocol::{
+        decode, encode, ActorState, ClientMessage, NetError, ServerMessage, Snapshot, DEFAULT_PORT,
+    },
+    simulated_link::{LinkConditions, SimulatedLink},
+    snapshot::{Quantizer, SnapshotDecoder, SnapshotEncoder, SnapshotError},
+};
@@ -0,0 +1,343 @@
+use bevy::{
+    ecs::event::{Events, ManualEventReader},
+    prelude::*,
+};
+use crowded_plaza_sim::{
+    headless_match, Faction, GameConfig, Heading, MatchEvent, PlayerInput, SimClock,
+    MAX_TICKS_PER_UPDATE,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{protocol::time_left, Snapshot};
+
+/// How the peers of a [`Lockstep`] match keep in step.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LockstepSettings {
+    /// Ticks between steering and the tick it is applied on, the time every peer has to receive
+    /// it. Shorter feels more direct, but stalls on a slower connection.
+    pub input_delay: u32,
+    /// Ticks between two checksums of the state, compared between the peers to find desyncs.
+    pub checksum_interval: u32,
+}
+
+impl Default for LockstepSettings {
+    fn default() -> Self {
+        LockstepSettings {
+            input_delay: 6,
+            checksum_interval: 60,
+        }
+    }
+}
+
+/// What lockstep peers send each other, one JSON text message each.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PeerMessage {
+    /// The first message to the host after connecting, asks for a seat.
+    Join { name: String },
+    /// From the host once every seat is taken: the same match for every peer, it plays
+    /// `Faction::Player(seat)`.
+    Start {
+        seat: u32,
+        seed: u64,
+        config: Box<GameConfig>,
+        settings: LockstepSettings,
+        names: Vec<(Faction, String)>,
+    },
+    /// The steering of `seat` applied on `tick`, a [`Heading`] or none to keep going. Every
+    /// peer sends one for every tick.
+    Input {
+        seat: u32,
+        tick: u64,
+        heading: Option<u16>,
+    },
+    /// The [`Snapshot::checksum`] of `seat` after `tick`.
+    Checksum { seat: u32, tick: u64, checksum: u64 },
+    /// The peer in `seat` left, the match cannot go on without their steering.
+    Left { seat: u32 },
+}
+
+/// What a peer knew when its checksum did not match another peer's: enough to play the match
+/// again up to the tick, and to diff the state against the other peers' reports.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DesyncReport {
+    /// The seat of the peer that wrote the report.
+    pub seat: u32,
+    /// The first tick the checksums differed after.
+    pub tick: u64,
+    pub checksum: u64,
+    /// The checksums of the other seats that arrived, by seat.
+    pub remote_checksums: Vec<(u32, u64)>,
+    pub seed: u64,
+    pub config: GameConfig,
+    pub settings: LockstepSettings,
+    /// Every steering applied up to `tick` as `(tick, seat, heading)`, in tick order.
+    pub inputs: Vec<(u64, u32, u16)>,
+    /// Every actor after `tick` on this peer.
+    pub snapshot: Snapshot,
+}
+
+impl DesyncReport {
+    /// A file name no other peer's report of the same desync uses.
+    pub fn file_name(&self) -> String {
+        format!("desync-{}-seat{}.json", self.tick, self.seat)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("reports always serialize")
+    }
+}
+
+// the checksums of a tick, until every peer's arrived
+#[derive(Default)]
+struct Checks {
+    // ours, once the tick ran
+    local: Option<(u64, Snapshot)>,
+    remote: BTreeMap<u32, u64>,
+}
+
+/// Deterministic lockstep: every peer runs the whole match, and only the steering goes over the
+/// network. A tick runs once the steering of every seat for it arrived, so all peers simulate
+/// the same ticks from the same input.
+///
+/// Steering is applied [`LockstepSettings::input_delay`] ticks after it was given, to give it
+/// time to arrive. Every [`LockstepSettings::checksum_interval`] ticks the peers compare a
+/// checksum of the state; floating point math that differs between machines shows up there as a
+/// [`DesyncReport`] and the match stops.
+///
+/// This only keeps the state, carrying the messages is up to the caller, see
+/// [`LockstepPeer`](crate::LockstepPeer) for one over WebSocket.
+pub struct Lockstep {
+    seat: u32,
+    seed: u64,
+    config: GameConfig,
+    settings: LockstepSettings,
+    app: App,
+    match_events: ManualEventReader<MatchEvent>,
+    /// Every match event seen so far.
+    pub events: Vec<MatchEvent>,
+    // steered since the last input was sent
+    steer: Option<Heading>,
+    // the last tick each seat's input arrived for, ours included
+    received: Vec<u64>,
+    // steering of the ticks that did not run yet, by tick and seat
+    inputs: BTreeMap<u64, BTreeMap<u32, Heading>>,
+    // steering of the ticks that ran, for the report
+    applied: Vec<(u64, u32, u16)>,
+    checks: BTreeMap<u64, Checks>,
+    checksums_matched: u32,
+    desync: Option<DesyncReport>,
+}
+
+impl Lockstep {
+    /// Set up the match `seed` plays for `config`, with a seat for each of
+    /// [`GameConfig::player_count`] peers. `config` is expected to be validated.
+    pub fn new(seat: u32, seed: u64, config: GameConfig, settings: LockstepSettings) -> Self {
+        // the clock starts paused so setting up the match doesn't tick it
+        let mut app = headless_match(config.clone(), Some(seed), true);
+        app.update();
+        app.world.resource_mut::<SimClock>().paused = false;
+
+        // nobody steers in the ticks before the first input arrives
+        let seats = config.player_count as usize;
+        Lockstep {
+            seat,
+            seed,
+            config,
+            settings,
+            app,
+            match_events: ManualEventReader::default(),
+            events: Vec::new(),
+            steer: None,
+            received: vec![settings.input_delay as u64; seats],
+            inputs: BTreeMap::new(),
+            applied: Vec::new(),
+            checks: BTreeMap::new(),
+            checksums_matched: 0,
+            desync: None,
+        }
+    }
+
+    pub fn seat(&self) -> u32 {
+        self.seat
+    }
+
+    /// The last tick that ran.
+    pub fn tick(&self) -> u64 {
+        self.app.world.resource::<SimClock>().tick()
+    }
+
+    /// The simulated match, as far as it ran.
+    pub fn world(&mut self) -> &mut World {
+        &mut self.app.world
+    }
+
+    /// Set once a checksum did not match another peer's, the match stops there.
+    pub fn desync(&self) -> Option<&DesyncReport> {
+        self.desync.as_ref()
+    }
+
+    /// Ticks every peer's checksum arrived for and matched.
+    pub fn checksums_matched(&self) -> u32 {
+        self.checksums_matched
+    }
+
+    /// Whether the match timer ran out.
+    pub fn is_over(&self) -> bool {
+        self.events.contains(&MatchEvent::TimeUp)
+    }
+
+    /// Turn the own pawn, the latest heading until the next input is sent counts.
+    pub fn steer(&mut self, heading: Heading) {
+        self.steer = Some(heading);
+    }
+
+    /// Take the input and checksum of another peer.
+    pub fn receive(&mut self, message: PeerMessage) {
+        match message {
+            PeerMessage::Input {
+                seat,
+                tick,
+                heading,
+            } => {
+                let received = match self.received.get_mut(seat as usize) {
+                    Some(received) if seat != self.seat && tick > *received => received,
+                    _ => return,
+                };
+                *received = tick;
+                if let Some(heading) = heading {
+                    self.inputs
+                        .entry(tick)
+                        .or_default()
+                        .insert(seat, Heading(heading));
+                }
+            }
+            PeerMessage::Checksum {
+                seat,
+                tick,
+                checksum,
+            } => {
+                if seat != self.seat && (seat as usize) < self.received.len() {
+                    self.checks
+                        .entry(tick)
+                        .or_default()
+                        .remote
+                        .insert(seat, checksum);
+                    self.compare(tick);
+                }
+            }
+            PeerMessage::Join { .. } | PeerMessage::Start { .. } | PeerMessage::Left { .. } => {}
+        }
+    }
+
+    /// Run the ticks every seat's steering arrived for: one, or a few more to catch up with
+    /// the other peers. Returns the input and checksums to send to every other peer.
+    pub fn advance(&mut self) -> Vec<PeerMessage> {
+        let mut messages = Vec::new();
+        for _ in 0..MAX_TICKS_PER_UPDATE {
+            if self.desync.is_some() || self.is_over() {
+                break;
+            }
+            let tick = self.tick() + 1;
+            let input_tick = tick + self.settings.input_delay as u64;
+            if self.received[self.seat as usize] < input_tick {
+                let heading = self.steer.take();
+                if let Some(heading) = heading {
+                    self.inputs
+                        .entry(input_tick)
+                        .or_default()
+                        .insert(self.seat, heading);
+                }
+                self.received[self.seat as usize] = input_tick;
+                messages.push(PeerMessage::Input {
+                    seat: self.seat,
+                    tick: input_tick,
+                    heading: heading.map(|heading| heading.0),
+                });
+            }
+            if self.received.iter().any(|received| *received < tick) {
+                break;
+            }
+            self.run_tick(tick, &mut messages);
+
+            // the others ran further, their input for the next tick is there already
+            let others = self
+                .received
+                .iter()
+                .enumerate()
+                .filter(|(seat, _)| *seat != self.seat as usize)
+                .map(|(_, received)| *received)
+                .min();
+            if !matches!(others, Some(received) if received > input_tick) {
+                break;
+            }
+        }
+        messages
+    }
+
+    fn run_tick(&mut self, tick: u64, messages: &mut Vec<PeerMessage>) {
+        let steer = self.inputs.remove(&tick).unwrap_or_default();
+        for (seat, heading) in steer.iter() {
+            self.applied.push((tick, *seat, heading.0));
+        }
+        self.app.world.resource_mut::<PlayerInput>().steer = steer;
+        self.app.update();
+
+        let events = self.app.world.resource::<Events<MatchEvent>>();
+        self.events.extend(self.match_events.iter(events));
+
+        if tick % self.settings.checksum_interval as u64 == 0 {
+            let time_left = time_left(&self.app.world);
+            let snapshot = Snapshot::capture(&mut self.app.world, tick, time_left);
+            let checksum = snapshot.checksum();
+            messages.push(PeerMessage::Checksum {
+                seat: self.seat,
+                tick,
+                checksum,
+            });
+            self.checks.entry(tick).or_default().local = Some((checksum, snapshot));
+            self.compare(tick);
+        }
+    }
+
+    // helper: check the checksums of `tick` once ours is known, forget them once every peer's is
+    fn compare(&mut self, tick: u64) {
+        let checks = match self.checks.get(&tick) {
+            Some(checks) => checks,
+            None => return,
+        };
+        let (checksum, snapshot) = match &checks.local {
+            Some(local) => local,
+            None => return,
+        };
+        if self.desync.is_none() && checks.remote.values().any(|remote| remote != checksum) {
+            self.desync = Some(DesyncReport {
+                seat: self.seat,
+                tick,
+                checksum: *checksum,
+                remote_checksums: checks
+                    .remote
+                    .iter()
+                    .map(|(seat, checksum)| (*seat, *checksum))
+                    .collect(),
+                seed: self.seed,
+                config: self.config.clone(),
+                settings: self.settings,
+                inputs: self
+                    .applied
+                    .iter()
+                    .copied()
+                    .filter(|(input_tick, _, _)| *input_tick <= tick)
+                    .collect(),
+                snapshot: snapshot.clone(),
+            });
+        }
+        if checks.remote.len() + 1 == self.received.len() {
+            if self.desync.is_none() {
+                self.checksums_matched += 1;
+            }
+            self.checks.remove(&tick);
+        }
+    }
+}
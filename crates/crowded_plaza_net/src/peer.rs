@@ -0,0 +1,287 @@
+use bevy::log::{info, warn};
+use crowded_plaza_sim::{Faction, GameConfig, SimRng, SimSeed};
+use std::{
+    io,
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+};
+use tungstenite::{stream::MaybeTlsStream, Message, WebSocket};
+
+use crate::{
+    connection::is_would_block, decode, encode, Lockstep, LockstepSettings, NetError, PeerMessage,
+};
+
+// longer names are cut
+const MAX_NAME_LEN: usize = 16;
+
+// a WebSocket to another peer, the seat on the other end once known
+struct Link {
+    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    seat: u32,
+    open: bool,
+}
+
+impl Link {
+    fn send(&mut self, message: &PeerMessage) {
+        if !self.open {
+            return;
+        }
+        // a full socket keeps the message queued until the next flush, input must not get lost
+        match self.socket.send(Message::text(encode(message))) {
+            Err(e) if !is_would_block(&e) => self.open = false,
+            _ => {}
+        }
+    }
+
+    fn flush(&mut self) {
+        match self.socket.flush() {
+            Err(e) if self.open && !is_would_block(&e) => self.open = false,
+            _ => {}
+        }
+    }
+
+    fn receive(&mut self) -> Vec<PeerMessage> {
+        let mut messages = Vec::new();
+        while self.open {
+            match self.socket.read() {
+                Ok(Message::Text(text)) => match decode(&text) {
+                    Ok(message) => messages.push(message),
+                    Err(e) => warn!("Ignoring message from seat {0}: {1}", self.seat, e),
+                },
+                Ok(Message::Close(_)) => self.open = false,
+                Ok(_) => {}
+                Err(e) => {
+                    self.open = is_would_block(&e);
+                    break;
+                }
+            }
+        }
+        messages
+    }
+
+    // helper: wait for the next message, before the match starts
+    fn receive_blocking(&mut self) -> Result<PeerMessage, NetError> {
+        loop {
+            match self.socket.read() {
+                Ok(Message::Text(text)) => return decode(&text),
+                Ok(Message::Close(_)) => {
+                    return Err(NetError::Connect("closed before the match".to_string()))
+                }
+                Ok(_) => {}
+                Err(e) => return Err(NetError::Connect(e.to_string())),
+            }
+        }
+    }
+
+    fn set_nonblocking(&mut self) -> Result<(), NetError> {
+        if let MaybeTlsStream::Plain(stream) = self.socket.get_mut() {
+            stream
+                .set_nonblocking(true)
+                .and_then(|_| stream.set_nodelay(true))
+                .map_err(|e| NetError::Connect(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Waits for the peers of a [`Lockstep`] match to connect. The host plays too, in the first
+/// seat.
+///
+/// ```no_run
+/// use crowded_plaza_net::{LockstepHost, LockstepSettings};
+/// use crowded_plaza_sim::GameConfig;
+///
+/// let config = GameConfig {
+///     player_count: 2,
+///     ..Default::default()
+/// };
+/// let host = LockstepHost::bind("0.0.0.0:7879").expect("cannot listen");
+/// let mut peer = host
+///     .start("Ann", config, LockstepSettings::default())
+///     .expect("nobody joined");
+/// while !peer.lockstep.is_over() {
+///     peer.update();
+///     std::thread::sleep(std::time::Duration::from_secs_f32(1.0 / 60.0));
+/// }
+/// ```
+pub struct LockstepHost {
+    listener: TcpListener,
+}
+
+impl LockstepHost {
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(LockstepHost {
+            listener: TcpListener::bind(address)?,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Block until a peer joined for every seat of [`GameConfig::player_count`] but the own,
+    /// then start the match with a fresh seed. `config` is expected to be validated.
+    pub fn start(
+        self,
+        name: &str,
+        config: GameConfig,
+        settings: LockstepSettings,
+    ) -> Result<LockstepPeer, NetError> {
+        let mut names = vec![(Faction::Player(0), player_name(name, 0))];
+        let mut links = Vec::new();
+        while names.len() < config.player_count as usize {
+            let seat = names.len() as u32;
+            let (stream, _) = self
+                .listener
+                .accept()
+                .map_err(|e| NetError::Connect(e.to_string()))?;
+            let socket = match tungstenite::accept(MaybeTlsStream::Plain(stream)) {
+                Ok(socket) => socket,
+                Err(e) => {
+                    warn!("WebSocket handshake failed: {}", e);
+                    continue;
+                }
+            };
+            let mut link = Link {
+                socket,
+                seat,
+                open: true,
+            };
+            match link.receive_blocking() {
+                Ok(PeerMessage::Join { name }) => {
+                    names.push((Faction::Player(seat), player_name(&name, seat)));
+                    info!("{0} took seat {1}", names[seat as usize].1, seat);
+                    links.push(link);
+                }
+                Ok(_) => warn!("Peer did not ask for a seat"),
+                Err(e) => warn!("Peer left before the match: {}", e),
+            }
+        }
+
+        let seed = SimRng::from_sim_seed(SimSeed(None)).seed();
+        for link in links.iter_mut() {
+            link.send(&PeerMessage::Start {
+                seat: link.seat,
+                seed,
+                config: Box::new(config.clone()),
+                settings,
+                names: names.clone(),
+            });
+            link.set_nonblocking()?;
+        }
+        Ok(LockstepPeer {
+            lockstep: Lockstep::new(0, seed, config, settings),
+            names,
+            left: None,
+            links,
+            hosting: true,
+        })
+    }
+}
+
+// helper: the name a seat goes by
+fn player_name(name: &str, seat: u32) -> String {
+    let name: String = name.trim().chars().take(MAX_NAME_LEN).collect();
+    if name.is_empty() {
+        format!("Player {}", seat + 1)
+    } else {
+        name
+    }
+}
+
+/// One peer of a [`Lockstep`] match over WebSocket. Every peer connects to the
+/// [`LockstepHost`], which passes each message on to the other peers: only the host has to be
+/// reachable, and it simulates the match like everyone else.
+pub struct LockstepPeer {
+    /// The match, advanced by [`update`](LockstepPeer::update).
+    pub lockstep: Lockstep,
+    /// The name of every seat.
+    pub names: Vec<(Faction, String)>,
+    /// Set once a peer left, the match stops there.
+    pub left: Option<u32>,
+    // to the host, or to every other peer for the host
+    links: Vec<Link>,
+    hosting: bool,
+}
+
+impl LockstepPeer {
+    /// Connect to the host at a `ws://` url, blocks until the match starts.
+    pub fn join(url: &str, name: &str) -> Result<Self, NetError> {
+        let (socket, _) =
+            tungstenite::connect(url).map_err(|e| NetError::Connect(e.to_string()))?;
+        let mut link = Link {
+            socket,
+            seat: 0,
+            open: true,
+        };
+        link.send(&PeerMessage::Join {
+            name: name.to_string(),
+        });
+        loop {
+            if let PeerMessage::Start {
+                seat,
+                seed,
+                config,
+                settings,
+                names,
+            } = link.receive_blocking()?
+            {
+                link.set_nonblocking()?;
+                return Ok(LockstepPeer {
+                    lockstep: Lockstep::new(seat, seed, *config, settings),
+                    names,
+                    left: None,
+                    links: vec![link],
+                    hosting: false,
+                });
+            }
+        }
+    }
+
+    /// Take what the other peers sent, run the ticks that can run and send them our input.
+    pub fn update(&mut self) {
+        let mut received = Vec::new();
+        for (index, link) in self.links.iter_mut().enumerate() {
+            received.extend(link.receive().into_iter().map(|message| (index, message)));
+        }
+        for (from, message) in received {
+            if self.hosting {
+                for (_, link) in self
+                    .links
+                    .iter_mut()
+                    .enumerate()
+                    .filter(|(index, _)| *index != from)
+                {
+                    link.send(&message);
+                }
+            }
+            if let PeerMessage::Left { seat } = message {
+                self.left = Some(seat);
+            }
+            self.lockstep.receive(message);
+        }
+
+        for index in 0..self.links.len() {
+            if self.links[index].open || self.left.is_some() {
+                continue;
+            }
+            let seat = self.links[index].seat;
+            self.left = Some(seat);
+            if self.hosting {
+                for link in self.links.iter_mut() {
+                    link.send(&PeerMessage::Left { seat });
+                }
+            }
+        }
+
+        if self.left.is_none() {
+            for message in self.lockstep.advance() {
+                for link in self.links.iter_mut() {
+                    link.send(&message);
+                }
+            }
+        }
+        for link in self.links.iter_mut() {
+            link.flush();
+        }
+    }
+}
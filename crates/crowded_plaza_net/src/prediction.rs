@@ -0,0 +1,173 @@
+use bevy::prelude::*;
+use crowded_plaza_sim::{Faction, Heading, TICK_SECONDS};
+use std::collections::VecDeque;
+
+use crate::{ClientMessage, Snapshot};
+
+// predicted positions kept to compare with snapshots, two seconds worth
+const HISTORY_TICKS: usize = 120;
+// how much of a correction is left to show after a tick, so the pawn glides to where it should be
+const ERROR_DECAY: f32 = 0.85;
+// corrections larger than this are shown at once
+const MAX_SMOOTHED_ERROR: f32 = 2.0;
+// a client that stalled longer does not catch up on every tick it missed
+const MAX_ADVANCE: f32 = 1.0;
+
+/// The server's side of prediction, one per player: which tick of the player's own count a
+/// snapshot includes, for [`Snapshot::input_tick`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InputTracker {
+    // the player's tick of their latest steering, and the server tick it was applied on
+    last: Option<(u64, u64)>,
+}
+
+impl InputTracker {
+    /// The steering the player predicted from their `client_tick` on is applied on
+    /// `server_tick`.
+    pub fn steered(&mut self, client_tick: u64, server_tick: u64) {
+        self.last = Some((client_tick, server_tick));
+    }
+
+    /// The player's tick matching the server's `tick`, if they steered yet.
+    pub fn input_tick(&self, tick: u64) -> Option<u64> {
+        self.last
+            .map(|(client_tick, server_tick)| client_tick + tick.saturating_sub(server_tick))
+    }
+}
+
+/// Moves the own pawn as soon as the player steers, instead of a round trip later when a
+/// snapshot shows it, and corrects it with the snapshots that include the steering.
+///
+/// The predictor counts ticks of its own and sends each steering with the tick it took effect
+/// on, the server answers with the matching tick in every snapshot. Pawns move by their heading
+/// alone, so a misprediction found there stays the same in every later tick: the prediction is
+/// shifted by it and the drawn pawn glides over.
+///
+/// Until the player steers the pawn is not predicted, draw it from the snapshots.
+pub struct PawnPredictor {
+    faction: Faction,
+    speed: f32,
+    tick: u64,
+    accumulator: f32,
+    // steered since the last tick
+    steer: Option<Heading>,
+    velocity: Vec2,
+    // the predicted position after `tick`, once the player steered
+    position: Option<Vec2>,
+    // the latest position the server sent
+    latest: Option<Vec2>,
+    // predicted position after each recent tick
+    history: VecDeque<(u64, Vec2)>,
+    // correction not shown yet
+    error: Vec2,
+}
+
+impl PawnPredictor {
+    /// For the pawn leading `faction`, moving with [`GameConfig::pawn_speed`](
+    /// crowded_plaza_sim::GameConfig::pawn_speed).
+    pub fn new(faction: Faction, speed: f32) -> Self {
+        PawnPredictor {
+            faction,
+            speed,
+            tick: 0,
+            accumulator: 0.0,
+            steer: None,
+            velocity: Vec2::ZERO,
+            position: None,
+            latest: None,
+            history: VecDeque::with_capacity(HISTORY_TICKS),
+            error: Vec2::ZERO,
+        }
+    }
+
+    /// Ticks predicted so far.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Turn the pawn on the next tick, the latest heading of a tick counts.
+    pub fn steer(&mut self, heading: Heading) {
+        self.steer = Some(heading);
+    }
+
+    /// Predict the ticks in the next `seconds`, returns the steering to send the server.
+    pub fn advance(&mut self, seconds: f32) -> Option<ClientMessage> {
+        let mut message = None;
+        self.accumulator = (self.accumulator + seconds).min(MAX_ADVANCE);
+        while self.accumulator >= TICK_SECONDS {
+            self.accumulator -= TICK_SECONDS;
+            self.tick += 1;
+            if let Some(heading) = self.steer.take() {
+                self.velocity = heading.direction() * self.speed;
+                if self.position.is_none() {
+                    self.position = self.latest;
+                }
+                message = Some(ClientMessage::Steer {
+                    heading: heading.0,
+                    tick: self.tick,
+                });
+            }
+            if let Some(position) = self.position.as_mut() {
+                *position += self.velocity * TICK_SECONDS;
+                if self.history.len() == HISTORY_TICKS {
+                    self.history.pop_front();
+                }
+                self.history.push_back((self.tick, *position));
+            }
+            self.error *= ERROR_DECAY;
+        }
+        message
+    }
+
+    /// Check the prediction against a snapshot from the server, returns the misprediction it
+    /// found.
+    pub fn reconcile(&mut self, snapshot: &Snapshot) -> Option<Vec2> {
+        let pawn = match snapshot.pawn(self.faction) {
+            Some(pawn) => Vec2::from(pawn.position),
+            // eliminated
+            None => {
+                self.position = None;
+                self.latest = None;
+                self.history.clear();
+                return None;
+            }
+        };
+        self.latest = Some(pawn);
+        let (input_tick, position) = match (snapshot.input_tick, self.position) {
+            (Some(input_tick), Some(position)) => (input_tick, position),
+            _ => return None,
+        };
+
+        let predicted = self
+            .history
+            .iter()
+            .find(|(tick, _)| *tick == input_tick)
+            .map(|(_, predicted)| *predicted);
+        let correction = match predicted {
+            Some(predicted) => pawn - predicted,
+            // too old to tell, carry on from the snapshot as if nobody steered since
+            None => {
+                let ticks = self.tick.saturating_sub(input_tick) as f32;
+                self.history.clear();
+                pawn + self.velocity * ticks * TICK_SECONDS - position
+            }
+        };
+        // snapshots won't go back to earlier ticks
+        self.history.retain(|(tick, _)| *tick >= input_tick);
+        for (_, predicted) in self.history.iter_mut() {
+            *predicted += correction;
+        }
+        self.position = Some(position + correction);
+        self.error += correction;
+        if self.error.length() > MAX_SMOOTHED_ERROR {
+            self.error = Vec2::ZERO;
+        }
+        Some(correction)
+    }
+
+    /// Where to draw the pawn now, while it is predicted.
+    pub fn position(&self) -> Option<Vec2> {
+        self.position
+            .map(|position| position + self.velocity * self.accumulator - self.error)
+    }
+}
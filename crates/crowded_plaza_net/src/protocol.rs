@@ -0,0 +1,178 @@
+use bevy::prelude::*;
+use crowded_plaza_sim::{Actor, Countdown, Faction, FactionRegistry, GameConfig, MatchEvent, Pawn};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The port the server listens on unless told otherwise.
+pub const DEFAULT_PORT: u16 = 7878;
+
+/// What a client sends, one JSON text message each.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// The first message after connecting, asks for a seat.
+    Join { name: String },
+    /// Steer the own pawn, a [`Heading`](crowded_plaza_sim::Heading), from `tick` on in the
+    /// client's own count of ticks, see [`PawnPredictor`](crate::PawnPredictor).
+    Steer { heading: u16, tick: u64 },
+    /// The snapshot of `tick` arrived, the next ones may be deltas against it.
+    Ack { tick: u64 },
+}
+
+/// What the server sends, one JSON text message each. Snapshots are binary messages instead,
+/// see [`SnapshotEncoder`](crate::SnapshotEncoder).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// The reply to [`ClientMessage::Join`], the player leads `Faction::Player(seat)`.
+    Welcome {
+        seat: u32,
+    },
+    /// Every seat is taken, the server closes the connection.
+    Full,
+    /// A match was set up. Sent to every player when it starts, and to players joining during
+    /// it, `tick` is how far it is.
+    MatchStart {
+        seed: u64,
+        tick: u64,
+        config: Box<GameConfig>,
+        names: Vec<(Faction, String)>,
+    },
+    /// A player joined or left, seats without a player are steered by a bot.
+    Roster {
+        names: Vec<(Faction, String)>,
+    },
+    /// Decoded from a binary message, never sent as JSON.
+    #[serde(skip)]
+    Snapshot(Snapshot),
+    Event {
+        event: MatchEvent,
+    },
+}
+
+/// Where every actor is after `tick`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub tick: u64,
+    /// Seconds until the match is over.
+    pub time_left: f32,
+    /// The tick in the receiving player's own count that matches `tick`, once the server applied
+    /// their steering. Set for each player before sending.
+    #[serde(default)]
+    pub input_tick: Option<u64>,
+    /// By id.
+    pub actors: Vec<ActorState>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ActorState {
+    /// Stays the same for the whole match.
+    pub id: u32,
+    pub position: [f32; 2],
+    pub faction: Faction,
+    /// Whether it is the pawn leading `faction`.
+    pub pawn: bool,
+}
+
+impl Snapshot {
+    /// The state of a match simulated in `world`.
+    pub fn capture(world: &mut World, tick: u64, time_left: f32) -> Self {
+        let mut actors: Vec<ActorState> = world
+            .query::<(Entity, &Transform, &Actor, Option<&Pawn>)>()
+            .iter(world)
+            .map(|(entity, tr, actor, pawn)| ActorState {
+                id: entity.id(),
+                position: [tr.translation.x, tr.translation.y],
+                faction: actor.faction,
+                pawn: pawn.is_some(),
+            })
+            .collect();
+        actors.sort_by_key(|actor| actor.id);
+        Snapshot {
+            tick,
+            time_left,
+            input_tick: None,
+            actors,
+        }
+    }
+
+    /// The pawn leading `faction`, if it is still alive.
+    pub fn pawn(&self, faction: Faction) -> Option<&ActorState> {
+        self.actors
+            .iter()
+            .find(|actor| actor.pawn && actor.faction == faction)
+    }
+
+    /// A hash of every actor's id, position and faction, the same on every machine that simulated
+    /// the match bit for bit.
+    pub fn checksum(&self) -> u64 {
+        // FNV-1a
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut write = |bytes: &[u8]| {
+            for byte in bytes {
+                hash ^= *byte as u64;
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+        for actor in self.actors.iter() {
+            let (kind, faction_id) = match actor.faction {
+                Faction::Neutral => (0, 0),
+                Faction::Player(id) => (1, id),
+                Faction::Bot(id) => (2, id),
+            };
+            write(&actor.id.to_le_bytes());
+            write(&actor.position[0].to_bits().to_le_bytes());
+            write(&actor.position[1].to_bits().to_le_bytes());
+            write(&[kind]);
+            write(&faction_id.to_le_bytes());
+        }
+        hash
+    }
+
+    /// Set the counts of every faction in `registry` to the actors in this snapshot.
+    pub fn count_factions(&self, registry: &mut FactionRegistry) {
+        for (_, info) in registry.iter_mut() {
+            info.count = 0;
+        }
+        for actor in self.actors.iter() {
+            if let Some(info) = registry.get_mut(actor.faction) {
+                info.count += 1;
+            }
+        }
+    }
+}
+
+// helper: seconds until the match simulated in `world` is over
+pub(crate) fn time_left(world: &World) -> f32 {
+    world.get_resource::<Countdown>().map_or(0.0, |countdown| {
+        (countdown.main_timer.duration() - countdown.main_timer.elapsed()).as_secs_f32()
+    })
+}
+
+/// Why a message or connection failed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum NetError {
+    /// Could not connect to the server.
+    Connect(String),
+    /// A message that is not part of the protocol.
+    Malformed(String),
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetError::Connect(e) => write!(f, "cannot connect: {}", e),
+            NetError::Malformed(e) => write!(f, "malformed message: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for NetError {}
+
+pub fn encode<T: Serialize>(message: &T) -> String {
+    serde_json::to_string(message).expect("protocol messages always serialize")
+}
+
+pub fn decode<'a, T: Deserialize<'a>>(text: &'a str) -> Result<T, NetError> {
+    serde_json::from_str(text).map_err(|e| NetError::Malformed(e.to_string()))
+}
@@ -0,0 +1,484 @@
+use bevy::{
+    ecs::event::{Events, ManualEventReader},
+    prelude::*,
+};
+use crowded_plaza_sim::{
+    headless_match, Faction, FactionRegistry, GameConfig, Heading, MatchEvent, OpponentController,
+    PlayerInput, SimClock, SimRng, Strategy, TICK_SECONDS,
+};
+use std::{
+    io,
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+use tungstenite::{
+    handshake::{
+        server::{NoCallback, ServerHandshake},
+        MidHandshake,
+    },
+    HandshakeError, Message, WebSocket,
+};
+
+use crate::{
+    connection::is_would_block, decode, encode, protocol::time_left, ClientMessage, InputTracker,
+    ServerMessage, Snapshot, SnapshotEncoder,
+};
+
+// longer names are cut
+const MAX_NAME_LEN: usize = 16;
+
+/// How a [`Server`] runs its matches.
+#[derive(Clone, Debug)]
+pub struct ServerSettings {
+    /// Ticks between two snapshots sent to the players.
+    pub snapshot_interval: u32,
+    /// Seconds between the end of a match and the start of the next one.
+    pub restart_delay: f32,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        ServerSettings {
+            snapshot_interval: 3,
+            restart_delay: 5.0,
+        }
+    }
+}
+
+struct Client {
+    socket: WebSocket<TcpStream>,
+    seat: Option<u32>,
+    name: String,
+    open: bool,
+    // the last message did not make it out completely, snapshots are skipped until it did
+    backlogged: bool,
+    snapshots: SnapshotEncoder,
+    inputs: InputTracker,
+}
+
+impl Client {
+    fn send(&mut self, message: &ServerMessage) {
+        self.send_text(&encode(message));
+    }
+
+    fn send_text(&mut self, text: &str) {
+        self.send_raw(Message::text(text));
+    }
+
+    fn send_snapshot(&mut self, snapshot: &Snapshot) {
+        let bytes = self.snapshots.encode(snapshot);
+        self.send_raw(Message::binary(bytes));
+    }
+
+    fn send_raw(&mut self, message: Message) {
+        if !self.open {
+            return;
+        }
+        match self.socket.send(message) {
+            Ok(_) => self.backlogged = false,
+            Err(e) if is_would_block(&e) => self.backlogged = true,
+            Err(_) => self.open = false,
+        }
+    }
+
+    // helper: whatever the socket would not take last time, went out now
+    fn catch_up(&mut self) -> bool {
+        if self.backlogged {
+            match self.socket.flush() {
+                Ok(_) => self.backlogged = false,
+                Err(e) if is_would_block(&e) => {}
+                Err(_) => self.open = false,
+            }
+        }
+        self.open && !self.backlogged
+    }
+
+    fn receive(&mut self) -> Vec<ClientMessage> {
+        let mut messages = Vec::new();
+        while self.open {
+            match self.socket.read() {
+                Ok(Message::Text(text)) => match decode(&text) {
+                    Ok(message) => messages.push(message),
+                    Err(e) => warn!("Ignoring message from {0}: {1}", self.name, e),
+                },
+                Ok(Message::Close(_)) => self.open = false,
+                Ok(_) => {}
+                Err(e) => {
+                    self.open = is_would_block(&e);
+                    break;
+                }
+            }
+        }
+        messages
+    }
+}
+
+/// The authoritative game server: runs matches one after the other at a fixed tick, takes
+/// steering from players connected over WebSocket and sends them snapshots of every actor.
+///
+/// Every seat of [`GameConfig::player_count`] leads a faction, seats without a player are
+/// steered by a bot until someone joins.
+///
+/// ```no_run
+/// use crowded_plaza_net::{Server, ServerSettings, DEFAULT_PORT};
+/// use crowded_plaza_sim::GameConfig;
+///
+/// let config = GameConfig {
+///     player_count: 8,
+///     ..Default::default()
+/// };
+/// let mut server = Server::bind(("0.0.0.0", DEFAULT_PORT), config, ServerSettings::default())
+///     .expect("cannot listen");
+/// server.run();
+/// ```
+pub struct Server {
+    config: GameConfig,
+    settings: ServerSettings,
+    listener: TcpListener,
+    handshakes: Vec<MidHandshake<ServerHandshake<TcpStream, NoCallback>>>,
+    clients: Vec<Client>,
+    app: App,
+    match_events: ManualEventReader<MatchEvent>,
+    // ticks left until the next match, once this one is over
+    restart_countdown: Option<u32>,
+    matches_played: u32,
+}
+
+impl Server {
+    /// Listen on `address` and set up the first match. `config` is expected to be validated.
+    pub fn bind(
+        address: impl ToSocketAddrs,
+        config: GameConfig,
+        settings: ServerSettings,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        let mut server = Server {
+            config,
+            settings,
+            listener,
+            handshakes: Vec::new(),
+            clients: Vec::new(),
+            app: App::new(),
+            match_events: ManualEventReader::default(),
+            restart_countdown: None,
+            matches_played: 0,
+        };
+        server.start_match();
+        Ok(server)
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Matches that ran until the time was up.
+    pub fn matches_played(&self) -> u32 {
+        self.matches_played
+    }
+
+    /// Serve forever, one [`update`](Server::update) per tick of real time.
+    pub fn run(&mut self) {
+        let tick = Duration::from_secs_f32(TICK_SECONDS);
+        let mut next_tick = Instant::now();
+        loop {
+            self.update();
+            next_tick += tick;
+            let now = Instant::now();
+            if next_tick > now {
+                std::thread::sleep(next_tick - now);
+            } else {
+                // too slow to keep up, don't try to catch up later
+                next_tick = now;
+            }
+        }
+    }
+
+    /// Take new connections and the players' input, then run one tick and send the players
+    /// what happened.
+    pub fn update(&mut self) {
+        self.accept_connections();
+        self.receive_messages();
+        self.drop_closed_clients();
+
+        match self.restart_countdown {
+            Some(0) => {
+                self.restart_countdown = None;
+                self.start_match();
+            }
+            Some(ticks) => self.restart_countdown = Some(ticks - 1),
+            None => self.tick_match(),
+        }
+    }
+
+    fn accept_connections(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(e) = stream
+                        .set_nonblocking(true)
+                        .and_then(|_| stream.set_nodelay(true))
+                    {
+                        warn!("Cannot set up connection: {}", e);
+                        continue;
+                    }
+                    self.handshake(tungstenite::accept(stream));
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("Cannot accept connection: {}", e);
+                    break;
+                }
+            }
+        }
+        // handshakes that were waiting for more data
+        for handshake in std::mem::take(&mut self.handshakes) {
+            self.handshake(handshake.handshake());
+        }
+    }
+
+    fn handshake(
+        &mut self,
+        result: Result<
+            WebSocket<TcpStream>,
+            HandshakeError<ServerHandshake<TcpStream, NoCallback>>,
+        >,
+    ) {
+        match result {
+            Ok(socket) => self.clients.push(Client {
+                socket,
+                seat: None,
+                name: String::new(),
+                open: true,
+                backlogged: false,
+                snapshots: SnapshotEncoder::new(self.config.action_region),
+                inputs: InputTracker::default(),
+            }),
+            Err(HandshakeError::Interrupted(handshake)) => self.handshakes.push(handshake),
+            Err(HandshakeError::Failure(e)) => warn!("WebSocket handshake failed: {}", e),
+        }
+    }
+
+    fn receive_messages(&mut self) {
+        for index in 0..self.clients.len() {
+            for message in self.clients[index].receive() {
+                match message {
+                    ClientMessage::Join { name } => self.join(index, &name),
+                    ClientMessage::Ack { tick } => self.clients[index].snapshots.ack(tick),
+                    ClientMessage::Steer { heading, tick } => {
+                        if let Some(seat) = self.clients[index].seat {
+                            // applied by the next tick
+                            let next_tick = self.app.world.resource::<SimClock>().tick() + 1;
+                            self.clients[index].inputs.steered(tick, next_tick);
+                            self.app
+                                .world
+                                .resource_mut::<PlayerInput>()
+                                .steer
+                                .insert(seat, Heading(heading));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn join(&mut self, index: usize, name: &str) {
+        if self.clients[index].seat.is_some() {
+            return;
+        }
+        let seat = (0..self.config.player_count as u32)
+            .find(|seat| self.clients.iter().all(|client| client.seat != Some(*seat)));
+        let seat = match seat {
+            Some(seat) => seat,
+            None => {
+                let client = &mut self.clients[index];
+                client.send(&ServerMessage::Full);
+                let _ = client.socket.close(None);
+                client.open = false;
+                return;
+            }
+        };
+
+        let name: String = name.trim().chars().take(MAX_NAME_LEN).collect();
+        let client = &mut self.clients[index];
+        client.seat = Some(seat);
+        client.name = if name.is_empty() {
+            format!("Player {}", seat + 1)
+        } else {
+            name
+        };
+        info!("{0} took seat {1}", client.name, seat);
+        client.send(&ServerMessage::Welcome { seat });
+        self.seat_player(seat);
+        let start = self.match_start();
+        let client = &mut self.clients[index];
+        client.snapshots = SnapshotEncoder::new(self.config.action_region);
+        client.inputs = InputTracker::default();
+        client.send(&start);
+        self.send_roster();
+    }
+
+    fn drop_closed_clients(&mut self) {
+        let mut left = Vec::new();
+        self.clients.retain(|client| {
+            if !client.open {
+                if let Some(seat) = client.seat {
+                    info!("{0} left seat {1}", client.name, seat);
+                    left.push(seat);
+                }
+            }
+            client.open
+        });
+        if !left.is_empty() {
+            for seat in left {
+                self.seat_bot(seat);
+            }
+            self.send_roster();
+        }
+    }
+
+    fn start_match(&mut self) {
+        // the clock starts paused so setting up the match doesn't tick it
+        let mut app = headless_match(self.config.clone(), None, true);
+        app.update();
+        app.world.resource_mut::<SimClock>().paused = false;
+        self.app = app;
+        self.match_events = ManualEventReader::default();
+
+        let seated: Vec<u32> = self
+            .clients
+            .iter()
+            .filter_map(|client| client.seat)
+            .collect();
+        for seat in 0..self.config.player_count as u32 {
+            if seated.contains(&seat) {
+                self.seat_player(seat);
+            } else {
+                self.seat_bot(seat);
+            }
+        }
+        let start = self.match_start();
+        for client in self
+            .clients
+            .iter_mut()
+            .filter(|client| client.seat.is_some())
+        {
+            client.snapshots = SnapshotEncoder::new(self.config.action_region);
+            client.inputs = InputTracker::default();
+            client.send(&start);
+        }
+    }
+
+    fn tick_match(&mut self) {
+        self.app.update();
+
+        let mut messages = Vec::new();
+        let events = self.app.world.resource::<Events<MatchEvent>>();
+        for event in self.match_events.iter(events) {
+            if *event == MatchEvent::TimeUp {
+                self.matches_played += 1;
+                self.restart_countdown = Some((self.settings.restart_delay / TICK_SECONDS) as u32);
+            }
+            messages.push(encode(&ServerMessage::Event { event: *event }));
+        }
+
+        let tick = self.app.world.resource::<SimClock>().tick();
+        if tick % self.settings.snapshot_interval as u64 == 0 {
+            let time_left = time_left(&self.app.world);
+            let mut snapshot = Snapshot::capture(&mut self.app.world, tick, time_left);
+            for client in self
+                .clients
+                .iter_mut()
+                .filter(|client| client.seat.is_some())
+            {
+                // a player that can't keep up misses snapshots, the next one is a delta against
+                // whatever it acknowledged
+                if client.catch_up() {
+                    snapshot.input_tick = client.inputs.input_tick(tick);
+                    client.send_snapshot(&snapshot);
+                }
+            }
+        }
+
+        for text in messages.iter() {
+            for client in self
+                .clients
+                .iter_mut()
+                .filter(|client| client.seat.is_some())
+            {
+                client.send_text(text);
+            }
+        }
+    }
+
+    // helper: hand a seat's pawn to its player
+    fn seat_player(&mut self, seat: u32) {
+        let name = self
+            .clients
+            .iter()
+            .find(|client| client.seat == Some(seat))
+            .map(|client| client.name.clone());
+        let mut registry = self.app.world.resource_mut::<FactionRegistry>();
+        let info = match registry.get_mut(Faction::Player(seat)) {
+            Some(info) => info,
+            None => return,
+        };
+        if let Some(name) = name {
+            info.name = name;
+        }
+        if let Some(leader) = info.leader {
+            self.app
+                .world
+                .entity_mut(leader)
+                .remove::<OpponentController>();
+        }
+    }
+
+    // helper: let a bot steer a seat's pawn until a player takes it
+    fn seat_bot(&mut self, seat: u32) {
+        let controller = OpponentController::new(
+            Strategy::SeekNeutral.brain(&self.config),
+            self.config.opponent_difficulty,
+        );
+        let mut registry = self.app.world.resource_mut::<FactionRegistry>();
+        let info = match registry.get_mut(Faction::Player(seat)) {
+            Some(info) => info,
+            None => return,
+        };
+        info.name = format!("Player {} (bot)", seat + 1);
+        if let Some(leader) = info.leader {
+            self.app.world.entity_mut(leader).insert(controller);
+        }
+    }
+
+    fn names(&self) -> Vec<(Faction, String)> {
+        self.app
+            .world
+            .resource::<FactionRegistry>()
+            .iter()
+            .map(|(faction, info)| (faction, info.name.clone()))
+            .collect()
+    }
+
+    fn match_start(&self) -> ServerMessage {
+        ServerMessage::MatchStart {
+            seed: self.app.world.resource::<SimRng>().seed(),
+            tick: self.app.world.resource::<SimClock>().tick(),
+            config: Box::new(self.config.clone()),
+            names: self.names(),
+        }
+    }
+
+    fn send_roster(&mut self) {
+        let text = encode(&ServerMessage::Roster {
+            names: self.names(),
+        });
+        for client in self
+            .clients
+            .iter_mut()
+            .filter(|client| client.seat.is_some())
+        {
+            client.send_text(&text);
+        }
+    }
+}
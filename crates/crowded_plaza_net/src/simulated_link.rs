@@ -0,0 +1,76 @@
+use crowded_plaza_sim::SimRng;
+use rand::Rng;
+use std::collections::VecDeque;
+
+/// How bad a connection a [`SimulatedLink`] is.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LinkConditions {
+    /// Seconds every message takes.
+    pub latency: f32,
+    /// Up to this many seconds more, random for every message.
+    pub jitter: f32,
+    /// The chance a message never arrives, from 0 to 1. Over WebSocket nothing gets lost, but a
+    /// server skips snapshots to a player that can't keep up.
+    pub loss: f32,
+}
+
+/// One direction of a connection with latency, jitter and loss, to try prediction and
+/// interpolation offline. Time only passes when the caller advances it, and like the WebSocket
+/// it stands in for, the messages that arrive do so in order.
+pub struct SimulatedLink<T> {
+    conditions: LinkConditions,
+    rng: SimRng,
+    now: f64,
+    // with their arrival time
+    in_flight: VecDeque<(f64, T)>,
+    sent: u32,
+    lost: u32,
+}
+
+impl<T> SimulatedLink<T> {
+    /// The same `seed` loses and delays the same messages.
+    pub fn new(conditions: LinkConditions, seed: u64) -> Self {
+        SimulatedLink {
+            conditions,
+            rng: SimRng::new(seed),
+            now: 0.0,
+            in_flight: VecDeque::new(),
+            sent: 0,
+            lost: 0,
+        }
+    }
+
+    pub fn send(&mut self, message: T) {
+        self.sent += 1;
+        if self.rng.gen_range(0.0..1.0) < self.conditions.loss {
+            self.lost += 1;
+            return;
+        }
+        let jitter = self.rng.gen_range(0.0..=self.conditions.jitter);
+        let mut arrival = self.now + (self.conditions.latency + jitter) as f64;
+        // a late message holds up the ones behind it
+        if let Some((last, _)) = self.in_flight.back() {
+            arrival = arrival.max(*last);
+        }
+        self.in_flight.push_back((arrival, message));
+    }
+
+    /// Let `seconds` pass, returns the messages that arrived meanwhile.
+    pub fn advance(&mut self, seconds: f32) -> Vec<T> {
+        self.now += seconds as f64;
+        let mut arrived = Vec::new();
+        while matches!(self.in_flight.front(), Some((arrival, _)) if *arrival <= self.now) {
+            arrived.extend(self.in_flight.pop_front().map(|(_, message)| message));
+        }
+        arrived
+    }
+
+    /// Messages sent so far, and how many of them were lost.
+    pub fn sent(&self) -> u32 {
+        self.sent
+    }
+
+    pub fn lost(&self) -> u32 {
+        self.lost
+    }
+}
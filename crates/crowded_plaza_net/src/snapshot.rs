@@ -0,0 +1,428 @@
+use crowded_plaza_sim::{Faction, MAX_OPPONENT_COUNT, MAX_PLAYER_COUNT};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+};
+
+use crate::{ActorState, Snapshot};
+
+const FORMAT_VERSION: u8 = 1;
+// snapshots kept as possible baselines, a second's worth at the default snapshot interval
+const HISTORY_LEN: usize = 32;
+
+// record flags
+const REMOVED: u8 = 1;
+const PAWN: u8 = 2;
+const FACTION: u8 = 4;
+const SMALL_DELTA: u8 = 8;
+
+/// Why a snapshot could not be decoded.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SnapshotError {
+    /// Written by a newer version of the game.
+    UnsupportedVersion(u8),
+    /// The message ends early.
+    Truncated,
+    /// A delta against a snapshot we no longer have.
+    UnknownBaseline(u64),
+    /// A faction byte that no faction encodes to.
+    UnknownFaction(u8),
+    /// An actor id past the largest an entity can have.
+    ActorIdOutOfRange,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot format version {}", version)
+            }
+            SnapshotError::Truncated => write!(f, "snapshot is truncated"),
+            SnapshotError::UnknownBaseline(tick) => {
+                write!(f, "snapshot is a delta against unknown tick {}", tick)
+            }
+            SnapshotError::UnknownFaction(byte) => write!(f, "unknown faction {}", byte),
+            SnapshotError::ActorIdOutOfRange => write!(f, "actor id is out of range"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+// helper: neutral is 0, then the players, then the bots
+fn faction_to_byte(faction: Faction) -> u8 {
+    match faction {
+        Faction::Neutral => 0,
+        Faction::Player(id) => 1 + id as u8,
+        Faction::Bot(id) => 1 + MAX_PLAYER_COUNT as u8 + id as u8,
+    }
+}
+
+fn faction_from_byte(byte: u8) -> Faction {
+    let players = MAX_PLAYER_COUNT as u8;
+    match byte {
+        0 => Faction::Neutral,
+        byte if byte <= players => Faction::Player((byte - 1) as u32),
+        byte => Faction::Bot((byte - 1 - players) as u32),
+    }
+}
+
+// helper: LEB128, same as in replay files
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.bytes.len() < len {
+            return Err(SnapshotError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, SnapshotError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn varint(&mut self) -> Result<u64, SnapshotError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(SnapshotError::Truncated)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct QuantizedActor {
+    x: u16,
+    y: u16,
+    faction: u8,
+    pawn: bool,
+}
+
+// a snapshot as both sides of the connection see it, positions quantized
+#[derive(Clone, Debug, Default)]
+struct QuantizedSnapshot {
+    tick: u64,
+    time_left_ms: u64,
+    input_tick: Option<u64>,
+    actors: BTreeMap<u32, QuantizedActor>,
+}
+
+/// Positions as 16-bit fixed point: the square of twice the action region, so crowds drifting
+/// off the plaza stay exact too. Further out they are clamped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quantizer {
+    extent: f32,
+}
+
+impl Quantizer {
+    pub fn new(action_region: f32) -> Self {
+        Quantizer {
+            extent: action_region * 2.0,
+        }
+    }
+
+    /// The largest error of a quantized coordinate, in units.
+    pub fn precision(&self) -> f32 {
+        self.extent / u16::MAX as f32
+    }
+
+    fn quantize(&self, value: f32) -> u16 {
+        let normalized = (value / self.extent * 0.5 + 0.5).clamp(0.0, 1.0);
+        (normalized * u16::MAX as f32).round() as u16
+    }
+
+    fn dequantize(&self, value: u16) -> f32 {
+        (value as f32 / u16::MAX as f32 - 0.5) * 2.0 * self.extent
+    }
+
+    fn snapshot(&self, snapshot: &Snapshot) -> QuantizedSnapshot {
+        QuantizedSnapshot {
+            tick: snapshot.tick,
+            time_left_ms: (snapshot.time_left.max(0.0) * 1000.0).round() as u64,
+            input_tick: snapshot.input_tick,
+            actors: snapshot
+                .actors
+                .iter()
+                .map(|actor| {
+                    let quantized = QuantizedActor {
+                        x: self.quantize(actor.position[0]),
+                        y: self.quantize(actor.position[1]),
+                        faction: faction_to_byte(actor.faction),
+                        pawn: actor.pawn,
+                    };
+                    (actor.id, quantized)
+                })
+                .collect(),
+        }
+    }
+
+    fn restore(&self, snapshot: &QuantizedSnapshot) -> Snapshot {
+        Snapshot {
+            tick: snapshot.tick,
+            time_left: snapshot.time_left_ms as f32 / 1000.0,
+            input_tick: snapshot.input_tick,
+            actors: snapshot
+                .actors
+                .iter()
+                .map(|(id, actor)| ActorState {
+                    id: *id,
+                    position: [self.dequantize(actor.x), self.dequantize(actor.y)],
+                    faction: faction_from_byte(actor.faction),
+                    pawn: actor.pawn,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Writes the snapshots sent to one client, each as a delta against the latest snapshot the
+/// client acknowledged, or in full while it has not acknowledged any we still have.
+///
+/// The format is a version byte, then LEB128 varints for the tick, the distance back to the
+/// baseline tick (0 for a full snapshot), the time left in milliseconds, the input tick plus one
+/// (0 for none) and the number of actor records. Records go by ascending id, only for actors
+/// that changed since the baseline:
+///
+/// ```text
+/// varint  id distance to the previous record's id, minus one
+/// u8      flags: 1 removed, 2 pawn, 4 a faction byte follows, 8 the position is a small delta
+/// [u8]    faction
+/// i8 i8   position change since the baseline, for a small delta
+/// u16 u16 position, otherwise, little endian, see `Quantizer`
+/// ```
+///
+/// Removed actors have no faction or position.
+pub struct SnapshotEncoder {
+    quantizer: Quantizer,
+    sent: VecDeque<QuantizedSnapshot>,
+    acked: Option<u64>,
+}
+
+impl SnapshotEncoder {
+    /// For a match played with `action_region`, start one per client and match.
+    pub fn new(action_region: f32) -> Self {
+        SnapshotEncoder {
+            quantizer: Quantizer::new(action_region),
+            sent: VecDeque::with_capacity(HISTORY_LEN),
+            acked: None,
+        }
+    }
+
+    /// The client has the snapshot of `tick`, later snapshots can be sent as deltas against it.
+    /// Acks for snapshots this encoder did not send, like late ones from the last match, are
+    /// ignored.
+    pub fn ack(&mut self, tick: u64) {
+        let sent = self.sent.iter().any(|sent| sent.tick == tick);
+        if sent && self.acked.is_none_or(|acked| tick > acked) {
+            self.acked = Some(tick);
+        }
+    }
+
+    pub fn encode(&mut self, snapshot: &Snapshot) -> Vec<u8> {
+        let current = self.quantizer.snapshot(snapshot);
+        let empty = QuantizedSnapshot::default();
+        let baseline = self
+            .acked
+            .and_then(|acked| self.sent.iter().find(|sent| sent.tick == acked))
+            .filter(|baseline| baseline.tick < current.tick);
+
+        let mut bytes = Vec::with_capacity(16 + current.actors.len() * 4);
+        bytes.push(FORMAT_VERSION);
+        write_varint(&mut bytes, current.tick);
+        write_varint(
+            &mut bytes,
+            baseline.map_or(0, |baseline| current.tick - baseline.tick),
+        );
+        write_varint(&mut bytes, current.time_left_ms);
+        write_varint(&mut bytes, current.input_tick.map_or(0, |tick| tick + 1));
+
+        let base_actors = &baseline.unwrap_or(&empty).actors;
+        let mut records = Vec::with_capacity(current.actors.len() * 4);
+        let mut record_count = 0;
+        let mut next_id = 0;
+        let mut write_id = |records: &mut Vec<u8>, id: u32| {
+            write_varint(records, (id - next_id) as u64);
+            next_id = id + 1;
+        };
+        // merge the ids of both snapshots in order
+        let mut ids: Vec<u32> = current.actors.keys().copied().collect();
+        ids.extend(
+            base_actors
+                .keys()
+                .filter(|id| !current.actors.contains_key(id)),
+        );
+        ids.sort_unstable();
+        for id in ids {
+            let (actor, base) = match (current.actors.get(&id), base_actors.get(&id)) {
+                (Some(actor), Some(base)) if actor == base => continue,
+                (Some(actor), base) => (actor, base),
+                (None, _) => {
+                    write_id(&mut records, id);
+                    records.push(REMOVED);
+                    record_count += 1;
+                    continue;
+                }
+            };
+            write_id(&mut records, id);
+            record_count += 1;
+
+            let mut flags = if actor.pawn { PAWN } else { 0 };
+            if base.is_none_or(|base| base.faction != actor.faction) {
+                flags |= FACTION;
+            }
+            let delta = base.and_then(|base| {
+                let dx = i8::try_from(actor.x as i32 - base.x as i32).ok()?;
+                let dy = i8::try_from(actor.y as i32 - base.y as i32).ok()?;
+                Some((dx, dy))
+            });
+            if delta.is_some() {
+                flags |= SMALL_DELTA;
+            }
+            records.push(flags);
+            if flags & FACTION != 0 {
+                records.push(actor.faction);
+            }
+            match delta {
+                Some((dx, dy)) => records.extend_from_slice(&[dx as u8, dy as u8]),
+                None => {
+                    records.extend_from_slice(&actor.x.to_le_bytes());
+                    records.extend_from_slice(&actor.y.to_le_bytes());
+                }
+            }
+        }
+        write_varint(&mut bytes, record_count);
+        bytes.extend_from_slice(&records);
+
+        if self.sent.len() == HISTORY_LEN {
+            self.sent.pop_front();
+        }
+        self.sent.push_back(current);
+        bytes
+    }
+}
+
+/// Reads the snapshots written by a [`SnapshotEncoder`], keeping the ones deltas may refer to.
+/// Every decoded snapshot should be acknowledged to the server.
+pub struct SnapshotDecoder {
+    quantizer: Quantizer,
+    received: VecDeque<QuantizedSnapshot>,
+}
+
+impl SnapshotDecoder {
+    /// For a match played with `action_region`.
+    pub fn new(action_region: f32) -> Self {
+        SnapshotDecoder {
+            quantizer: Quantizer::new(action_region),
+            received: VecDeque::with_capacity(HISTORY_LEN),
+        }
+    }
+
+    pub fn decode(&mut self, bytes: &[u8]) -> Result<Snapshot, SnapshotError> {
+        let mut reader = Reader { bytes };
+        let version = reader.u8()?;
+        if version != FORMAT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let tick = reader.varint()?;
+        let baseline_distance = reader.varint()?;
+        let time_left_ms = reader.varint()?;
+        let input_tick = reader.varint()?.checked_sub(1);
+        let record_count = reader.varint()?;
+
+        let mut actors = match baseline_distance {
+            0 => BTreeMap::new(),
+            distance => {
+                let baseline_tick = tick.saturating_sub(distance);
+                let actors = self
+                    .received
+                    .iter()
+                    .find(|received| received.tick == baseline_tick)
+                    .ok_or(SnapshotError::UnknownBaseline(baseline_tick))?
+                    .actors
+                    .clone();
+                // acks only go forward, so older baselines won't be used again
+                self.received
+                    .retain(|received| received.tick >= baseline_tick);
+                actors
+            }
+        };
+
+        let mut next_id: u64 = 0;
+        for _ in 0..record_count {
+            let id = next_id
+                .checked_add(reader.varint()?)
+                .and_then(|id| u32::try_from(id).ok())
+                .ok_or(SnapshotError::ActorIdOutOfRange)?;
+            next_id = id as u64 + 1;
+            let flags = reader.u8()?;
+            if flags & REMOVED != 0 {
+                actors.remove(&id);
+                continue;
+            }
+            let base = actors.get(&id).copied();
+            let faction = match (flags & FACTION != 0, base) {
+                (true, _) => reader.u8()?,
+                (false, Some(base)) => base.faction,
+                (false, None) => return Err(SnapshotError::Truncated),
+            };
+            if faction as i32 > MAX_PLAYER_COUNT + MAX_OPPONENT_COUNT {
+                return Err(SnapshotError::UnknownFaction(faction));
+            }
+            let (x, y) = match (flags & SMALL_DELTA != 0, base) {
+                (true, Some(base)) => {
+                    let dx = reader.u8()? as i8;
+                    let dy = reader.u8()? as i8;
+                    (
+                        (base.x as i32 + dx as i32) as u16,
+                        (base.y as i32 + dy as i32) as u16,
+                    )
+                }
+                (true, None) => return Err(SnapshotError::Truncated),
+                (false, _) => (reader.u16()?, reader.u16()?),
+            };
+            actors.insert(
+                id,
+                QuantizedActor {
+                    x,
+                    y,
+                    faction,
+                    pawn: flags & PAWN != 0,
+                },
+            );
+        }
+
+        let snapshot = QuantizedSnapshot {
+            tick,
+            time_left_ms,
+            input_tick,
+            actors,
+        };
+        let restored = self.quantizer.restore(&snapshot);
+        if self.received.len() == HISTORY_LEN {
+            self.received.pop_front();
+        }
+        self.received.push_back(snapshot);
+        Ok(restored)
+    }
+}
@@ -0,0 +1,84 @@
+use crowded_plaza_net::{BotClient, Server, ServerSettings};
+use crowded_plaza_sim::{GameConfig, MatchEvent};
+use std::{
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+// a server on localhost with a short match, until `matches` are played
+fn spawn_server(config: GameConfig, matches: u32) -> String {
+    let (address_tx, address_rx) = mpsc::channel();
+    thread::spawn(move || {
+        let settings = ServerSettings {
+            snapshot_interval: 3,
+            restart_delay: 0.5,
+        };
+        let mut server = Server::bind("127.0.0.1:0", config, settings).unwrap();
+        address_tx.send(server.local_addr().unwrap()).unwrap();
+        let tick = Duration::from_secs_f32(crowded_plaza_sim::TICK_SECONDS);
+        while server.matches_played() < matches {
+            server.update();
+            thread::sleep(tick);
+        }
+    });
+    format!("ws://{}", address_rx.recv().unwrap())
+}
+
+fn small_config(player_count: i32) -> GameConfig {
+    GameConfig {
+        actor_count: 200,
+        player_count,
+        opponent_count: 2,
+        game_time: 2.0,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn bots_play_a_match() {
+    let url = spawn_server(small_config(2), 1);
+    let mut bots = [
+        BotClient::connect(&url, "first").unwrap(),
+        BotClient::connect(&url, "second").unwrap(),
+    ];
+
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while !bots
+        .iter()
+        .all(|bot| bot.events.contains(&MatchEvent::TimeUp))
+    {
+        assert!(Instant::now() < deadline, "the match did not end");
+        for bot in bots.iter_mut() {
+            bot.update();
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    let mut seats: Vec<u32> = bots.iter().map(|bot| bot.seat.unwrap()).collect();
+    seats.sort_unstable();
+    assert_eq!(seats, vec![0, 1]);
+    for bot in bots.iter() {
+        assert!(bot.snapshots_received > 10);
+        let snapshot = bot.snapshot.as_ref().unwrap();
+        // the neutrals and the pawns of both seats and both bots
+        assert_eq!(snapshot.actors.len(), 200 + 2 + 2);
+    }
+}
+
+#[test]
+fn full_server_rejects_players() {
+    let url = spawn_server(small_config(1), 1);
+    let mut first = BotClient::connect(&url, "first").unwrap();
+    let mut second = BotClient::connect(&url, "second").unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while first.seat.is_none() || !second.rejected {
+        assert!(Instant::now() < deadline, "no answer to joining");
+        first.update();
+        second.update();
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(first.seat, Some(0));
+    assert_eq!(second.seat, None);
+}
@@ -0,0 +1,179 @@
+use bevy::prelude::*;
+use crowded_plaza_net::{
+    DesyncReport, LinkConditions, Lockstep, LockstepHost, LockstepPeer, LockstepSettings,
+    PeerMessage, SimulatedLink, Snapshot,
+};
+use crowded_plaza_sim::{GameConfig, Heading, TICK_SECONDS};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+const SETTINGS: LockstepSettings = LockstepSettings {
+    input_delay: 6,
+    checksum_interval: 30,
+};
+
+fn small_config(player_count: i32) -> GameConfig {
+    GameConfig {
+        actor_count: 300,
+        player_count,
+        opponent_count: 2,
+        game_time: 5.0,
+        ..Default::default()
+    }
+}
+
+// every peer steers somewhere else every half second
+fn steer(peer: &mut Lockstep, frame: u64) {
+    if frame % 30 == peer.seat() as u64 * 7 {
+        peer.steer(Heading((frame * 7919 + peer.seat() as u64 * 104729) as u16));
+    }
+}
+
+// peers connected to each other by simulated links, until every match is over or stopped
+fn play(peers: &mut [Lockstep], conditions: LinkConditions, mut tamper: impl FnMut(&mut Lockstep)) {
+    let count = peers.len();
+    // from every peer to every other one, by sender and receiver
+    let mut links: Vec<Vec<SimulatedLink<PeerMessage>>> = (0..count)
+        .map(|from| {
+            (0..count)
+                .map(|to| SimulatedLink::new(conditions, (from * count + to) as u64))
+                .collect()
+        })
+        .collect();
+
+    for frame in 0..2000 {
+        if peers
+            .iter()
+            .all(|peer| peer.is_over() || peer.desync().is_some())
+        {
+            return;
+        }
+        for (to, peer) in peers.iter_mut().enumerate() {
+            for from in links.iter_mut() {
+                for message in from[to].advance(TICK_SECONDS) {
+                    peer.receive(message);
+                }
+            }
+        }
+        for (from, peer) in peers.iter_mut().enumerate() {
+            steer(peer, frame);
+            tamper(peer);
+            for message in peer.advance() {
+                for (to, link) in links[from].iter_mut().enumerate() {
+                    if to != from {
+                        link.send(message.clone());
+                    }
+                }
+            }
+        }
+    }
+    panic!("the match did not end");
+}
+
+fn final_checksum(peer: &mut Lockstep) -> u64 {
+    let tick = peer.tick();
+    Snapshot::capture(peer.world(), tick, 0.0).checksum()
+}
+
+#[test]
+fn peers_stay_in_step() {
+    let config = small_config(3);
+    let mut peers: Vec<Lockstep> = (0..3)
+        .map(|seat| Lockstep::new(seat, 11, config.clone(), SETTINGS))
+        .collect();
+    play(
+        &mut peers,
+        LinkConditions {
+            latency: 0.04,
+            jitter: 0.03,
+            loss: 0.0,
+        },
+        |_| {},
+    );
+
+    let checksum = final_checksum(&mut peers[0]);
+    for peer in peers.iter_mut() {
+        assert!(peer.is_over());
+        assert_eq!(peer.desync(), None);
+        // a 5 second match, the last checksum may still be on its way
+        assert!(peer.checksums_matched() >= 9);
+        assert_eq!(final_checksum(peer), checksum);
+    }
+}
+
+#[test]
+fn desyncs_are_reported() {
+    let config = small_config(2);
+    let mut peers: Vec<Lockstep> = (0..2)
+        .map(|seat| Lockstep::new(seat, 5, config.clone(), SETTINGS))
+        .collect();
+    // the second peer's floating point math goes a tiny bit off after tick 100
+    play(&mut peers, LinkConditions::default(), |peer| {
+        if peer.seat() == 1 && peer.tick() == 100 {
+            let world = peer.world();
+            let mut actor = world.query::<&mut Transform>();
+            actor.iter_mut(world).next().unwrap().translation.x += 0.0001;
+        }
+    });
+
+    let reports: Vec<DesyncReport> = peers
+        .iter()
+        .map(|peer| peer.desync().unwrap().clone())
+        .collect();
+    for (seat, report) in reports.iter().enumerate() {
+        let other = &reports[1 - seat];
+        assert_eq!(report.seat, seat as u32);
+        // the first checksum after the change
+        assert_eq!(report.tick, 120);
+        assert_eq!(report.remote_checksums, vec![(other.seat, other.checksum)]);
+        assert_eq!(report.checksum, report.snapshot.checksum());
+        assert!(report.inputs.iter().all(|(tick, _, _)| *tick <= 120));
+        assert!(report.inputs.iter().any(|(_, seat, _)| *seat == 1));
+
+        let parsed: DesyncReport = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(&parsed, report);
+    }
+    assert_ne!(reports[0].file_name(), reports[1].file_name());
+    // the actors that differ
+    let differ = reports[0]
+        .snapshot
+        .actors
+        .iter()
+        .zip(reports[1].snapshot.actors.iter())
+        .filter(|(first, second)| first != second)
+        .count();
+    assert!(differ > 0);
+}
+
+// steer and update `peer` in real time until its match is over, returns the final checksum
+fn play_over_websocket(mut peer: LockstepPeer) -> u64 {
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while !peer.lockstep.is_over() {
+        assert!(Instant::now() < deadline, "the match did not end");
+        assert_eq!(peer.left, None);
+        let heading = peer.lockstep.tick() * 331 + peer.lockstep.seat() as u64 * 9000;
+        peer.lockstep.steer(Heading(heading as u16));
+        peer.update();
+        thread::sleep(Duration::from_millis(2));
+    }
+    assert_eq!(peer.lockstep.desync(), None);
+    assert_eq!(peer.names[1].1, "second");
+    final_checksum(&mut peer.lockstep)
+}
+
+#[test]
+fn peers_over_websocket() {
+    let config = GameConfig {
+        game_time: 2.0,
+        ..small_config(2)
+    };
+    let host = LockstepHost::bind("127.0.0.1:0").unwrap();
+    let url = format!("ws://{}", host.local_addr().unwrap());
+    // the simulation stays on the thread it runs on
+    let joining =
+        thread::spawn(move || play_over_websocket(LockstepPeer::join(&url, "second").unwrap()));
+    let hosting = play_over_websocket(host.start("first", config, SETTINGS).unwrap());
+    assert_eq!(joining.join().unwrap(), hosting);
+}
@@ -0,0 +1,183 @@
+use bevy::prelude::*;
+use crowded_plaza_net::{
+    ClientMessage, InputTracker, LinkConditions, PawnPredictor, SimulatedLink, Snapshot,
+    SnapshotBuffer, SnapshotDecoder, SnapshotEncoder,
+};
+use crowded_plaza_sim::{
+    headless_match, Faction, GameConfig, Heading, PlayerInput, SimClock, TICK_SECONDS,
+};
+use std::collections::BTreeMap;
+
+const SNAPSHOT_INTERVAL: u64 = 3;
+const INTERPOLATION_DELAY: f32 = 0.1;
+// a match of 20 seconds, the player turns every second
+const TICKS: u64 = 1200;
+const STEER_TICKS: u64 = 60;
+// the first seconds start the prediction from an old snapshot and fill the buffer
+const SETTLE_TICKS: u64 = 120;
+
+#[derive(Debug, Default)]
+struct Stats {
+    // largest misprediction found once settled
+    max_correction: f32,
+    // distance of drawn actors to where they really were at the drawn tick
+    mean_interpolation_error: f32,
+    // share of frames drawn from the latest snapshot alone, playback ran out of snapshots
+    starved: f32,
+    bytes_per_tick: f32,
+}
+
+// the server side of a match and one player, over a link with `conditions` both ways
+fn play(conditions: LinkConditions) -> Stats {
+    let config = GameConfig {
+        actor_count: 300,
+        opponent_count: 3,
+        game_time: 60.0,
+        ..Default::default()
+    };
+    let mut app = headless_match(config.clone(), Some(3), false);
+    let mut encoder = SnapshotEncoder::new(config.action_region);
+    let mut inputs = InputTracker::default();
+    let mut truth = BTreeMap::new();
+
+    let mut downlink = SimulatedLink::new(conditions, 1);
+    // steering is never lost over WebSocket, only snapshots are skipped
+    let mut uplink = SimulatedLink::new(
+        LinkConditions {
+            loss: 0.0,
+            ..conditions
+        },
+        2,
+    );
+    let mut decoder = SnapshotDecoder::new(config.action_region);
+    let mut predictor = PawnPredictor::new(Faction::Player(0), config.pawn_speed);
+    let mut buffer = SnapshotBuffer::new(INTERPOLATION_DELAY);
+
+    let mut stats = Stats::default();
+    let mut bytes = 0;
+    let mut frames = 0;
+    let mut starved = 0;
+    let mut error_sum = 0.0;
+    let mut error_count = 0;
+    for frame in 0..TICKS {
+        // the server takes the steering and acks that arrived, ticks and sends a snapshot
+        for message in uplink.advance(TICK_SECONDS) {
+            match message {
+                ClientMessage::Steer { heading, tick } => {
+                    let next_tick = app.world.resource::<SimClock>().tick() + 1;
+                    inputs.steered(tick, next_tick);
+                    let mut input = app.world.resource_mut::<PlayerInput>();
+                    input.steer.insert(0, Heading(heading));
+                }
+                ClientMessage::Ack { tick } => encoder.ack(tick),
+                ClientMessage::Join { .. } => {}
+            }
+        }
+        app.update();
+        let tick = app.world.resource::<SimClock>().tick();
+        let mut snapshot = Snapshot::capture(&mut app.world, tick, 0.0);
+        if tick % SNAPSHOT_INTERVAL == 0 {
+            snapshot.input_tick = inputs.input_tick(tick);
+            let encoded = encoder.encode(&snapshot);
+            bytes += encoded.len();
+            downlink.send(encoded);
+        }
+        truth.insert(tick, snapshot);
+
+        // the client takes the snapshots that arrived, steers and draws
+        for encoded in downlink.advance(TICK_SECONDS) {
+            let snapshot = decoder.decode(&encoded).unwrap();
+            uplink.send(ClientMessage::Ack {
+                tick: snapshot.tick,
+            });
+            if let Some(correction) = predictor.reconcile(&snapshot) {
+                if frame > SETTLE_TICKS {
+                    stats.max_correction = stats.max_correction.max(correction.length());
+                }
+            }
+            buffer.push(snapshot);
+        }
+        if frame % STEER_TICKS == STEER_TICKS / 2 {
+            predictor.steer(Heading((frame * 9973) as u16));
+        }
+        if let Some(message) = predictor.advance(TICK_SECONDS) {
+            uplink.send(message);
+        }
+        buffer.advance(TICK_SECONDS);
+
+        let (render_tick, (from, to, fraction)) = match (buffer.render_tick(), buffer.sample()) {
+            (Some(render_tick), Some(sample)) => (render_tick, sample),
+            _ => continue,
+        };
+        if frame > SETTLE_TICKS {
+            frames += 1;
+            if from.tick == to.tick {
+                starved += 1;
+            }
+        }
+        let earlier = &truth[&(render_tick.floor() as u64)];
+        let later = &truth[&(render_tick.ceil() as u64)];
+        let truth_fraction = render_tick.fract() as f32;
+        for (index, (from, to)) in from.actors.iter().zip(to.actors.iter()).enumerate() {
+            assert_eq!(from.id, earlier.actors[index].id);
+            let drawn = Vec2::from(from.position).lerp(Vec2::from(to.position), fraction);
+            let real = Vec2::from(earlier.actors[index].position)
+                .lerp(Vec2::from(later.actors[index].position), truth_fraction);
+            error_sum += drawn.distance(real);
+            error_count += 1;
+        }
+    }
+
+    stats.mean_interpolation_error = error_sum / error_count as f32;
+    stats.starved = starved as f32 / frames as f32;
+    stats.bytes_per_tick = bytes as f32 / TICKS as f32;
+    println!("{:?}: {:?}", conditions, stats);
+    stats
+}
+
+#[test]
+fn perfect_link() {
+    let stats = play(LinkConditions::default());
+    // only the quantization is off
+    assert!(stats.max_correction < 0.002);
+    assert!(stats.mean_interpolation_error < 0.01);
+    assert_eq!(stats.starved, 0.0);
+}
+
+#[test]
+fn latency_jitter_and_loss() {
+    let stats = play(LinkConditions {
+        latency: 0.08,
+        jitter: 0.03,
+        loss: 0.05,
+    });
+    // steering that takes longer to arrive than the one before turns the pawn later on the
+    // server than predicted, by up to the jitter
+    let speed = GameConfig::default().pawn_speed;
+    assert!(stats.max_correction < 2.0 * speed * (0.03 + TICK_SECONDS));
+    assert!(stats.mean_interpolation_error < 0.02);
+    assert!(stats.starved < 0.05);
+}
+
+#[test]
+fn simulated_link_delays_in_order() {
+    let conditions = LinkConditions {
+        latency: 0.1,
+        jitter: 0.05,
+        loss: 0.2,
+    };
+    let mut link = SimulatedLink::new(conditions, 7);
+    let mut arrived = Vec::new();
+    for message in 0..1000 {
+        link.send(message);
+        arrived.extend(link.advance(0.01));
+        // nothing arrives faster than the latency
+        assert!(arrived.last().is_none_or(|last| message - last >= 9));
+    }
+    arrived.extend(link.advance(1.0));
+
+    assert_eq!(link.sent(), 1000);
+    assert_eq!(arrived.len() as u32, link.sent() - link.lost());
+    assert!((150..250).contains(&link.lost()));
+    assert!(arrived.windows(2).all(|pair| pair[0] < pair[1]));
+}
@@ -0,0 +1,188 @@
+use crowded_plaza_net::{
+    ActorState, Quantizer, Snapshot, SnapshotDecoder, SnapshotEncoder, SnapshotError,
+};
+use crowded_plaza_sim::{headless_match, Faction, GameConfig};
+use std::collections::VecDeque;
+
+// snapshots every 3 ticks, like the server by default
+const SNAPSHOT_INTERVAL: u64 = 3;
+
+fn assert_decoded(decoded: &Snapshot, snapshot: &Snapshot, quantizer: &Quantizer) {
+    // the precision plus rounding of the float math
+    let tolerance = quantizer.precision() * 1.01;
+    assert_eq!(decoded.tick, snapshot.tick);
+    assert_eq!(decoded.input_tick, snapshot.input_tick);
+    assert!((decoded.time_left - snapshot.time_left).abs() < 0.001);
+    assert_eq!(decoded.actors.len(), snapshot.actors.len());
+    for (decoded, actor) in decoded.actors.iter().zip(snapshot.actors.iter()) {
+        assert_eq!(decoded.id, actor.id);
+        assert_eq!(decoded.faction, actor.faction);
+        assert_eq!(decoded.pawn, actor.pawn);
+        for axis in 0..2 {
+            assert!(
+                (decoded.position[axis] - actor.position[axis]).abs() <= tolerance,
+                "actor {} is at {:?} instead of {:?}",
+                actor.id,
+                decoded.position,
+                actor.position
+            );
+        }
+    }
+}
+
+#[test]
+fn full_snapshot_round_trip() {
+    let config = GameConfig::default();
+    let mut app = headless_match(config.clone(), Some(7), false);
+    for _ in 0..120 {
+        app.update();
+    }
+    let snapshot = Snapshot::capture(&mut app.world, 120, 42.5);
+
+    let mut encoder = SnapshotEncoder::new(config.action_region);
+    let mut decoder = SnapshotDecoder::new(config.action_region);
+    let bytes = encoder.encode(&snapshot);
+    let decoded = decoder.decode(&bytes).unwrap();
+    assert_decoded(&decoded, &snapshot, &Quantizer::new(config.action_region));
+
+    let json = serde_json::to_string(&snapshot).unwrap().len();
+    println!(
+        "full snapshot of {} actors: {} bytes, {} as JSON",
+        snapshot.actors.len(),
+        bytes.len(),
+        json
+    );
+    // an id, flags, faction and two coordinates per actor
+    assert!(bytes.len() <= 16 + snapshot.actors.len() * 7);
+}
+
+#[test]
+fn deltas_against_acked_snapshots() {
+    let config = GameConfig::default();
+    let quantizer = Quantizer::new(config.action_region);
+    let mut app = headless_match(config.clone(), Some(7), false);
+    let mut encoder = SnapshotEncoder::new(config.action_region);
+    let mut decoder = SnapshotDecoder::new(config.action_region);
+    // acks arrive two snapshots late, as over a connection with some latency
+    let mut acks = VecDeque::new();
+
+    let ticks = 600;
+    let mut delta_bytes = 0;
+    let mut full_bytes = 0;
+    for tick in 1..=ticks {
+        app.update();
+        if tick % SNAPSHOT_INTERVAL != 0 {
+            continue;
+        }
+        let snapshot = Snapshot::capture(&mut app.world, tick, 0.0);
+        let bytes = encoder.encode(&snapshot);
+        let decoded = decoder.decode(&bytes).unwrap();
+        assert_decoded(&decoded, &snapshot, &quantizer);
+        delta_bytes += bytes.len();
+        full_bytes += SnapshotEncoder::new(config.action_region)
+            .encode(&snapshot)
+            .len();
+
+        acks.push_back(decoded.tick);
+        if acks.len() > 2 {
+            encoder.ack(acks.pop_front().unwrap());
+        }
+    }
+
+    println!(
+        "{} bytes per tick as deltas, {} in full",
+        delta_bytes as u64 / ticks,
+        full_bytes as u64 / ticks
+    );
+    assert!(delta_bytes * 3 < full_bytes * 2);
+}
+
+fn actor(id: u32, x: f32, faction: Faction) -> ActorState {
+    ActorState {
+        id,
+        position: [x, -x],
+        faction,
+        pawn: false,
+    }
+}
+
+#[test]
+fn changes_since_the_baseline() {
+    let quantizer = Quantizer::new(20.0);
+    let mut encoder = SnapshotEncoder::new(20.0);
+    let mut decoder = SnapshotDecoder::new(20.0);
+    let first = Snapshot {
+        tick: 3,
+        time_left: 10.0,
+        input_tick: None,
+        actors: vec![
+            actor(0, 1.0, Faction::Neutral),
+            actor(1, 2.0, Faction::Neutral),
+            actor(2, 3.0, Faction::Bot(4)),
+        ],
+    };
+    decoder.decode(&encoder.encode(&first)).unwrap();
+    encoder.ack(3);
+
+    // one moved far, one was converted, one is gone and one is new
+    let second = Snapshot {
+        tick: 6,
+        time_left: 9.95,
+        input_tick: Some(4),
+        actors: vec![
+            actor(0, 1.0, Faction::Neutral),
+            actor(1, -30.0, Faction::Player(31)),
+            actor(5, 3.0, Faction::Bot(99)),
+        ],
+    };
+    let bytes = encoder.encode(&second);
+    assert_decoded(&decoder.decode(&bytes).unwrap(), &second, &quantizer);
+
+    // nothing changed, only the header is left
+    let third = Snapshot {
+        tick: 9,
+        ..second.clone()
+    };
+    encoder.ack(6);
+    let bytes = encoder.encode(&third);
+    assert!(bytes.len() < 8);
+    assert_decoded(&decoder.decode(&bytes).unwrap(), &third, &quantizer);
+}
+
+#[test]
+fn unknown_baselines() {
+    let snapshot = |tick| Snapshot {
+        tick,
+        time_left: 1.0,
+        input_tick: None,
+        actors: vec![actor(0, 1.0, Faction::Neutral)],
+    };
+    let mut encoder = SnapshotEncoder::new(20.0);
+    encoder.encode(&snapshot(3));
+    // never sent, so the next snapshot is still full
+    encoder.ack(2);
+    let full = encoder.encode(&snapshot(6));
+    assert!(SnapshotDecoder::new(20.0).decode(&full).is_ok());
+
+    // a delta needs the snapshot it refers to
+    encoder.ack(6);
+    let delta = encoder.encode(&snapshot(9));
+    assert_eq!(
+        SnapshotDecoder::new(20.0).decode(&delta),
+        Err(SnapshotError::UnknownBaseline(6))
+    );
+    assert_eq!(
+        SnapshotDecoder::new(20.0).decode(&full[..full.len() - 1]),
+        Err(SnapshotError::Truncated)
+    );
+}
+
+#[test]
+fn actor_ids_out_of_range() {
+    // version, tick, no baseline, time left, no input tick, one record, for actor id 2^32
+    let bytes = [1, 0, 0, 0, 0, 1, 0x80, 0x80, 0x80, 0x80, 0x10];
+    assert_eq!(
+        SnapshotDecoder::new(20.0).decode(&bytes),
+        Err(SnapshotError::ActorIdOutOfRange)
+    );
+}
@@ -0,0 +1,48 @@
+// not every bench uses every helper
+#![allow(dead_code)]
+
+use bevy::prelude::*;
+use crowded_plaza_sim::GameConfig;
+
+// ticks before measuring, so some actors are converted and crowd around their pawns
+pub const WARMUP_TICKS: u32 = 60;
+
+// helper: the comma separated values of `--name a,b,c`
+pub fn list_flag(name: &str) -> Option<Vec<usize>> {
+    let flag = format!("--{}", name);
+    let value = std::env::args().skip_while(|arg| *arg != flag).nth(1)?;
+    let values = value
+        .split(',')
+        .map(|item| match item.trim().parse() {
+            Ok(item) => item,
+            Err(_) => panic!("invalid value `{}` for {}", value, flag),
+        })
+        .collect();
+    Some(values)
+}
+
+// helper: `actor_count` actors on a plaza grown so they are `density` times as crowded as in a
+// default match, and a match long enough for any measurement
+pub fn crowd(actor_count: usize, density: f32) -> GameConfig {
+    let default = GameConfig::default();
+    GameConfig {
+        actor_count: actor_count as i32,
+        action_region: default.action_region
+            * (actor_count as f32 / default.actor_count as f32 / density).sqrt(),
+        game_time: 3600.0,
+        ..default
+    }
+}
+
+// helper: run `WARMUP_TICKS` of a match that wasn't set up yet
+pub fn warm_up(mut app: App) -> App {
+    for _ in 0..WARMUP_TICKS {
+        app.update();
+    }
+    app
+}
+
+// helper: a match with `config`, warmed up
+pub fn headless_match(config: GameConfig) -> App {
+    warm_up(crowded_plaza_sim::headless_match(config, Some(1), false))
+}
@@ -0,0 +1,86 @@
+//! How faction conversion and repulsion scale with the cores they get, for crowds of 10k, 50k
+//! and 100k actors. The crowd is spread over a larger plaza the more actors there are, so every
+//! actor has about as many neighbors as in a default match.
+//!
+//! ```text
+//! cargo bench -p crowded_plaza_sim --bench parallel_systems -- [--actors 10000,50000,100000]
+//!     [--threads 1,2,4,8] [--runs 20]
+//! ```
+//!
+//! Thread counts default to powers of two up to the cores there are, speedups are against the
+//! first one.
+mod common;
+
+use bevy::{
+    prelude::*,
+    tasks::{ComputeTaskPool, TaskPoolBuilder},
+};
+use crowded_plaza_sim::{
+    change_actor_faction_system, rebuild_actor_space_system, repulse_actor_system,
+};
+use std::time::{Duration, Instant};
+
+use common::{crowd, list_flag, warm_up};
+
+// helper: a match where the systems get as many threads as asked for, the pool replaces the one
+// of the plugins before any system runs
+fn headless_match(actor_count: usize, threads: usize) -> App {
+    let mut app = crowded_plaza_sim::headless_match(crowd(actor_count, 1.0), Some(1), false);
+    app.insert_resource(ComputeTaskPool(
+        TaskPoolBuilder::new().num_threads(threads).build(),
+    ));
+    warm_up(app)
+}
+
+// helper: mean time of running `stage` on the match, with the kd-tree rebuilt before every run
+fn time_stage(app: &mut App, stage: &mut SystemStage, runs: usize) -> Duration {
+    let mut rebuild = SystemStage::single_threaded().with_system(rebuild_actor_space_system);
+    let mut total = Duration::ZERO;
+    for _ in 0..runs {
+        rebuild.run(&mut app.world);
+        let start = Instant::now();
+        stage.run(&mut app.world);
+        total += start.elapsed();
+    }
+    total / runs as u32
+}
+
+fn main() {
+    let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+    let actor_counts = list_flag("actors").unwrap_or_else(|| vec![10_000, 50_000, 100_000]);
+    let thread_counts = list_flag("threads").unwrap_or_else(|| {
+        std::iter::successors(Some(1), |threads| Some(threads * 2))
+            .take_while(|threads| *threads <= cores)
+            .collect()
+    });
+    let runs = list_flag("runs").map_or(20, |runs| runs[0]);
+
+    println!("{} cores", cores);
+    println!(
+        "{:>8} {:>8} {:>14} {:>8} {:>15} {:>8}",
+        "actors", "threads", "repulsion ms", "speedup", "conversion ms", "speedup"
+    );
+    for actor_count in actor_counts {
+        let mut first: Option<(f64, f64)> = None;
+        for threads in thread_counts.iter().copied() {
+            let mut app = headless_match(actor_count, threads);
+            let mut repulsion = SystemStage::single_threaded().with_system(repulse_actor_system);
+            let mut conversion =
+                SystemStage::single_threaded().with_system(change_actor_faction_system);
+            let repulsion_ms = time_stage(&mut app, &mut repulsion, runs).as_secs_f64() * 1000.0;
+            let conversion_ms = time_stage(&mut app, &mut conversion, runs).as_secs_f64() * 1000.0;
+
+            let (repulsion_base, conversion_base) =
+                *first.get_or_insert((repulsion_ms, conversion_ms));
+            println!(
+                "{:>8} {:>8} {:>14.2} {:>7.2}x {:>15.2} {:>7.2}x",
+                actor_count,
+                threads,
+                repulsion_ms,
+                repulsion_base / repulsion_ms,
+                conversion_ms,
+                conversion_base / conversion_ms
+            );
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! The kd-tree against the uniform grid, for crowds of 10k, 50k and 100k actors spread like in
+//! [`parallel_systems`](./parallel_systems.rs): rebuilding the index, finding the neighbors of
+//! every actor as conversion does, the nearest actor to every actor as repulsion does, and a
+//! whole tick of the match.
+//!
+//! ```text
+//! cargo bench -p crowded_plaza_sim --bench spatial_index -- [--actors 10000,50000,100000]
+//!     [--runs 20]
+//! ```
+mod common;
+
+use bevy::prelude::*;
+use crowded_plaza_sim::{Actor, ActorSpace, GameConfig, SpatialBackend};
+use std::time::{Duration, Instant};
+
+use common::{crowd, headless_match, list_flag};
+
+const BACKENDS: [SpatialBackend; 2] = [SpatialBackend::KdTree, SpatialBackend::UniformGrid];
+
+// helper: mean time of `f` in milliseconds
+fn time_ms(runs: usize, mut f: impl FnMut()) -> f64 {
+    let mut total = Duration::ZERO;
+    for _ in 0..runs {
+        let start = Instant::now();
+        f();
+        total += start.elapsed();
+    }
+    (total / runs as u32).as_secs_f64() * 1000.0
+}
+
+fn main() {
+    let actor_counts = list_flag("actors").unwrap_or_else(|| vec![10_000, 50_000, 100_000]);
+    let runs = list_flag("runs").map_or(20, |runs| runs[0]);
+
+    println!(
+        "{:>8} {:>13} {:>11} {:>13} {:>11} {:>8}",
+        "actors", "index", "rebuild ms", "neighbors ms", "nearest ms", "tick ms"
+    );
+    for actor_count in actor_counts {
+        for backend in BACKENDS {
+            let mut app = headless_match(GameConfig {
+                spatial_index: backend,
+                ..crowd(actor_count, 1.0)
+            });
+            let neighbor_threshold = app.world.resource::<GameConfig>().neighbor_threshold;
+            let actors: Vec<(Vec3, Entity)> = app
+                .world
+                .query_filtered::<(Entity, &Transform), With<Actor>>()
+                .iter(&app.world)
+                .map(|(entity, tr)| (tr.translation, entity))
+                .collect();
+
+            let mut spatial = ActorSpace::for_config(app.world.resource::<GameConfig>());
+            let rebuild_ms = time_ms(runs, || spatial.recreate(actors.clone()));
+            let neighbors_ms = time_ms(runs, || {
+                for (position, _) in actors.iter() {
+                    std::hint::black_box(spatial.within_distance(*position, neighbor_threshold));
+                }
+            });
+            let nearest_ms = time_ms(runs, || {
+                for (position, _) in actors.iter() {
+                    std::hint::black_box(spatial.k_nearest_neighbour(*position, 2));
+                }
+            });
+            let tick_ms = time_ms(runs, || app.update());
+
+            let index = match backend {
+                SpatialBackend::KdTree => "kd_tree",
+                SpatialBackend::UniformGrid => "uniform_grid",
+            };
+            println!(
+                "{:>8} {:>13} {:>11.2} {:>13.2} {:>11.2} {:>8.2}",
+                actor_count, index, rebuild_ms, neighbors_ms, nearest_ms, tick_ms
+            );
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! What each simulation system costs per tick, at several crowd sizes and densities, measured
+//! with criterion on a headless match that already ran a second.
+//!
+//! ```text
+//! cargo bench -p crowded_plaza_sim --bench systems
+//! cargo bench -p crowded_plaza_sim --bench systems -- repulsion/dense
+//! ```
+//!
+//! Keep a run to compare later ones against with `-- --save-baseline <name>`, and compare with
+//! `-- --baseline <name>`; criterion reports what got faster or slower.
+mod common;
+
+use bevy::{ecs::schedule::IntoSystemDescriptor, prelude::*, utils::HashMap};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use crowded_plaza_sim::{
+    change_actor_faction_system, follow_pawn_system, move_actor_system, move_pawn_system,
+    rebuild_actor_space_system, repulse_actor_system, ActorSpace, GameConfig, SpatialBackend,
+};
+
+use common::{crowd, headless_match};
+
+const ACTOR_COUNTS: [i32; 3] = [2_000, 10_000, 50_000];
+// actors per area against the default plaza
+const DENSITIES: [(&str, f32); 3] = [("sparse", 0.25), ("default", 1.0), ("dense", 4.0)];
+
+// matches are set up the first time a benchmark that isn't filtered out needs them
+#[derive(Default)]
+struct Matches(HashMap<(i32, &'static str), App>);
+
+impl Matches {
+    fn get(&mut self, actor_count: i32, density: (&'static str, f32)) -> &mut App {
+        self.0
+            .entry((actor_count, density.0))
+            .or_insert_with(|| headless_match(crowd(actor_count as usize, density.1)))
+    }
+}
+
+// helper: a group timing one run of `system` for every crowd, with the spatial index up to date
+fn bench_system<Params>(
+    c: &mut Criterion,
+    matches: &mut Matches,
+    name: &str,
+    system: impl IntoSystemDescriptor<Params> + Clone,
+) {
+    let mut group = c.benchmark_group(name);
+    for density in DENSITIES {
+        for actor_count in ACTOR_COUNTS {
+            let id = BenchmarkId::new(density.0, actor_count);
+            group.bench_function(id, |b| {
+                let app = matches.get(actor_count, density);
+                SystemStage::single_threaded()
+                    .with_system(rebuild_actor_space_system)
+                    .run(&mut app.world);
+                let mut stage = SystemStage::single_threaded().with_system(system.clone());
+                b.iter(|| stage.run(&mut app.world));
+            });
+        }
+    }
+    group.finish();
+}
+
+fn systems(c: &mut Criterion) {
+    let mut matches = Matches::default();
+    bench_system(c, &mut matches, "move", move_actor_system);
+    bench_system(c, &mut matches, "move_pawn", move_pawn_system);
+    bench_system(c, &mut matches, "follow", follow_pawn_system);
+    bench_system(c, &mut matches, "repulsion", repulse_actor_system);
+    bench_system(c, &mut matches, "conversion", change_actor_faction_system);
+
+    // the index is rebuilt every tick, with either backend
+    let mut group = c.benchmark_group("rebuild_index");
+    for backend in [SpatialBackend::KdTree, SpatialBackend::UniformGrid] {
+        let index = match backend {
+            SpatialBackend::KdTree => "kd_tree",
+            SpatialBackend::UniformGrid => "uniform_grid",
+        };
+        for density in DENSITIES {
+            for actor_count in ACTOR_COUNTS {
+                let id = BenchmarkId::new(format!("{}/{}", index, density.0), actor_count);
+                group.bench_function(id, |b| {
+                    let app = matches.get(actor_count, density);
+                    let cell_size = app.world.resource::<GameConfig>().neighbor_threshold;
+                    let previous = app.world.remove_resource::<ActorSpace>().unwrap();
+                    app.world
+                        .insert_resource(ActorSpace::new(backend, cell_size));
+                    let mut stage =
+                        SystemStage::single_threaded().with_system(rebuild_actor_space_system);
+                    b.iter(|| stage.run(&mut app.world));
+                    app.world.insert_resource(previous);
+                });
+            }
+        }
+    }
+    group.finish();
+
+    // every system of a tick, the match plays on between iterations
+    let mut group = c.benchmark_group("tick");
+    for density in DENSITIES {
+        for actor_count in ACTOR_COUNTS {
+            group.bench_function(BenchmarkId::new(density.0, actor_count), |b| {
+                let app = matches.get(actor_count, density);
+                b.iter(|| app.update());
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    // a 50k actor tick takes tens of milliseconds, fewer samples keep the suite to minutes
+    config = Criterion::default().sample_size(20);
+    targets = systems
+}
+criterion_main!(benches);
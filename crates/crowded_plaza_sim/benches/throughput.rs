@@ -0,0 +1,56 @@
+//! How many ticks a second the whole simulation runs headless, for crowds of 10k, 50k and 100k
+//! actors spread like in [`parallel_systems`](./parallel_systems.rs), with either spatial
+//! index. A tick is real time at 60 ticks a second, below that a match slows down.
+//!
+//! ```text
+//! cargo bench -p crowded_plaza_sim --bench throughput -- [--actors 10000,50000,100000]
+//!     [--ticks 300]
+//! ```
+mod common;
+
+use crowded_plaza_sim::{GameConfig, SpatialBackend, TICK_SECONDS};
+use std::time::Instant;
+
+use common::{crowd, headless_match, list_flag};
+
+const BACKENDS: [SpatialBackend; 2] = [SpatialBackend::KdTree, SpatialBackend::UniformGrid];
+
+fn main() {
+    let actor_counts = list_flag("actors").unwrap_or_else(|| vec![10_000, 50_000, 100_000]);
+    let ticks = list_flag("ticks").map_or(300, |ticks| ticks[0]);
+    let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+
+    println!("{} cores, {} ticks", cores, ticks);
+    println!(
+        "{:>8} {:>13} {:>9} {:>9} {:>15} {:>10}",
+        "actors", "index", "ms/tick", "ticks/s", "actor ticks/s", "real time"
+    );
+    for actor_count in actor_counts {
+        for backend in BACKENDS {
+            let mut app = headless_match(GameConfig {
+                spatial_index: backend,
+                ..crowd(actor_count, 1.0)
+            });
+            let start = Instant::now();
+            for _ in 0..ticks {
+                app.update();
+            }
+            let seconds = start.elapsed().as_secs_f64();
+
+            let ticks_per_second = ticks as f64 / seconds;
+            let index = match backend {
+                SpatialBackend::KdTree => "kd_tree",
+                SpatialBackend::UniformGrid => "uniform_grid",
+            };
+            println!(
+                "{:>8} {:>13} {:>9.2} {:>9.1} {:>15.0} {:>9.2}x",
+                actor_count,
+                index,
+                seconds * 1000.0 / ticks as f64,
+                ticks_per_second,
+                ticks_per_second * actor_count as f64,
+                ticks_per_second * TICK_SECONDS as f64
+            );
+        }
+    }
+}
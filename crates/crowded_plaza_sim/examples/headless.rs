@@ -0,0 +1,43 @@
+//! Runs a single match without a window and prints the final faction counts.
+//!
+//! ```
+//! cargo run -p crowded_plaza_sim --example headless -- [seed] [config.ron]
+//! ```
+use bevy::{app::AppExit, prelude::*};
+use crowded_plaza_sim::{headless_match, Faction, FactionRegistry, GameConfig, MatchEvent};
+
+fn main() {
+    let seed = std::env::args().nth(1).and_then(|seed| seed.parse().ok());
+    let config = match std::env::args().nth(2) {
+        Some(path) => {
+            let bytes = std::fs::read(&path).expect("cannot read config file");
+            GameConfig::from_ron(&bytes).unwrap_or_else(|e| panic!("{}: {}", path, e))
+        }
+        None => GameConfig::default(),
+    };
+    // no need to wait for real time, tick as fast as we can
+    headless_match(config, seed, false)
+        .add_system(exit_on_match_end)
+        .run();
+}
+
+fn exit_on_match_end(
+    mut match_events: EventReader<MatchEvent>,
+    registry: Res<FactionRegistry>,
+    mut app_exit_events: EventWriter<AppExit>,
+) {
+    for event in match_events.iter() {
+        println!("{:?}", event);
+        if matches!(
+            event,
+            MatchEvent::PawnEliminated {
+                faction: Faction::PLAYER
+            } | MatchEvent::TimeUp
+        ) {
+            for (faction, info) in registry.iter() {
+                println!("{0:?} {1}: {2}", faction, info.name, info.count);
+            }
+            app_exit_events.send(AppExit);
+        }
+    }
+}
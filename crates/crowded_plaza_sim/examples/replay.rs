@@ -0,0 +1,56 @@
+//! Plays a recorded match without a window and prints the final faction counts, to check what
+//! happened in a reported match.
+//!
+//! ```
+//! cargo run -p crowded_plaza_sim --example replay -- <file.replay>
+//! ```
+use bevy::{app::AppExit, prelude::*};
+use crowded_plaza_sim::{
+    setup_match, FactionRegistry, MatchEvent, Replay, ReplayPlayback, SimClock, SimSeed,
+    SimulationPlugin, TickMode,
+};
+
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .expect("usage: replay <file.replay>");
+    let bytes = std::fs::read(&path).expect("cannot read replay file");
+    let replay = Replay::from_bytes(&bytes).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    println!(
+        "Replaying seed {0}, {1} ticks, {2} inputs, {3} config changes",
+        replay.seed,
+        replay.end_tick,
+        replay.inputs.len(),
+        replay.config_changes.len()
+    );
+
+    let mut clock = SimClock::new(TickMode::Manual);
+    clock.last_tick = Some(replay.end_tick);
+    App::new()
+        .add_plugins(MinimalPlugins)
+        .add_plugin(SimulationPlugin)
+        .insert_resource(replay.config.clone())
+        .insert_resource(SimSeed(Some(replay.seed)))
+        .insert_resource(clock)
+        .insert_resource(ReplayPlayback(replay))
+        .add_startup_system(setup_match)
+        .add_system(exit_on_replay_end)
+        .run();
+}
+
+fn exit_on_replay_end(
+    mut match_events: EventReader<MatchEvent>,
+    clock: Res<SimClock>,
+    registry: Res<FactionRegistry>,
+    mut app_exit_events: EventWriter<AppExit>,
+) {
+    for event in match_events.iter() {
+        println!("{:?}", event);
+    }
+    if matches!(clock.last_tick, Some(last) if clock.tick() >= last) {
+        for (faction, info) in registry.iter() {
+            println!("{0:?} {1}: {2}", faction, info.name, info.count);
+        }
+        app_exit_events.send(AppExit);
+    }
+}
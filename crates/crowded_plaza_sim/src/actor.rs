@@ -0,0 +1,306 @@
+use bevy::{prelude::*, tasks::ComputeTaskPool};
+use rand::Rng;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    boundary::apply_boundary, obstacle::apply_obstacles, ActorSpace, Faction, FlowFields,
+    GameConfig, Heading, Obstacles, PreviousTranslation, ReplayRecorder, SimClock, SimRng,
+    TickStats, PAR_BATCH_SIZE, TICK_SECONDS,
+};
+
+#[derive(Component)]
+pub struct Actor {
+    pub faction: Faction,
+    /// units per second
+    pub velocity: Vec3,
+    /// units per second squared, damped every tick
+    pub accleration: Vec3,
+}
+
+#[derive(Component)]
+pub struct Pawn;
+/// Marks the pawn of the player in this seat, leading [`Faction::Player`] with the same id.
+#[derive(Component)]
+pub struct PlayerController(pub u32);
+
+/// Where the players want to go, set between ticks and applied by the next tick.
+#[derive(Clone, Debug, Default)]
+pub struct PlayerInput {
+    /// Heading per seat, for the players that steered since the last tick.
+    pub steer: BTreeMap<u32, Heading>,
+}
+
+// helper: change a actor's direction with some chance
+fn random_change_direction(actor: &mut Actor, rng: &mut SimRng, speed: f32, move_scale: f32) {
+    let change_direction_random = rng.gen_range(0.0..1.0);
+    if change_direction_random < 0.01 {
+        let mut random_direction =
+            Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
+        random_direction = random_direction.normalize() * move_scale;
+        let new_direction = actor.velocity + random_direction;
+        actor.velocity = new_direction.normalize() * speed;
+    };
+}
+
+// system: actor with no faction will change direction randomly
+pub fn change_direction_actor_system(
+    config: Res<GameConfig>,
+    mut rng: ResMut<SimRng>,
+    mut actor_query: Query<&mut Actor, Without<Pawn>>,
+) {
+    for mut actor in actor_query.iter_mut() {
+        if !actor.faction.is_neutral() {
+            continue;
+        }
+        random_change_direction(
+            &mut actor,
+            &mut rng,
+            config.wander_speed,
+            config.opponent_move_scale,
+        );
+    }
+}
+
+// system: turn the players' pawns to the headings steered since the last tick, and record the
+// first seat's, replays only have one player
+pub fn steer_player_system(
+    config: Res<GameConfig>,
+    clock: Res<SimClock>,
+    mut input: ResMut<PlayerInput>,
+    mut recorder: Option<ResMut<ReplayRecorder>>,
+    mut player_query: Query<(&PlayerController, &mut Actor), With<Pawn>>,
+) {
+    if input.steer.is_empty() {
+        return;
+    }
+    for (controller, mut player) in player_query.iter_mut() {
+        let heading = match input.steer.remove(&controller.0) {
+            Some(heading) => heading,
+            None => continue,
+        };
+        player.velocity = heading.direction().extend(0.0) * config.pawn_speed;
+        if let (0, Some(recorder)) = (controller.0, recorder.as_mut()) {
+            recorder.0.inputs.push((clock.tick(), heading));
+        }
+    }
+    // seats without a pawn
+    input.steer.clear();
+}
+
+// system: update actor's location with velocity, clamp velocity and damp acceleration
+pub fn move_actor_system(
+    config: Res<GameConfig>,
+    obstacles: Res<Obstacles>,
+    mut actor_query: Query<(&mut Transform, &mut Actor, &mut PreviousTranslation), Without<Pawn>>,
+) {
+    let boundary = config.boundary();
+    for (mut tr, mut actor, mut previous) in actor_query.iter_mut() {
+        let actor = &mut *actor;
+        tr.translation += actor.velocity * TICK_SECONDS;
+        // a wrap jumps, it is not drawn as a move across the plaza
+        previous.0 += apply_boundary(&config, &boundary, &mut tr.translation, &mut actor.velocity);
+        apply_obstacles(&obstacles, &mut tr.translation, &mut actor.velocity);
+        let acc = actor.accleration;
+        actor.velocity += acc * TICK_SECONDS;
+        if actor.velocity.length() > config.pawn_speed {
+            actor.velocity = actor.velocity.normalize() * config.pawn_speed;
+        }
+        actor.accleration = acc * 0.5; // damping
+    }
+}
+
+// system: update pawn's location with velocity, at the same speed when the boundary turns it or
+// it slides along an obstacle
+pub fn move_pawn_system(
+    config: Res<GameConfig>,
+    obstacles: Res<Obstacles>,
+    mut actor_query: Query<(&mut Transform, &mut Actor, &mut PreviousTranslation), With<Pawn>>,
+) {
+    let boundary = config.boundary();
+    for (mut tr, mut actor, mut previous) in actor_query.iter_mut() {
+        tr.translation += actor.velocity * TICK_SECONDS;
+        let speed = actor.velocity.length();
+        previous.0 += apply_boundary(&config, &boundary, &mut tr.translation, &mut actor.velocity);
+        apply_obstacles(&obstacles, &mut tr.translation, &mut actor.velocity);
+        actor.velocity = actor.velocity.normalize_or_zero() * speed;
+    }
+}
+
+// system: faction's actor should follow leader pawn's dirion, along the flow field around the
+// obstacles in the way
+pub fn follow_pawn_system(
+    config: Res<GameConfig>,
+    obstacles: Res<Obstacles>,
+    flow: Res<FlowFields>,
+    mut actor_query: Query<(&mut Actor, &Transform), Without<Pawn>>,
+    pawn_query: Query<(&Actor, &Pawn, &Transform)>,
+) {
+    let mut faction_to_velocity: HashMap<Faction, Vec3> = HashMap::new();
+    let mut faction_to_position: HashMap<Faction, Vec3> = HashMap::new();
+    for (pawn_actor, _, tr) in pawn_query.iter() {
+        faction_to_velocity.insert(pawn_actor.faction, pawn_actor.velocity);
+        faction_to_position.insert(pawn_actor.faction, tr.translation);
+    }
+    for (mut actor, tr) in actor_query.iter_mut() {
+        if !actor.faction.is_neutral() {
+            if let Some(v) = faction_to_velocity.get(&actor.faction) {
+                // align to leader pawn's direction, add to acceleration, sliding along
+                // obstacles instead of into them
+                let position = tr.translation.truncate();
+                let margin = config.obstacle_margin;
+                let align = (*v - actor.velocity) * config.align_factor;
+                actor.accleration += obstacles
+                    .steer_around(position, align.truncate(), margin, 0.0)
+                    .extend(0.0);
+
+                // move to leader pawn's position, add to acceleration, and away from obstacles.
+                // Around them the flow field shows the way, as hard as the straight line would
+                let mut toward_pawn =
+                    *faction_to_position.get(&actor.faction).unwrap() - tr.translation;
+                if let Some(direction) = flow.direction(actor.faction, position) {
+                    toward_pawn = direction.extend(0.0) * toward_pawn.length();
+                }
+                let attract = toward_pawn * config.attract_factor;
+                actor.accleration += obstacles
+                    .steer_around(
+                        position,
+                        attract.truncate(),
+                        margin,
+                        config.repulsion_factor,
+                    )
+                    .extend(0.0);
+            }
+        }
+    }
+}
+
+// system: boids steering of faction crowds, every actor looks at the actors of its faction
+// around it: it separates from those too close, and moves to their center and along with them
+#[allow(clippy::too_many_arguments)]
+pub fn repulse_actor_system(
+    config: Res<GameConfig>,
+    spatial_query: Res<ActorSpace>,
+    obstacles: Res<Obstacles>,
+    task_pool: Res<ComputeTaskPool>,
+    mut stats: ResMut<TickStats>,
+    mut factions: Local<Vec<Faction>>,
+    mut velocities: Local<Vec<Vec3>>,
+    mut actor_set: Query<(Entity, &Transform, &mut Actor)>,
+) {
+    collect_factions(
+        &mut factions,
+        actor_set.iter().map(|(entity, _, actor)| (entity, actor)),
+    );
+    collect_velocities(
+        &mut velocities,
+        actor_set.iter().map(|(entity, _, actor)| (entity, actor)),
+    );
+    let factions = &factions;
+    let velocities = &velocities;
+    let config = &*config;
+    let spatial_query = &*spatial_query;
+    let obstacles = &*obstacles;
+    let radius = config.flock_radius.max(config.repulsion_threshold);
+    // every pair is seen from both of its actors
+    let pair_ends = AtomicUsize::new(0);
+    actor_set.par_for_each_mut(&task_pool, PAR_BATCH_SIZE, |(entity, tr, mut actor)| {
+        let position = tr.translation;
+        let mut separation = Vec3::ZERO;
+        let mut center = Vec3::ZERO;
+        let mut heading = Vec3::ZERO;
+        let mut flockmates = 0;
+        let mut too_close = 0;
+        // neighbor query include self
+        for (neighbor_pos, neighbor_entity) in spatial_query.within_distance(position, radius) {
+            if neighbor_entity == entity {
+                continue;
+            }
+            let neighbor_faction = faction_of(factions, neighbor_entity);
+            if neighbor_faction.is_neutral() || neighbor_faction != actor.faction {
+                continue; // we skip neighbor no faction actor, and other crowds
+            }
+            let distance = neighbor_pos.distance(position);
+            if distance < config.repulsion_threshold {
+                // actors put on the same spot of the edge have no direction to push in
+                separation += (position - neighbor_pos).normalize_or_zero();
+                too_close += 1;
+            }
+            if distance < config.flock_radius {
+                center += neighbor_pos;
+                heading += velocity_of(velocities, neighbor_entity);
+                flockmates += 1;
+            }
+        }
+        if too_close > 0 {
+            pair_ends.fetch_add(too_close, Ordering::Relaxed);
+        }
+        let mut steer = separation * config.repulsion_factor;
+        if flockmates > 0 {
+            let flockmates = flockmates as f32;
+            steer += (center / flockmates - position) * config.cohesion_factor;
+            steer += (heading / flockmates - actor.velocity) * config.flock_align_factor;
+        }
+        if steer == Vec3::ZERO {
+            return;
+        }
+        // never into an obstacle
+        let push = obstacles.steer_around(
+            position.truncate(),
+            steer.truncate(),
+            config.obstacle_margin,
+            0.0,
+        );
+        actor.accleration += push.extend(0.0);
+    });
+    stats.repulsion_pairs = pair_ends.into_inner() / 2;
+}
+
+// helper: every actor's faction by entity id, so systems running in parallel can look up
+// their neighbors' without a query
+pub(crate) fn collect_factions<'a>(
+    factions: &mut Vec<Faction>,
+    actors: impl Iterator<Item = (Entity, &'a Actor)>,
+) {
+    factions.clear();
+    for (entity, actor) in actors {
+        let index = entity.id() as usize;
+        if index >= factions.len() {
+            factions.resize(index + 1, Faction::Neutral);
+        }
+        factions[index] = actor.faction;
+    }
+}
+
+// helper: the faction collected for `entity`, neutral if it is no actor
+pub(crate) fn faction_of(factions: &[Faction], entity: Entity) -> Faction {
+    factions
+        .get(entity.id() as usize)
+        .copied()
+        .unwrap_or_default()
+}
+
+// helper: every actor's velocity by entity id, like `collect_factions`
+fn collect_velocities<'a>(
+    velocities: &mut Vec<Vec3>,
+    actors: impl Iterator<Item = (Entity, &'a Actor)>,
+) {
+    velocities.clear();
+    for (entity, actor) in actors {
+        let index = entity.id() as usize;
+        if index >= velocities.len() {
+            velocities.resize(index + 1, Vec3::ZERO);
+        }
+        velocities[index] = actor.velocity;
+    }
+}
+
+// helper: the velocity collected for `entity`, still if it is no actor
+fn velocity_of(velocities: &[Vec3], entity: Entity) -> Vec3 {
+    velocities
+        .get(entity.id() as usize)
+        .copied()
+        .unwrap_or_default()
+}
@@ -0,0 +1,90 @@
+//! Drives a [`SimEnv`] over stdin and stdout, one JSON object per line, so trainers in any
+//! language can run the simulation as a subprocess.
+//!
+//! ```text
+//! cargo run --release -p crowded_plaza_sim --bin crowded_plaza_env -- [--config game.config.ron]
+//!     [--grid_size 11] [--cell_size 1.0] [--ticks_per_step 4]
+//! ```
+//!
+//! It starts by writing the shapes of observations and actions, then answers every request line:
+//!
+//! ```text
+//! > {"spaces":{"observation":[3,11,11],"channels":["neutral","own","enemy"],"action":[2]}}
+//! < {"cmd":"reset","seed":42}
+//! > {"observation":{"grid":[...],"grid_size":11,"velocity":[0.8,-0.9],"own_count":1,"time_left":30.0}}
+//! < {"cmd":"step","action":[1.0,0.0]}
+//! > {"observation":{...},"reward":2.0,"done":false}
+//! < {"cmd":"close"}
+//! ```
+//!
+//! Malformed requests are answered with `{"error":"..."}`. Logs go to stderr.
+use crowded_plaza_sim::{EnvSettings, GameConfig, SimEnv, OBSERVATION_CHANNELS};
+use serde::Deserialize;
+use serde_json::json;
+use std::io::{self, BufRead, Write};
+
+#[derive(Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+enum Request {
+    Reset { seed: u64 },
+    Step { action: [f32; 2] },
+    Close,
+}
+
+// helper: the value of `--name value`, parsed
+fn flag<T: std::str::FromStr>(name: &str) -> Option<T> {
+    let flag = format!("--{}", name);
+    let value = std::env::args().skip_while(|arg| *arg != flag).nth(1)?;
+    match value.parse() {
+        Ok(value) => Some(value),
+        Err(_) => panic!("invalid value `{}` for {}", value, flag),
+    }
+}
+
+fn main() {
+    let config = match flag::<String>("config") {
+        Some(path) => {
+            let bytes = std::fs::read(&path).expect("cannot read config file");
+            GameConfig::from_ron(&bytes).unwrap_or_else(|e| panic!("{}: {}", path, e))
+        }
+        None => GameConfig::default(),
+    };
+    let defaults = EnvSettings::default();
+    let settings = EnvSettings {
+        grid_size: flag("grid_size").unwrap_or(defaults.grid_size),
+        cell_size: flag("cell_size").unwrap_or(defaults.cell_size),
+        ticks_per_step: flag("ticks_per_step").unwrap_or(defaults.ticks_per_step),
+    };
+    if let Err(e) = settings.validate() {
+        panic!("{}", e);
+    }
+
+    let grid_size = settings.grid_size;
+    let mut env = SimEnv::new(config, settings);
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let spaces = json!({
+        "spaces": {
+            "observation": [OBSERVATION_CHANNELS.len(), grid_size, grid_size],
+            "channels": OBSERVATION_CHANNELS,
+            "action": [2],
+        }
+    });
+    writeln!(out, "{}", spaces).unwrap();
+    out.flush().unwrap();
+
+    for line in io::stdin().lock().lines() {
+        let line = line.expect("cannot read stdin");
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(Request::Reset { seed }) => json!({ "observation": env.reset(seed) }),
+            Ok(Request::Step { action }) => json!(env.step(action)),
+            Ok(Request::Close) => break,
+            Err(e) => json!({ "error": e.to_string() }),
+        };
+        writeln!(out, "{}", response).unwrap();
+        out.flush().unwrap();
+    }
+}
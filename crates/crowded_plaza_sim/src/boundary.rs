@@ -0,0 +1,375 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::{config::check_range, ConfigError, GameConfig, TICK_SECONDS};
+
+// how far inside the edge wrapped actors come back in, so they aren't wrapped again right away
+const WRAP_INSET: f32 = 0.01;
+
+/// The edge of the plaza, centered on its origin. Sizes are in multiples of `action_region`,
+/// so the plaza grows with it, see [`GameConfig::boundary`] for the shape in units.
+///
+/// In a config file: `rectangle(half_width: 1.2, half_height: 0.8)`, `circle(radius: 1.2)`
+/// or `polygon([(-1.2, -1.0), (1.2, -1.0), (0.0, 1.5)])`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoundaryShape {
+    Rectangle {
+        half_width: f32,
+        half_height: f32,
+    },
+    Circle {
+        radius: f32,
+    },
+    /// Corners in order, either way round. Has to contain the origin.
+    Polygon(Vec<(f32, f32)>),
+}
+
+impl Default for BoundaryShape {
+    /// A square a little larger than the one actors spawn in.
+    fn default() -> Self {
+        BoundaryShape::Rectangle {
+            half_width: 1.25,
+            half_height: 1.25,
+        }
+    }
+}
+
+/// What keeps actors on the plaza.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoundaryBehavior {
+    /// Put back on the edge, heading away from it.
+    #[default]
+    Bounce,
+    /// Come back in on the opposite side.
+    Wrap,
+    /// Slowed down and pushed back in once closer to the edge than `boundary_margin`, with a
+    /// force growing to `boundary_force` on the edge. Actors may end up a little over the edge.
+    Steer,
+}
+
+impl FromStr for BoundaryBehavior {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bounce" => Ok(BoundaryBehavior::Bounce),
+            "wrap" => Ok(BoundaryBehavior::Wrap),
+            "steer" => Ok(BoundaryBehavior::Steer),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The closest point on the edge of a [`BoundaryShape`], or of an obstacle, to some position.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundaryEdge {
+    pub point: Vec2,
+    /// Unit normal at `point`, pointing out of the plaza or the obstacle.
+    pub normal: Vec2,
+    /// Distance from the position to the edge, negative outside.
+    pub depth: f32,
+}
+
+// helper: closest point to `p` on the segment from `a` to `b`
+fn closest_on_segment(p: Vec2, a: Vec2, b: Vec2) -> Vec2 {
+    let ab = b - a;
+    let t = ((p - a).dot(ab) / ab.length_squared().max(f32::EPSILON)).clamp(0.0, 1.0);
+    a + ab * t
+}
+
+// helper: whether `position` is inside the polygon, by the even-odd rule: a ray along +x
+// crosses its edges an odd number of times
+pub(crate) fn polygon_contains(corners: &[Vec2], position: Vec2) -> bool {
+    let mut inside = false;
+    for (i, a) in corners.iter().enumerate() {
+        let b = corners[(i + 1) % corners.len()];
+        if (a.y > position.y) != (b.y > position.y)
+            && position.x < a.x + (position.y - a.y) / (b.y - a.y) * (b.x - a.x)
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+// helper: the closest point on the edge of the polygon, corners in either order
+pub(crate) fn polygon_edge(corners: &[Vec2], position: Vec2) -> BoundaryEdge {
+    let inside = polygon_contains(corners, position);
+    // twice the signed area, positive for counter-clockwise corners
+    let winding: f32 = corners
+        .iter()
+        .enumerate()
+        .map(|(i, a)| a.perp_dot(corners[(i + 1) % corners.len()]))
+        .sum();
+    let mut closest = (f32::INFINITY, Vec2::ZERO, Vec2::X);
+    for (i, a) in corners.iter().enumerate() {
+        let b = corners[(i + 1) % corners.len()];
+        let point = closest_on_segment(position, *a, b);
+        let distance = point.distance_squared(position);
+        if distance < closest.0 {
+            let side = (b - *a).perp().normalize_or_zero();
+            let outward = if winding > 0.0 { -side } else { side };
+            closest = (distance, point, outward);
+        }
+    }
+    let (distance, point, side) = closest;
+    // outside a corner, away from the corner
+    let normal = if inside {
+        side
+    } else {
+        (position - point).try_normalize().unwrap_or(side)
+    };
+    BoundaryEdge {
+        point,
+        normal,
+        depth: if inside {
+            distance.sqrt()
+        } else {
+            -distance.sqrt()
+        },
+    }
+}
+
+impl BoundaryShape {
+    /// Every size times `scale`.
+    pub fn scaled(&self, scale: f32) -> Self {
+        match self {
+            BoundaryShape::Rectangle {
+                half_width,
+                half_height,
+            } => BoundaryShape::Rectangle {
+                half_width: half_width * scale,
+                half_height: half_height * scale,
+            },
+            BoundaryShape::Circle { radius } => BoundaryShape::Circle {
+                radius: radius * scale,
+            },
+            BoundaryShape::Polygon(corners) => BoundaryShape::Polygon(
+                corners
+                    .iter()
+                    .map(|(x, y)| (x * scale, y * scale))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn corners(&self) -> Vec<Vec2> {
+        match self {
+            BoundaryShape::Polygon(corners) => {
+                corners.iter().map(|(x, y)| Vec2::new(*x, *y)).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn contains(&self, position: Vec2) -> bool {
+        match self {
+            BoundaryShape::Rectangle {
+                half_width,
+                half_height,
+            } => position.x.abs() <= *half_width && position.y.abs() <= *half_height,
+            BoundaryShape::Circle { radius } => position.length_squared() <= radius * radius,
+            BoundaryShape::Polygon(_) => polygon_contains(&self.corners(), position),
+        }
+    }
+
+    /// The closest point on the edge to `position`, inside or out.
+    pub fn edge(&self, position: Vec2) -> BoundaryEdge {
+        let inside = self.contains(position);
+        let (point, normal) = match self {
+            BoundaryShape::Rectangle {
+                half_width,
+                half_height,
+            } => {
+                let extent = Vec2::new(*half_width, *half_height);
+                if inside {
+                    // onto the nearer side
+                    let gap = extent - position.abs();
+                    if gap.x < gap.y {
+                        let side = position.x.signum();
+                        (
+                            Vec2::new(side * half_width, position.y),
+                            Vec2::new(side, 0.0),
+                        )
+                    } else {
+                        let side = position.y.signum();
+                        (
+                            Vec2::new(position.x, side * half_height),
+                            Vec2::new(0.0, side),
+                        )
+                    }
+                } else {
+                    let point = position.clamp(-extent, extent);
+                    (point, (position - point).normalize_or_zero())
+                }
+            }
+            BoundaryShape::Circle { radius } => {
+                let normal = position.try_normalize().unwrap_or(Vec2::X);
+                (normal * *radius, normal)
+            }
+            BoundaryShape::Polygon(_) => return polygon_edge(&self.corners(), position),
+        };
+        let distance = point.distance(position);
+        BoundaryEdge {
+            point,
+            normal,
+            depth: if inside { distance } else { -distance },
+        }
+    }
+
+    /// Where an actor that left at `position` comes back in: on the opposite side of a
+    /// rectangle, like on a torus, or through the origin on the far edge of other shapes.
+    pub fn wrap(&self, position: Vec2) -> Vec2 {
+        match self {
+            BoundaryShape::Rectangle {
+                half_width,
+                half_height,
+            } => Vec2::new(
+                (position.x + half_width).rem_euclid(2.0 * half_width) - half_width,
+                (position.y + half_height).rem_euclid(2.0 * half_height) - half_height,
+            ),
+            BoundaryShape::Circle { radius } => {
+                -position.try_normalize().unwrap_or(Vec2::X) * (radius - WRAP_INSET)
+            }
+            BoundaryShape::Polygon(_) => {
+                // the nearest edge the ray from the origin away from `position` hits
+                let corners = self.corners();
+                let direction = -position.try_normalize().unwrap_or(Vec2::X);
+                let mut nearest = f32::INFINITY;
+                for (i, a) in corners.iter().enumerate() {
+                    let edge = corners[(i + 1) % corners.len()] - *a;
+                    let denominator = direction.perp_dot(edge);
+                    if denominator.abs() < f32::EPSILON {
+                        continue;
+                    }
+                    let t = a.perp_dot(edge) / denominator;
+                    let s = a.perp_dot(direction) / denominator;
+                    if t >= 0.0 && (0.0..=1.0).contains(&s) {
+                        nearest = nearest.min(t);
+                    }
+                }
+                direction * (nearest - WRAP_INSET).max(0.0)
+            }
+        }
+    }
+
+    /// Points along the edge in order, a circle as a polygon of `segments` corners.
+    pub fn outline(&self, segments: usize) -> Vec<Vec2> {
+        match self {
+            BoundaryShape::Rectangle {
+                half_width,
+                half_height,
+            } => vec![
+                Vec2::new(-half_width, -half_height),
+                Vec2::new(*half_width, -half_height),
+                Vec2::new(*half_width, *half_height),
+                Vec2::new(-half_width, *half_height),
+            ],
+            BoundaryShape::Circle { radius } => (0..segments)
+                .map(|i| {
+                    let (sin, cos) = (i as f32 / segments as f32 * std::f32::consts::TAU).sin_cos();
+                    Vec2::new(cos, sin) * *radius
+                })
+                .collect(),
+            BoundaryShape::Polygon(_) => self.corners(),
+        }
+    }
+
+    /// Half the width and height of the smallest rectangle around the shape.
+    pub fn half_extents(&self) -> Vec2 {
+        match self {
+            BoundaryShape::Rectangle {
+                half_width,
+                half_height,
+            } => Vec2::new(*half_width, *half_height),
+            BoundaryShape::Circle { radius } => Vec2::splat(*radius),
+            BoundaryShape::Polygon(_) => self
+                .corners()
+                .iter()
+                .fold(Vec2::ZERO, |extent, corner| extent.max(corner.abs())),
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), ConfigError> {
+        // snapshots are exact within twice the action region
+        let expected = "sizes between 0.05 and 2 times `action_region` around the origin";
+        match self {
+            BoundaryShape::Rectangle {
+                half_width,
+                half_height,
+            } => {
+                check_range("boundary_shape", *half_width, 0.05, 2.0, expected)?;
+                check_range("boundary_shape", *half_height, 0.05, 2.0, expected)
+            }
+            BoundaryShape::Circle { radius } => {
+                check_range("boundary_shape", *radius, 0.05, 2.0, expected)
+            }
+            BoundaryShape::Polygon(corners) => {
+                let extent = self.half_extents();
+                if corners.len() < 3
+                    || corners
+                        .iter()
+                        .any(|(x, y)| !x.is_finite() || !y.is_finite())
+                    || !self.contains(Vec2::ZERO)
+                {
+                    return Err(ConfigError::OutOfRange {
+                        field: "boundary_shape",
+                        value: format!("{:?}", self),
+                        expected: "a polygon of at least 3 corners around the origin",
+                    });
+                }
+                check_range("boundary_shape", extent.x, 0.05, 2.0, expected)?;
+                check_range("boundary_shape", extent.y, 0.05, 2.0, expected)
+            }
+        }
+    }
+}
+
+// helper: keep an actor that just moved on the plaza, returns how far a wrap moved it
+pub(crate) fn apply_boundary(
+    config: &GameConfig,
+    boundary: &BoundaryShape,
+    translation: &mut Vec3,
+    velocity: &mut Vec3,
+) -> Vec3 {
+    let position = translation.truncate();
+    match config.boundary_behavior {
+        BoundaryBehavior::Bounce => {
+            let edge = boundary.edge(position);
+            if edge.depth < 0.0 {
+                *translation = edge.point.extend(translation.z);
+                let outward = velocity.truncate().dot(edge.normal);
+                if outward > 0.0 {
+                    *velocity -= (edge.normal * 2.0 * outward).extend(0.0);
+                }
+            }
+            Vec3::ZERO
+        }
+        BoundaryBehavior::Wrap => {
+            if boundary.contains(position) {
+                return Vec3::ZERO;
+            }
+            let jump = (boundary.wrap(position) - position).extend(0.0);
+            *translation += jump;
+            jump
+        }
+        BoundaryBehavior::Steer => {
+            let edge = boundary.edge(position);
+            if edge.depth < config.boundary_margin {
+                let push = (config.boundary_margin - edge.depth) / config.boundary_margin;
+                // heading out slows down, not at all at the margin and to a stop on the edge,
+                // then the push turns it around
+                let outward = velocity.truncate().dot(edge.normal).max(0.0);
+                let change = -edge.normal
+                    * (outward * push.min(1.0) + push * config.boundary_force * TICK_SECONDS);
+                *velocity += change.extend(0.0);
+                // this tick's move was made with the old velocity
+                *translation += (change * TICK_SECONDS).extend(0.0);
+            }
+            Vec3::ZERO
+        }
+    }
+}
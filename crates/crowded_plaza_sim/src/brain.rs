@@ -0,0 +1,399 @@
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, str::FromStr};
+
+use crate::{Actor, ActorSpace, Faction, FactionRegistry, GameConfig, Pawn, SafeZone, SimRng};
+
+// how close to the edge of the safe zone bots turn back to its center, in units
+const ZONE_MARGIN: f32 = 1.5;
+
+/// How well bots see and how quickly they react.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    /// How far a bot's pawn perceives other actors, in units.
+    pub fn sight(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 4.0,
+            Difficulty::Normal => 6.0,
+            Difficulty::Hard => 9.0,
+        }
+    }
+
+    /// Ticks between two decisions.
+    pub fn reaction_ticks(&self) -> u32 {
+        match self {
+            Difficulty::Easy => 30,
+            Difficulty::Normal => 15,
+            Difficulty::Hard => 5,
+        }
+    }
+
+    /// Largest random turn added to a decision, in radians.
+    pub fn jitter(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.8,
+            Difficulty::Normal => 0.3,
+            Difficulty::Hard => 0.0,
+        }
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "easy" => Ok(Difficulty::Easy),
+            "normal" => Ok(Difficulty::Normal),
+            "hard" => Ok(Difficulty::Hard),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The built-in [`OpponentBrain`]s, as picked in the [`GameConfig`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// Random walk, how bots used to play.
+    Wander,
+    /// Head for the densest patch of neutrals in sight.
+    SeekNeutral,
+    /// Run from leaders of larger factions, otherwise seek neutrals.
+    FleeLarger,
+    /// Chase the nearest leader of a smaller faction, otherwise seek neutrals.
+    HuntSmaller,
+    /// Stay with the own crowd and go after enemies coming close to it.
+    DefendCrowd,
+}
+
+impl Strategy {
+    pub fn brain(&self, config: &GameConfig) -> Box<dyn OpponentBrain> {
+        match self {
+            Strategy::Wander => Box::new(WanderBrain {
+                move_scale: config.opponent_move_scale,
+            }),
+            Strategy::SeekNeutral => Box::new(SeekNeutralBrain),
+            Strategy::FleeLarger => Box::new(FleeLargerBrain),
+            Strategy::HuntSmaller => Box::new(HuntSmallerBrain),
+            Strategy::DefendCrowd => Box::new(DefendCrowdBrain),
+        }
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wander" => Ok(Strategy::Wander),
+            "seek_neutral" => Ok(Strategy::SeekNeutral),
+            "flee_larger" => Ok(Strategy::FleeLarger),
+            "hunt_smaller" => Ok(Strategy::HuntSmaller),
+            "defend_crowd" => Ok(Strategy::DefendCrowd),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Another actor a bot's pawn can see.
+#[derive(Clone, Copy, Debug)]
+pub struct Sighting {
+    pub position: Vec3,
+    pub faction: Faction,
+    /// Whether it is the pawn leading `faction`.
+    pub leader: bool,
+    /// Live actors in `faction`.
+    pub faction_count: i32,
+}
+
+/// What a bot's pawn knows when it decides where to go.
+pub struct Perception<'a> {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub faction: Faction,
+    /// Live actors in the bot's faction, its pawn included.
+    pub own_count: i32,
+    /// Every other actor within [`Difficulty::sight`], found with the kd-tree.
+    pub sightings: &'a [Sighting],
+}
+
+/// Steers a bot's pawn. Brains only see what is in their [`Perception`] and must draw any
+/// randomness from the given [`SimRng`], so matches stay reproducible from their seed.
+pub trait OpponentBrain: Send + Sync {
+    /// The direction to head in, `None` keeps the current heading.
+    fn decide(&mut self, perception: &Perception, rng: &mut SimRng) -> Option<Vec2>;
+
+    /// Ticks between two decisions.
+    fn reaction_ticks(&self, difficulty: Difficulty) -> u32 {
+        difficulty.reaction_ticks()
+    }
+}
+
+/// Steers an opponent pawn with an [`OpponentBrain`].
+#[derive(Component)]
+pub struct OpponentController {
+    pub brain: Box<dyn OpponentBrain>,
+    pub difficulty: Difficulty,
+    next_decision: u32,
+}
+
+impl OpponentController {
+    pub fn new(brain: Box<dyn OpponentBrain>, difficulty: Difficulty) -> Self {
+        OpponentController {
+            brain,
+            difficulty,
+            next_decision: 0,
+        }
+    }
+}
+
+// helper: a random new heading every few decisions, for brains with nothing better to do
+fn wander(perception: &Perception, rng: &mut SimRng) -> Option<Vec2> {
+    if rng.gen_range(0.0..1.0) < 0.2 {
+        let random_direction = Vec2::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+        Some(perception.velocity.truncate().normalize_or_zero() + random_direction)
+    } else {
+        None
+    }
+}
+
+// helper: center of the patch of ground with the most neutrals in sight
+fn densest_neutral_patch(perception: &Perception) -> Option<Vec3> {
+    const PATCH_SIZE: f32 = 2.0;
+    // ordered map, so ties are broken the same way in every run
+    let mut patches: BTreeMap<(i32, i32), (i32, Vec3)> = BTreeMap::new();
+    for sighting in perception.sightings.iter() {
+        if !sighting.faction.is_neutral() {
+            continue;
+        }
+        let key = (
+            (sighting.position.x / PATCH_SIZE).floor() as i32,
+            (sighting.position.y / PATCH_SIZE).floor() as i32,
+        );
+        let patch = patches.entry(key).or_insert((0, Vec3::ZERO));
+        patch.0 += 1;
+        patch.1 += sighting.position;
+    }
+    patches
+        .values()
+        .max_by_key(|(count, _)| *count)
+        .map(|(count, sum)| *sum / *count as f32)
+}
+
+fn seek_neutral(perception: &Perception, rng: &mut SimRng) -> Option<Vec2> {
+    match densest_neutral_patch(perception) {
+        Some(target) => Some((target - perception.position).truncate()),
+        None => wander(perception, rng),
+    }
+}
+
+/// The random walk bots used before they had brains.
+pub struct WanderBrain {
+    pub move_scale: f32,
+}
+
+impl OpponentBrain for WanderBrain {
+    fn decide(&mut self, perception: &Perception, rng: &mut SimRng) -> Option<Vec2> {
+        if rng.gen_range(0.0..1.0) < 0.01 {
+            let random_direction = Vec2::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+            Some(perception.velocity.truncate() + random_direction.normalize() * self.move_scale)
+        } else {
+            None
+        }
+    }
+
+    fn reaction_ticks(&self, _difficulty: Difficulty) -> u32 {
+        1
+    }
+}
+
+pub struct SeekNeutralBrain;
+
+impl OpponentBrain for SeekNeutralBrain {
+    fn decide(&mut self, perception: &Perception, rng: &mut SimRng) -> Option<Vec2> {
+        seek_neutral(perception, rng)
+    }
+}
+
+pub struct FleeLargerBrain;
+
+impl OpponentBrain for FleeLargerBrain {
+    fn decide(&mut self, perception: &Perception, rng: &mut SimRng) -> Option<Vec2> {
+        // away from every larger leader, the closer the stronger
+        let mut away = Vec2::ZERO;
+        for sighting in perception.sightings.iter() {
+            if sighting.leader
+                && sighting.faction != perception.faction
+                && sighting.faction_count > perception.own_count
+            {
+                let offset = (perception.position - sighting.position).truncate();
+                away += offset.normalize_or_zero() / offset.length().max(0.1);
+            }
+        }
+        if away != Vec2::ZERO {
+            Some(away)
+        } else {
+            seek_neutral(perception, rng)
+        }
+    }
+}
+
+pub struct HuntSmallerBrain;
+
+impl OpponentBrain for HuntSmallerBrain {
+    fn decide(&mut self, perception: &Perception, rng: &mut SimRng) -> Option<Vec2> {
+        let prey = perception
+            .sightings
+            .iter()
+            .filter(|sighting| {
+                sighting.leader
+                    && sighting.faction != perception.faction
+                    && sighting.faction_count < perception.own_count
+            })
+            .min_by(|a, b| {
+                let distance_a = a.position.distance_squared(perception.position);
+                let distance_b = b.position.distance_squared(perception.position);
+                distance_a.total_cmp(&distance_b)
+            });
+        match prey {
+            Some(prey) => Some((prey.position - perception.position).truncate()),
+            None => seek_neutral(perception, rng),
+        }
+    }
+}
+
+pub struct DefendCrowdBrain;
+
+impl OpponentBrain for DefendCrowdBrain {
+    fn decide(&mut self, perception: &Perception, rng: &mut SimRng) -> Option<Vec2> {
+        const CROWD_RADIUS: f32 = 2.0;
+        let followers: Vec<Vec3> = perception
+            .sightings
+            .iter()
+            .filter(|sighting| sighting.faction == perception.faction)
+            .map(|sighting| sighting.position)
+            .collect();
+        if followers.is_empty() {
+            return seek_neutral(perception, rng);
+        }
+        let crowd = followers.iter().sum::<Vec3>() / followers.len() as f32;
+        if crowd.distance(perception.position) > CROWD_RADIUS {
+            return Some((crowd - perception.position).truncate());
+        }
+
+        // intercept the enemy closest to the crowd
+        let intruder = perception
+            .sightings
+            .iter()
+            .filter(|sighting| {
+                !sighting.faction.is_neutral()
+                    && sighting.faction != perception.faction
+                    && sighting.position.distance(crowd) < CROWD_RADIUS * 2.0
+            })
+            .min_by(|a, b| {
+                let distance_a = a.position.distance_squared(crowd);
+                let distance_b = b.position.distance_squared(crowd);
+                distance_a.total_cmp(&distance_b)
+            });
+        match intruder {
+            Some(intruder) => Some((intruder.position - perception.position).truncate()),
+            None => seek_neutral(perception, rng),
+        }
+    }
+}
+
+// system: let every opponent's brain steer its pawn, back into the safe zone first
+pub fn change_direction_opponent_system(
+    config: Res<GameConfig>,
+    registry: Res<FactionRegistry>,
+    zone: Option<Res<SafeZone>>,
+    spatial_query: Res<ActorSpace>,
+    mut rng: ResMut<SimRng>,
+    mut opponent_query: Query<(Entity, &mut OpponentController)>,
+    mut actor_query: Query<(&Transform, &mut Actor, Option<&Pawn>)>,
+) {
+    let mut sightings: Vec<Sighting> = Vec::new();
+    let mut decisions: Vec<(Entity, Vec2)> = Vec::new();
+    for (entity, mut controller) in opponent_query.iter_mut() {
+        let (tr, actor, pawn) = match actor_query.get(entity) {
+            Ok(found) => found,
+            Err(_) => continue,
+        };
+        if pawn.is_none() {
+            continue; // eliminated, it is a follower of another faction now
+        }
+        if let Some(zone) = zone.as_deref() {
+            let margin = ZONE_MARGIN.min(zone.radius * 0.25);
+            let offset = tr.translation.truncate();
+            if offset.length() > zone.radius - margin {
+                if offset.length_squared() > 0.0 {
+                    decisions.push((entity, -offset));
+                }
+                continue;
+            }
+        }
+        if controller.next_decision > 0 {
+            controller.next_decision -= 1;
+            continue;
+        }
+
+        sightings.clear();
+        for (position, neighbor_entity) in
+            spatial_query.within_distance(tr.translation, controller.difficulty.sight())
+        {
+            if neighbor_entity == entity {
+                continue;
+            }
+            if let Ok((_, neighbor_actor, neighbor_pawn)) = actor_query.get(neighbor_entity) {
+                sightings.push(Sighting {
+                    position,
+                    faction: neighbor_actor.faction,
+                    leader: neighbor_pawn.is_some(),
+                    faction_count: registry.count(neighbor_actor.faction),
+                });
+            }
+        }
+        let perception = Perception {
+            position: tr.translation,
+            velocity: actor.velocity,
+            faction: actor.faction,
+            own_count: registry.count(actor.faction),
+            sightings: &sightings,
+        };
+
+        let difficulty = controller.difficulty;
+        let decision = controller.brain.decide(&perception, &mut rng);
+        controller.next_decision = controller.brain.reaction_ticks(difficulty).max(1) - 1;
+        if let Some(direction) = decision {
+            let jitter = difficulty.jitter();
+            let turn = if jitter > 0.0 {
+                rng.gen_range(-jitter..jitter)
+            } else {
+                0.0
+            };
+            let (sin, cos) = turn.sin_cos();
+            let direction = Vec2::new(
+                direction.x * cos - direction.y * sin,
+                direction.x * sin + direction.y * cos,
+            );
+            if direction.length_squared() > 0.0 {
+                decisions.push((entity, direction));
+            }
+        }
+    }
+
+    for (entity, direction) in decisions {
+        if let Ok((_, mut actor, _)) = actor_query.get_mut(entity) {
+            actor.velocity = direction.normalize().extend(0.0) * config.pawn_speed;
+        }
+    }
+}
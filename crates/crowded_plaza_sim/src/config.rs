@@ -0,0 +1,620 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadState, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+use crate::{
+    BoundaryBehavior, BoundaryShape, Difficulty, GameMode, PlazaMap, PlazaMapLoader,
+    SpatialBackend, Strategy,
+};
+
+pub const MAX_OPPONENT_COUNT: i32 = 100;
+pub const MAX_PLAYER_COUNT: i32 = 32;
+
+/// Tuning of a match. Speeds are in units per second, accelerations in units per second squared.
+///
+/// Loaded from a RON file by [`GameConfigPlugin`]; fields missing from the file keep their
+/// default. Changes to the speeds and factors apply immediately, counts and sizes apply when
+/// the next match is set up.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, TypeUuid)]
+#[uuid = "1a7c16df-37ca-4cd1-bc9e-b3b75a287c9a"]
+#[serde(default, deny_unknown_fields)]
+pub struct GameConfig {
+    pub pawn_speed: f32,
+    pub wander_speed: f32,
+    pub opponent_move_scale: f32,
+    pub neighbor_threshold: f32,
+    /// How actors find their neighbors.
+    pub spatial_index: SpatialBackend,
+    /// Actors of a crowd closer than this push apart, just as hard at any distance below it.
+    pub repulsion_threshold: f32,
+    /// How hard crowds separate, see `repulsion_threshold`.
+    pub repulsion_factor: f32,
+    /// Alignment to the leader pawn's velocity.
+    pub align_factor: f32,
+    /// Attraction to the leader pawn's position.
+    pub attract_factor: f32,
+    /// Actors of a crowd within this distance flock together, see `cohesion_factor` and
+    /// `flock_align_factor`.
+    pub flock_radius: f32,
+    /// Attraction to the center of the actors of the crowd around.
+    pub cohesion_factor: f32,
+    /// Alignment to the average velocity of the actors of the crowd around.
+    pub flock_align_factor: f32,
+    pub actor_count: i32,
+    /// Seats for human players, more than one only makes sense on a server.
+    pub player_count: i32,
+    pub opponent_count: i32,
+    pub opponent_difficulty: Difficulty,
+    /// Handed out to the bots in turn.
+    pub opponent_strategies: Vec<Strategy>,
+    pub action_region: f32,
+    /// The edge of the plaza, in multiples of `action_region`. Actors spawn within
+    /// `action_region` whatever it is.
+    pub boundary_shape: BoundaryShape,
+    pub boundary_behavior: BoundaryBehavior,
+    /// How close to the edge [`BoundaryBehavior::Steer`] starts pushing, in units.
+    pub boundary_margin: f32,
+    /// How hard [`BoundaryBehavior::Steer`] pushes on the edge.
+    pub boundary_force: f32,
+    /// Obstacles and spawn zones. Not a field to override, the game client loads it from its
+    /// own file.
+    pub map: PlazaMap,
+    /// How close to an obstacle followers start steering around it, in units. They are pushed
+    /// away as hard as `repulsion_factor` on its edge.
+    pub obstacle_margin: f32,
+    /// Size of the cells followers find their way around obstacles on, in units. Larger plazas
+    /// get larger cells, up to 256 along a side.
+    pub flow_cell_size: f32,
+    pub game_time: f32,
+    pub mode: GameMode,
+    /// Radius the [`SafeZone`](crate::SafeZone) of a battle royale shrinks to by the end of the
+    /// match.
+    pub zone_end_radius: f32,
+    /// hex colors, only used by the game client
+    pub clear_color: String,
+    pub light_color: String,
+    pub ambient_color: String,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            pawn_speed: 1.2,
+            wander_speed: 0.3,
+            opponent_move_scale: 1.0,
+            neighbor_threshold: 0.5,
+            spatial_index: SpatialBackend::KdTree,
+            repulsion_threshold: 0.2,
+            repulsion_factor: 3.6,
+            align_factor: 0.6,
+            attract_factor: 1.08,
+            flock_radius: 0.6,
+            cohesion_factor: 0.5,
+            flock_align_factor: 0.3,
+            actor_count: 2000,
+            player_count: 1,
+            opponent_count: 5,
+            opponent_difficulty: Difficulty::Normal,
+            opponent_strategies: vec![
+                Strategy::SeekNeutral,
+                Strategy::FleeLarger,
+                Strategy::HuntSmaller,
+                Strategy::DefendCrowd,
+            ],
+            action_region: 20.0,
+            boundary_shape: BoundaryShape::default(),
+            boundary_behavior: BoundaryBehavior::Bounce,
+            boundary_margin: 2.0,
+            boundary_force: 6.0,
+            map: PlazaMap::default(),
+            obstacle_margin: 1.0,
+            flow_cell_size: 1.0,
+            game_time: 30.0,
+            mode: GameMode::Classic,
+            zone_end_radius: 3.0,
+            clear_color: "1a1a1a".to_string(),
+            light_color: "fdfff4".to_string(),
+            ambient_color: "d7deff".to_string(),
+        }
+    }
+}
+
+/// Why a config was rejected.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigError {
+    /// The file is not valid RON for a [`GameConfig`].
+    Parse(String),
+    /// An override named a field that does not exist.
+    UnknownField(String),
+    /// A value could not be parsed as the field's type.
+    InvalidValue { field: String, value: String },
+    /// A value parsed fine but is outside what the game can handle.
+    OutOfRange {
+        field: &'static str,
+        value: String,
+        expected: &'static str,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Parse(message) => write!(f, "invalid game config: {}", message),
+            ConfigError::UnknownField(field) => write!(f, "unknown config field `{}`", field),
+            ConfigError::InvalidValue { field, value } => {
+                write!(f, "`{}` is not a valid value for `{}`", value, field)
+            }
+            ConfigError::OutOfRange {
+                field,
+                value,
+                expected,
+            } => write!(f, "`{}` is {}, expected {}", field, value, expected),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+pub(crate) fn check_range<T: PartialOrd + fmt::Display>(
+    field: &'static str,
+    value: T,
+    min: T,
+    max: T,
+    expected: &'static str,
+) -> Result<(), ConfigError> {
+    // written so that NaN fails too
+    if value >= min && value <= max {
+        Ok(())
+    } else {
+        Err(ConfigError::OutOfRange {
+            field,
+            value: value.to_string(),
+            expected,
+        })
+    }
+}
+
+fn check_color(field: &'static str, value: &str) -> Result<(), ConfigError> {
+    if (value.len() == 6 || value.len() == 8) && value.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(())
+    } else {
+        Err(ConfigError::OutOfRange {
+            field,
+            value: value.to_string(),
+            expected: "a hex color like `fdfff4`",
+        })
+    }
+}
+
+fn parse_field<T: FromStr>(field: &str, value: &str) -> Result<T, ConfigError> {
+    value.parse().map_err(|_| ConfigError::InvalidValue {
+        field: field.to_string(),
+        value: value.to_string(),
+    })
+}
+
+impl GameConfig {
+    /// Names accepted by [`GameConfig::set_field`].
+    pub const FIELDS: &'static [&'static str] = &[
+        "pawn_speed",
+        "wander_speed",
+        "opponent_move_scale",
+        "neighbor_threshold",
+        "spatial_index",
+        "repulsion_threshold",
+        "repulsion_factor",
+        "align_factor",
+        "attract_factor",
+        "flock_radius",
+        "cohesion_factor",
+        "flock_align_factor",
+        "actor_count",
+        "player_count",
+        "opponent_count",
+        "opponent_difficulty",
+        "opponent_strategies",
+        "action_region",
+        "boundary_shape",
+        "boundary_behavior",
+        "boundary_margin",
+        "boundary_force",
+        "obstacle_margin",
+        "flow_cell_size",
+        "game_time",
+        "mode",
+        "zone_end_radius",
+        "clear_color",
+        "light_color",
+        "ambient_color",
+    ];
+
+    /// The edge of the plaza in units.
+    pub fn boundary(&self) -> BoundaryShape {
+        self.boundary_shape.scaled(self.action_region)
+    }
+
+    /// Parse and validate a config from RON.
+    pub fn from_ron(bytes: &[u8]) -> Result<Self, ConfigError> {
+        let config: GameConfig =
+            ron::de::from_bytes(bytes).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check every value is within what the simulation can handle.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        check_range(
+            "pawn_speed",
+            self.pawn_speed,
+            0.01,
+            100.0,
+            "between 0.01 and 100",
+        )?;
+        check_range(
+            "wander_speed",
+            self.wander_speed,
+            0.0,
+            self.pawn_speed,
+            "between 0 and `pawn_speed`",
+        )?;
+        check_range(
+            "opponent_move_scale",
+            self.opponent_move_scale,
+            0.01,
+            100.0,
+            "between 0.01 and 100",
+        )?;
+        check_range(
+            "neighbor_threshold",
+            self.neighbor_threshold,
+            0.01,
+            10.0,
+            "between 0.01 and 10",
+        )?;
+        check_range(
+            "repulsion_threshold",
+            self.repulsion_threshold,
+            0.0,
+            10.0,
+            "between 0 and 10",
+        )?;
+        check_range(
+            "repulsion_factor",
+            self.repulsion_factor,
+            0.0,
+            1000.0,
+            "between 0 and 1000",
+        )?;
+        check_range(
+            "align_factor",
+            self.align_factor,
+            0.0,
+            60.0,
+            "between 0 and 60",
+        )?;
+        check_range(
+            "attract_factor",
+            self.attract_factor,
+            0.0,
+            1000.0,
+            "between 0 and 1000",
+        )?;
+        check_range(
+            "flock_radius",
+            self.flock_radius,
+            0.0,
+            10.0,
+            "between 0 and 10",
+        )?;
+        check_range(
+            "cohesion_factor",
+            self.cohesion_factor,
+            0.0,
+            1000.0,
+            "between 0 and 1000",
+        )?;
+        check_range(
+            "flock_align_factor",
+            self.flock_align_factor,
+            0.0,
+            60.0,
+            "between 0 and 60",
+        )?;
+        check_range(
+            "actor_count",
+            self.actor_count,
+            0,
+            1_000_000,
+            "between 0 and 1000000",
+        )?;
+        check_range(
+            "player_count",
+            self.player_count,
+            1,
+            MAX_PLAYER_COUNT,
+            "between 1 and 32",
+        )?;
+        check_range(
+            "opponent_count",
+            self.opponent_count,
+            0,
+            MAX_OPPONENT_COUNT,
+            "between 0 and 100",
+        )?;
+        check_range(
+            "action_region",
+            self.action_region,
+            1.0,
+            1000.0,
+            "between 1 and 1000",
+        )?;
+        self.boundary_shape.validate()?;
+        check_range(
+            "boundary_margin",
+            self.boundary_margin,
+            0.01,
+            100.0,
+            "between 0.01 and 100",
+        )?;
+        check_range(
+            "boundary_force",
+            self.boundary_force,
+            0.0,
+            1000.0,
+            "between 0 and 1000",
+        )?;
+        self.map.validate()?;
+        check_range(
+            "obstacle_margin",
+            self.obstacle_margin,
+            0.01,
+            10.0,
+            "between 0.01 and 10",
+        )?;
+        check_range(
+            "flow_cell_size",
+            self.flow_cell_size,
+            0.25,
+            10.0,
+            "between 0.25 and 10",
+        )?;
+        check_range(
+            "game_time",
+            self.game_time,
+            1.0,
+            3600.0,
+            "between 1 and 3600 seconds",
+        )?;
+        check_range(
+            "zone_end_radius",
+            self.zone_end_radius,
+            0.5,
+            1000.0,
+            "between 0.5 and 1000",
+        )?;
+        check_color("clear_color", &self.clear_color)?;
+        check_color("light_color", &self.light_color)?;
+        check_color("ambient_color", &self.ambient_color)?;
+        Ok(())
+    }
+
+    /// Set a single field from its text form, as given on the command line or in a url query.
+    /// The result is not validated.
+    pub fn set_field(&mut self, field: &str, value: &str) -> Result<(), ConfigError> {
+        match field {
+            "pawn_speed" => self.pawn_speed = parse_field(field, value)?,
+            "wander_speed" => self.wander_speed = parse_field(field, value)?,
+            "opponent_move_scale" => self.opponent_move_scale = parse_field(field, value)?,
+            "neighbor_threshold" => self.neighbor_threshold = parse_field(field, value)?,
+            "spatial_index" => self.spatial_index = parse_field(field, value)?,
+            "repulsion_threshold" => self.repulsion_threshold = parse_field(field, value)?,
+            "repulsion_factor" => self.repulsion_factor = parse_field(field, value)?,
+            "align_factor" => self.align_factor = parse_field(field, value)?,
+            "attract_factor" => self.attract_factor = parse_field(field, value)?,
+            "flock_radius" => self.flock_radius = parse_field(field, value)?,
+            "cohesion_factor" => self.cohesion_factor = parse_field(field, value)?,
+            "flock_align_factor" => self.flock_align_factor = parse_field(field, value)?,
+            "actor_count" => self.actor_count = parse_field(field, value)?,
+            "player_count" => self.player_count = parse_field(field, value)?,
+            "opponent_count" => self.opponent_count = parse_field(field, value)?,
+            "opponent_difficulty" => self.opponent_difficulty = parse_field(field, value)?,
+            // comma separated, e.g. `seek_neutral,hunt_smaller`
+            "opponent_strategies" => {
+                self.opponent_strategies = value
+                    .split(',')
+                    .filter(|strategy| !strategy.is_empty())
+                    .map(|strategy| parse_field(field, strategy.trim()))
+                    .collect::<Result<_, _>>()?
+            }
+            "action_region" => self.action_region = parse_field(field, value)?,
+            // as in the config file, e.g. `circle(radius: 40.0)`
+            "boundary_shape" => {
+                self.boundary_shape =
+                    ron::from_str(value).map_err(|_| ConfigError::InvalidValue {
+                        field: field.to_string(),
+                        value: value.to_string(),
+                    })?
+            }
+            "boundary_behavior" => self.boundary_behavior = parse_field(field, value)?,
+            "boundary_margin" => self.boundary_margin = parse_field(field, value)?,
+            "boundary_force" => self.boundary_force = parse_field(field, value)?,
+            "obstacle_margin" => self.obstacle_margin = parse_field(field, value)?,
+            "flow_cell_size" => self.flow_cell_size = parse_field(field, value)?,
+            "game_time" => self.game_time = parse_field(field, value)?,
+            "mode" => self.mode = parse_field(field, value)?,
+            "zone_end_radius" => self.zone_end_radius = parse_field(field, value)?,
+            "clear_color" => self.clear_color = value.to_string(),
+            "light_color" => self.light_color = value.to_string(),
+            "ambient_color" => self.ambient_color = value.to_string(),
+            _ => return Err(ConfigError::UnknownField(field.to_string())),
+        }
+        Ok(())
+    }
+
+    /// Apply `overrides` one by one, skipping and logging those that would make the config invalid.
+    pub fn apply_overrides(&mut self, overrides: &GameConfigOverrides) {
+        for (field, value) in overrides.0.iter() {
+            let mut overridden = self.clone();
+            match overridden
+                .set_field(field, value)
+                .and_then(|_| overridden.validate())
+            {
+                Ok(_) => *self = overridden,
+                Err(e) => warn!("Ignoring config override {}={}: {}", field, value, e),
+            }
+        }
+    }
+}
+
+/// `(field, value)` pairs applied on top of the loaded config, e.g. from url query params.
+#[derive(Clone, Debug, Default)]
+pub struct GameConfigOverrides(pub Vec<(String, String)>);
+
+/// The config file loaded by [`GameConfigPlugin`].
+pub struct GameConfigHandle {
+    pub handle: Handle<GameConfig>,
+    /// The map file, if one was given.
+    pub map: Option<Handle<PlazaMap>>,
+    /// Whether the files' values made it into the [`GameConfig`] resource, or they failed to
+    /// load and the defaults are used. Wait for this before setting up the first match.
+    pub ready: bool,
+}
+
+#[derive(Default)]
+pub struct GameConfigLoader;
+
+impl AssetLoader for GameConfigLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let config = GameConfig::from_ron(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(config));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["config.ron"]
+    }
+}
+
+/// Loads the [`GameConfig`] resource from a `.config.ron` asset and keeps it up to date when the
+/// file is hot-reloaded. Needs bevy's `AssetPlugin`; headless drivers without it can insert a
+/// [`GameConfig`] resource directly instead.
+pub struct GameConfigPlugin {
+    /// Path relative to the assets folder.
+    pub path: String,
+    /// A `.map.ron` file replacing the config's map, relative to the assets folder.
+    pub map: Option<String>,
+}
+
+impl Default for GameConfigPlugin {
+    fn default() -> Self {
+        GameConfigPlugin {
+            path: "config/game.config.ron".to_string(),
+            map: None,
+        }
+    }
+}
+
+impl Plugin for GameConfigPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<GameConfig>()
+            .add_asset::<PlazaMap>()
+            .init_asset_loader::<GameConfigLoader>()
+            .init_asset_loader::<PlazaMapLoader>()
+            .init_resource::<GameConfig>()
+            .init_resource::<GameConfigOverrides>()
+            .add_system_to_stage(CoreStage::PreUpdate, apply_game_config_system);
+
+        let asset_server = app.world.resource::<AssetServer>();
+        let handle = asset_server.load(self.path.as_str());
+        let map = self
+            .map
+            .as_ref()
+            .map(|path| asset_server.load(path.as_str()));
+        app.insert_resource(GameConfigHandle {
+            handle,
+            map,
+            ready: false,
+        });
+    }
+}
+
+// system: rebuild the config resource when the files are (re)loaded or the overrides change
+#[allow(clippy::too_many_arguments)]
+pub fn apply_game_config_system(
+    asset_server: Res<AssetServer>,
+    mut asset_events: EventReader<AssetEvent<GameConfig>>,
+    mut map_events: EventReader<AssetEvent<PlazaMap>>,
+    assets: Res<Assets<GameConfig>>,
+    maps: Res<Assets<PlazaMap>>,
+    mut config_handle: ResMut<GameConfigHandle>,
+    overrides: Res<GameConfigOverrides>,
+    mut config: ResMut<GameConfig>,
+) {
+    let mut reload = overrides.is_changed();
+    for event in asset_events.iter() {
+        match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                reload |= *handle == config_handle.handle;
+            }
+            AssetEvent::Removed { .. } => {}
+        }
+    }
+    for event in map_events.iter() {
+        match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                reload |= Some(handle) == config_handle.map.as_ref();
+            }
+            AssetEvent::Removed { .. } => {}
+        }
+    }
+    if !config_handle.ready {
+        // both files loaded or failed to
+        let config_failed = asset_server.get_load_state(&config_handle.handle) == LoadState::Failed;
+        let map_failed = config_handle
+            .map
+            .as_ref()
+            .is_some_and(|map| asset_server.get_load_state(map) == LoadState::Failed);
+        let config_done = config_failed || assets.contains(&config_handle.handle);
+        let map_done = config_handle
+            .map
+            .as_ref()
+            .is_none_or(|map| map_failed || maps.contains(map));
+        if config_done && map_done {
+            if config_failed {
+                warn!("Could not load the game config, using defaults");
+            }
+            if map_failed {
+                warn!("Could not load the plaza map, using the config's");
+            }
+            config_handle.ready = true;
+            reload = true;
+        }
+    }
+    if !reload {
+        return;
+    }
+
+    let mut new_config = assets
+        .get(&config_handle.handle)
+        .cloned()
+        .unwrap_or_default();
+    if let Some(map) = config_handle.map.as_ref().and_then(|map| maps.get(map)) {
+        new_config.map = map.clone();
+    }
+    new_config.apply_overrides(&overrides);
+    if *config != new_config {
+        info!("Game config updated: {:?}", new_config);
+        *config = new_config;
+    }
+}
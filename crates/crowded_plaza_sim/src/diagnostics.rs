@@ -0,0 +1,103 @@
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticId, Diagnostics},
+    prelude::*,
+};
+
+use crate::{ActorSpace, Faction, FactionRegistry, SimClock};
+
+// measurements averaged over, like the frame time diagnostics
+const HISTORY_LENGTH: usize = 20;
+
+/// What the last tick of the simulation did, filled in by its systems.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TickStats {
+    /// Queries on the [`ActorSpace`], by every system.
+    pub neighbor_queries: usize,
+    /// Actors that went over to another faction.
+    pub conversions: usize,
+    /// Pairs of actors of the same faction close enough to push each other away.
+    pub repulsion_pairs: usize,
+}
+
+/// Adds the [`TickStats`] of the last tick of every app update and the actors of each faction
+/// to the [`Diagnostics`]. Needs the `DiagnosticsPlugin`.
+#[derive(Default)]
+pub struct SimDiagnosticsPlugin;
+
+impl Plugin for SimDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(Self::setup_system)
+            .add_system_to_stage(CoreStage::PostUpdate, Self::diagnostic_system);
+    }
+}
+
+impl SimDiagnosticsPlugin {
+    pub const NEIGHBOR_QUERIES: DiagnosticId =
+        DiagnosticId::from_u128(198742767043407244518555640303023715769);
+    pub const CONVERSIONS: DiagnosticId =
+        DiagnosticId::from_u128(114345266985516774268709786957748370904);
+    pub const REPULSION_PAIRS: DiagnosticId =
+        DiagnosticId::from_u128(118192575274183901753137018105125631019);
+
+    /// Actors in `faction`, added once the faction shows up in a match.
+    pub fn faction_actors(faction: Faction) -> DiagnosticId {
+        let (kind, id) = match faction {
+            Faction::Neutral => (0, 0),
+            Faction::Player(id) => (1, id),
+            Faction::Bot(id) => (2, id),
+        };
+        DiagnosticId::from_u128(
+            273392268707099266990914040387137923698 ^ ((kind as u128) << 32 | id as u128),
+        )
+    }
+
+    pub fn setup_system(mut diagnostics: ResMut<Diagnostics>) {
+        diagnostics.add(Diagnostic::new(
+            Self::NEIGHBOR_QUERIES,
+            "neighbor_queries",
+            HISTORY_LENGTH,
+        ));
+        diagnostics.add(Diagnostic::new(
+            Self::CONVERSIONS,
+            "conversions",
+            HISTORY_LENGTH,
+        ));
+        diagnostics.add(Diagnostic::new(
+            Self::REPULSION_PAIRS,
+            "repulsion_pairs",
+            HISTORY_LENGTH,
+        ));
+    }
+
+    pub fn diagnostic_system(
+        mut diagnostics: ResMut<Diagnostics>,
+        clock: Res<SimClock>,
+        stats: Res<TickStats>,
+        registry: Res<FactionRegistry>,
+    ) {
+        // nothing new while paused, or online where the server ticks
+        if clock.ticks_this_update() == 0 {
+            return;
+        }
+        diagnostics.add_measurement(Self::NEIGHBOR_QUERIES, stats.neighbor_queries as f64);
+        diagnostics.add_measurement(Self::CONVERSIONS, stats.conversions as f64);
+        diagnostics.add_measurement(Self::REPULSION_PAIRS, stats.repulsion_pairs as f64);
+        for (faction, info) in registry.iter() {
+            let id = Self::faction_actors(faction);
+            if diagnostics.get(id).is_none() {
+                let name = match faction {
+                    Faction::Neutral => "actors_neutral".to_string(),
+                    Faction::Player(id) => format!("actors_player_{}", id),
+                    Faction::Bot(id) => format!("actors_bot_{}", id),
+                };
+                diagnostics.add(Diagnostic::new(id, name, HISTORY_LENGTH));
+            }
+            diagnostics.add_measurement(id, info.count as f64);
+        }
+    }
+}
+
+// system: neighbor queries are counted by the index, read them once every system is done
+pub fn record_tick_stats_system(spatial: Res<ActorSpace>, mut stats: ResMut<TickStats>) {
+    stats.neighbor_queries = spatial.queries();
+}
@@ -0,0 +1,247 @@
+use bevy::{
+    ecs::event::{Events, ManualEventReader},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::SQRT_2;
+
+use crate::{
+    config::check_range, headless_match, Actor, ActorSpace, ConfigError, Countdown, Faction,
+    FactionRegistry, GameConfig, Heading, MatchEvent, PlayerController, PlayerInput, SimClock,
+};
+
+/// Observation channels, in the order they appear in [`Observation::grid`].
+pub const OBSERVATION_CHANNELS: [&str; 3] = ["neutral", "own", "enemy"];
+
+/// How an [`SimEnv`] observes and steps the match.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnvSettings {
+    /// Cells per side of the observation grid, odd so the pawn sits in the middle cell.
+    pub grid_size: usize,
+    /// Side of a grid cell in world units.
+    pub cell_size: f32,
+    /// Simulation ticks an action is held for.
+    pub ticks_per_step: u32,
+}
+
+impl Default for EnvSettings {
+    fn default() -> Self {
+        EnvSettings {
+            grid_size: 11,
+            cell_size: 1.0,
+            ticks_per_step: 4,
+        }
+    }
+}
+
+impl EnvSettings {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let expected = "an odd number between 1 and 255";
+        check_range("grid_size", self.grid_size, 1, 255, expected)?;
+        if self.grid_size % 2 == 0 {
+            return Err(ConfigError::OutOfRange {
+                field: "grid_size",
+                value: self.grid_size.to_string(),
+                expected,
+            });
+        }
+        check_range(
+            "cell_size",
+            self.cell_size,
+            0.01,
+            100.0,
+            "between 0.01 and 100",
+        )?;
+        check_range(
+            "ticks_per_step",
+            self.ticks_per_step,
+            1,
+            600,
+            "between 1 and 600",
+        )?;
+        Ok(())
+    }
+}
+
+/// What the agent sees after a reset or step.
+#[derive(Clone, Debug, Serialize)]
+pub struct Observation {
+    /// Actors per square unit, `[channel][row][column]` flattened, channels as in
+    /// [`OBSERVATION_CHANNELS`]. Rows go up in y and columns in x, centered on the player's pawn,
+    /// which is not counted itself.
+    pub grid: Vec<f32>,
+    pub grid_size: usize,
+    /// The pawn's velocity in units per second.
+    pub velocity: [f32; 2],
+    /// Actors in the player's faction, the pawn included.
+    pub own_count: i32,
+    /// Seconds until the match is over.
+    pub time_left: f32,
+}
+
+/// The result of [`SimEnv::step`].
+#[derive(Clone, Debug, Serialize)]
+pub struct Step {
+    pub observation: Observation,
+    /// Actors the player's faction gained during the step, negative when it lost some.
+    pub reward: f32,
+    /// The player's pawn was eliminated or time ran out, call [`SimEnv::reset`] next.
+    pub done: bool,
+}
+
+/// The headless simulation as a reinforcement learning environment with a gym-style API, one
+/// match per episode with the agent steering the player's pawn.
+///
+/// ```no_run
+/// use crowded_plaza_sim::{EnvSettings, GameConfig, SimEnv};
+///
+/// let mut env = SimEnv::new(GameConfig::default(), EnvSettings::default());
+/// let _observation = env.reset(42);
+/// while !env.step([1.0, 0.0]).done {}
+/// ```
+pub struct SimEnv {
+    config: GameConfig,
+    settings: EnvSettings,
+    app: App,
+    match_events: ManualEventReader<MatchEvent>,
+    done: bool,
+}
+
+impl SimEnv {
+    /// An environment playing matches with `config`, ready to [`step`](SimEnv::step) a match
+    /// with seed 0. Both are expected to be validated.
+    pub fn new(config: GameConfig, settings: EnvSettings) -> Self {
+        let mut env = SimEnv {
+            config,
+            settings,
+            app: App::new(),
+            match_events: ManualEventReader::default(),
+            done: false,
+        };
+        env.reset(0);
+        env
+    }
+
+    pub fn settings(&self) -> &EnvSettings {
+        &self.settings
+    }
+
+    /// Start a new match with `seed`, before its first tick.
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        // the clock starts paused so setting up the match doesn't tick it
+        let mut app = headless_match(self.config.clone(), Some(seed), true);
+        app.update();
+        app.world.resource_mut::<SimClock>().paused = false;
+
+        self.app = app;
+        self.match_events = ManualEventReader::default();
+        self.done = false;
+        self.observe()
+    }
+
+    /// Steer the player's pawn along `action`, a direction that does not need to be
+    /// normalized, for [`EnvSettings::ticks_per_step`] ticks. A zero action keeps the heading.
+    pub fn step(&mut self, action: [f32; 2]) -> Step {
+        let own_count_before = self.own_count();
+        let action = Vec2::from(action);
+        for _ in 0..self.settings.ticks_per_step {
+            if self.done {
+                break;
+            }
+            if action != Vec2::ZERO && action.is_finite() {
+                self.app
+                    .world
+                    .resource_mut::<PlayerInput>()
+                    .steer
+                    .insert(0, Heading::from_direction(action));
+            }
+            self.app.update();
+
+            let events = self.app.world.resource::<Events<MatchEvent>>();
+            for event in self.match_events.iter(events) {
+                if matches!(
+                    event,
+                    MatchEvent::PawnEliminated {
+                        faction: Faction::PLAYER
+                    } | MatchEvent::TimeUp
+                ) {
+                    self.done = true;
+                }
+            }
+        }
+
+        Step {
+            reward: (self.own_count() - own_count_before) as f32,
+            done: self.done,
+            observation: self.observe(),
+        }
+    }
+
+    fn own_count(&self) -> i32 {
+        self.app
+            .world
+            .resource::<FactionRegistry>()
+            .count(Faction::PLAYER)
+    }
+
+    fn observe(&mut self) -> Observation {
+        let grid_size = self.settings.grid_size;
+        let cell_size = self.settings.cell_size;
+        let mut grid = vec![0.0; OBSERVATION_CHANNELS.len() * grid_size * grid_size];
+
+        let world = &mut self.app.world;
+        let (player, center, velocity) = world
+            .query::<(Entity, &Transform, &Actor, &PlayerController)>()
+            .iter(world)
+            .find(|(_, _, _, controller)| controller.0 == 0)
+            .map(|(entity, tr, actor, _)| (entity, tr.translation, actor.velocity))
+            .expect("the player is spawned by `setup_match`");
+
+        // the spatial index was built at the start of the last tick, bring it up to date, the next tick
+        // rebuilds it from the same positions anyway
+        let actors = world
+            .query_filtered::<(Entity, &Transform), With<Actor>>()
+            .iter(world)
+            .map(|(entity, tr)| (tr.translation, entity))
+            .collect();
+        world.resource_mut::<ActorSpace>().recreate(actors);
+
+        let half_extent = grid_size as f32 * cell_size / 2.0;
+        let cell_area = cell_size * cell_size;
+        let spatial = world.resource::<ActorSpace>();
+        for (position, entity) in spatial.within_distance(center, half_extent * SQRT_2) {
+            if entity == player {
+                continue;
+            }
+            let actor = match world.get::<Actor>(entity) {
+                Some(actor) => actor,
+                None => continue,
+            };
+            let offset = position - center + Vec3::splat(half_extent);
+            let column = (offset.x / cell_size).floor();
+            let row = (offset.y / cell_size).floor();
+            if column < 0.0 || row < 0.0 || column >= grid_size as f32 || row >= grid_size as f32 {
+                continue;
+            }
+            let channel = match actor.faction {
+                Faction::Neutral => 0,
+                Faction::PLAYER => 1,
+                _ => 2,
+            };
+            let index = (channel * grid_size + row as usize) * grid_size + column as usize;
+            grid[index] += 1.0 / cell_area;
+        }
+
+        let time_left = world.get_resource::<Countdown>().map_or(0.0, |countdown| {
+            (countdown.main_timer.duration() - countdown.main_timer.elapsed()).as_secs_f32()
+        });
+        Observation {
+            grid,
+            grid_size,
+            velocity: [velocity.x, velocity.y],
+            own_count: self.own_count(),
+            time_left,
+        }
+    }
+}
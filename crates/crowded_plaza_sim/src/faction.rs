@@ -0,0 +1,315 @@
+use bevy::{prelude::*, tasks::ComputeTaskPool};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{
+    actor::{collect_factions, faction_of},
+    Actor, ActorSpace, GameConfig, Pawn, TickStats, MAX_OPPONENT_COUNT, MAX_PLAYER_COUNT,
+    PAR_BATCH_SIZE,
+};
+
+// factions a match can have, neutrals included
+const MAX_FACTIONS: usize = 1 + MAX_PLAYER_COUNT as usize + MAX_OPPONENT_COUNT as usize;
+
+/// Who an actor belongs to.
+///
+/// Ordered neutral, players, then bots, each by id. Conversion ties go to the lowest faction in
+/// this order.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub enum Faction {
+    /// Not converted yet, wanders on its own.
+    #[default]
+    Neutral,
+    /// Led by a pawn under player control.
+    Player(u32),
+    /// Led by a pawn with an [`OpponentController`](crate::OpponentController).
+    Bot(u32),
+}
+
+impl Faction {
+    /// The local player's faction, the first seat.
+    pub const PLAYER: Faction = Faction::Player(0);
+
+    pub fn is_neutral(&self) -> bool {
+        *self == Faction::Neutral
+    }
+}
+
+// helper: every faction a match can have at its own index below `MAX_FACTIONS`, in order
+fn faction_slot(faction: Faction) -> usize {
+    match faction {
+        Faction::Neutral => 0,
+        Faction::Player(id) => 1 + id as usize,
+        Faction::Bot(id) => 1 + MAX_PLAYER_COUNT as usize + id as usize,
+    }
+}
+
+// helper: the faction at `slot`, see `faction_slot`
+fn slot_faction(slot: usize) -> Faction {
+    let players = MAX_PLAYER_COUNT as usize;
+    match slot {
+        0 => Faction::Neutral,
+        slot if slot <= players => Faction::Player(slot as u32 - 1),
+        slot => Faction::Bot((slot - players - 1) as u32),
+    }
+}
+
+const BOT_NAMES: [&str; 26] = [
+    "Anderson", "Bob", "Cat", "Doug", "Eason", "Fiona", "Gus", "Hana", "Ivan", "Jade", "Kai",
+    "Luna", "Milo", "Nora", "Otto", "Pia", "Quinn", "Rosa", "Sam", "Tess", "Uma", "Vic", "Wes",
+    "Xia", "Yuri", "Zoe",
+];
+
+// helper: a name for every bot, numbered once we run out of names
+fn bot_name(id: u32) -> String {
+    let name = BOT_NAMES[id as usize % BOT_NAMES.len()];
+    match id as usize / BOT_NAMES.len() {
+        0 => name.to_string(),
+        round => format!("{0} {1}", name, round + 1),
+    }
+}
+
+// helper: hsl to srgb, hue in degrees
+fn hsl_to_srgb(hue: f32, saturation: f32, lightness: f32) -> [f32; 3] {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let sector = hue / 60.0;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    [r + m, g + m, b + m]
+}
+
+// helper: spread bot hues by the golden angle, so any number of them stay apart,
+// and keep clear of the player's red
+fn bot_color(id: u32) -> [f32; 4] {
+    let hue = 30.0 + (id as f32 * 137.508) % 300.0;
+    // every other ten bots a darker shade
+    let lightness = if id % 20 < 10 { 0.55 } else { 0.4 };
+    let [r, g, b] = hsl_to_srgb(hue, 0.75, lightness);
+    [r, g, b, 1.0]
+}
+
+// helper: the first player is red, the others get lighter shades than the bots
+fn player_color(id: u32) -> [f32; 4] {
+    if id == 0 {
+        return [1.0, 0.0, 0.0, 1.0];
+    }
+    let hue = (id as f32 * 137.508) % 360.0;
+    let [r, g, b] = hsl_to_srgb(hue, 0.9, 0.75);
+    [r, g, b, 1.0]
+}
+
+/// What the game knows about a faction during a match.
+#[derive(Clone, Debug)]
+pub struct FactionInfo {
+    pub name: String,
+    /// sRGBA
+    pub color: [f32; 4],
+    /// A `StandardMaterial` in `color`, set by the game client. Headless runs leave it empty.
+    pub material: Option<HandleUntyped>,
+    /// The pawn leading the faction, until it is eliminated. Neutrals have none.
+    pub leader: Option<Entity>,
+    /// Live actors in the faction, the leader included.
+    pub count: i32,
+}
+
+/// Every faction of the current match, filled in by [`setup_match`](crate::setup_match).
+#[derive(Clone, Debug, Default)]
+pub struct FactionRegistry {
+    factions: BTreeMap<Faction, FactionInfo>,
+}
+
+impl FactionRegistry {
+    /// Neutrals, `player_count` players and `opponent_count` bots, without leaders yet.
+    pub fn new(actor_count: i32, player_count: i32, opponent_count: i32) -> Self {
+        let mut factions = BTreeMap::new();
+        let mut insert = |faction, name: String, color, count| {
+            factions.insert(
+                faction,
+                FactionInfo {
+                    name,
+                    color,
+                    material: None,
+                    leader: None,
+                    count,
+                },
+            );
+        };
+        insert(
+            Faction::Neutral,
+            "Neutral".to_string(),
+            [1.0, 1.0, 1.0, 1.0],
+            actor_count,
+        );
+        for id in 0..player_count as u32 {
+            let name = match id {
+                0 => "Player".to_string(),
+                _ => format!("Player {}", id + 1),
+            };
+            insert(Faction::Player(id), name, player_color(id), 1);
+        }
+        for id in 0..opponent_count as u32 {
+            insert(Faction::Bot(id), bot_name(id), bot_color(id), 1);
+        }
+        FactionRegistry { factions }
+    }
+
+    pub fn get(&self, faction: Faction) -> Option<&FactionInfo> {
+        self.factions.get(&faction)
+    }
+
+    pub fn get_mut(&mut self, faction: Faction) -> Option<&mut FactionInfo> {
+        self.factions.get_mut(&faction)
+    }
+
+    /// All factions, in [`Faction`] order.
+    pub fn iter(&self) -> impl Iterator<Item = (Faction, &FactionInfo)> {
+        self.factions.iter().map(|(faction, info)| (*faction, info))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Faction, &mut FactionInfo)> {
+        self.factions
+            .iter_mut()
+            .map(|(faction, info)| (*faction, info))
+    }
+
+    /// Live actors in `faction`, zero for factions not in this match.
+    pub fn count(&self, faction: Faction) -> i32 {
+        self.get(faction).map_or(0, |info| info.count)
+    }
+
+    /// `(faction, count)` from the largest faction down, ties go to the lowest faction.
+    /// Neutrals are not ranked.
+    pub fn ranking(&self) -> Vec<(Faction, i32)> {
+        let mut ranking: Vec<(Faction, i32)> = self
+            .iter()
+            .filter(|(faction, _)| !faction.is_neutral())
+            .map(|(faction, info)| (faction, info.count))
+            .collect();
+        ranking.sort_by(|(fac1, count1), (fac2, count2)| count2.cmp(count1).then(fac1.cmp(fac2)));
+        ranking
+    }
+}
+
+/// Things that happened in a match which whoever drives the simulation may want to react to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MatchEvent {
+    /// The pawn leading `faction` lost its last follower and was converted.
+    PawnEliminated { faction: Faction },
+    /// The match timer ran out.
+    TimeUp,
+}
+
+/// The faction an actor goes over to at the end of the tick, the majority of its neighbors.
+/// Worked out for every actor before any of them changes, spawn actors with it.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct Conversion(pub Option<Faction>);
+
+// system: change actor's faction according to it's surrounding majority faction
+#[allow(clippy::too_many_arguments)]
+pub fn change_actor_faction_system(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    spatial_query: Res<ActorSpace>,
+    task_pool: Res<ComputeTaskPool>,
+    mut factions: Local<Vec<Faction>>,
+    mut actor_set: Query<(
+        Entity,
+        &Transform,
+        &mut Actor,
+        Option<&Pawn>,
+        &mut Conversion,
+    )>,
+    mut registry: ResMut<FactionRegistry>,
+    mut match_events: EventWriter<MatchEvent>,
+    mut stats: ResMut<TickStats>,
+) {
+    // every actor's majority in parallel, from the factions before this tick's conversions
+    collect_factions(
+        &mut factions,
+        actor_set
+            .iter()
+            .map(|(entity, _, actor, _, _)| (entity, actor)),
+    );
+    let factions = &factions;
+    let config = &*config;
+    let spatial_query = &*spatial_query;
+    actor_set.par_for_each_mut(
+        &task_pool,
+        PAR_BATCH_SIZE,
+        |(_, tr, _, _, mut conversion)| {
+            let mut counts = [0u32; MAX_FACTIONS];
+            // (count, slot) of the faction with the most neighbors so far
+            let mut majority: Option<(u32, usize)> = None;
+            let neighbors =
+                spatial_query.within_distance(tr.translation, config.neighbor_threshold);
+            for (_, neighbor_entity) in neighbors.iter() {
+                let neighbor_faction = faction_of(factions, *neighbor_entity);
+                if neighbor_faction.is_neutral() {
+                    continue; // we skip neighbor no faction actor
+                }
+                let slot = faction_slot(neighbor_faction);
+                counts[slot] += 1;
+                // ties go to the lowest faction
+                let count = counts[slot];
+                if majority.is_none_or(|(most, at)| count > most || (count == most && slot < at)) {
+                    majority = Some((count, slot));
+                }
+            }
+            conversion.0 = majority.map(|(_, slot)| slot_faction(slot));
+        },
+    );
+
+    // then one by one, a pawn is eliminated by the conversions before it
+    stats.conversions = 0;
+    for (entity, _, mut actor, pawn, conversion) in actor_set.iter_mut() {
+        let fac = match conversion.0 {
+            Some(fac) if !fac.is_neutral() && fac != actor.faction => fac,
+            _ => continue,
+        };
+        match pawn {
+            Some(_) => {
+                let faction_count = registry.count(actor.faction);
+                if faction_count <= 1 {
+                    // this pawn is dead!
+                    info!(
+                        "Remove pawn for faction {0:?}, with count {1}",
+                        actor.faction, faction_count
+                    );
+                    if let Some(info) = registry.get_mut(actor.faction) {
+                        info.leader = None;
+                    }
+                    match_events.send(MatchEvent::PawnEliminated {
+                        faction: actor.faction,
+                    });
+                    commands.entity(entity).remove::<Pawn>();
+                }
+            }
+            None => {
+                // this is a normal actor
+
+                // update faction count
+                if let Some(info) = registry.get_mut(actor.faction) {
+                    info.count -= 1;
+                }
+                if let Some(info) = registry.get_mut(fac) {
+                    info.count += 1;
+                }
+
+                // update actor faction
+                actor.faction = fac;
+                actor.velocity = Vec3::new(0.0, 0.0, 0.0);
+                stats.conversions += 1;
+            }
+        }
+    }
+}
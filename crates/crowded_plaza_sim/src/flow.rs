@@ -0,0 +1,240 @@
+use bevy::prelude::*;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap},
+};
+
+use crate::{Actor, Faction, GameConfig, Obstacles, Pawn};
+
+// cost of a step to a side and to a corner, whole numbers so the fields are exact
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+// cells are made larger on plazas that would need more along a side
+const MAX_CELLS_PER_SIDE: f32 = 256.0;
+const UNREACHED: u32 = u32::MAX;
+// cells a field's rebuild gets through per update, a large plaza takes a few ticks
+const CELLS_PER_UPDATE: usize = 4096;
+const NEIGHBORS: [(isize, isize); 8] = [
+    (1, 0),
+    (-1, 0),
+    (0, 1),
+    (0, -1),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+    (-1, -1),
+];
+
+// how far each cell is from the leader's cell, around the obstacles
+#[derive(Clone, Debug, Default)]
+struct FlowField {
+    source: usize,
+    distance: Vec<u32>,
+    // the field toward the cell the leader moved to while it is built, its memory otherwise
+    next: Search,
+}
+
+// Dijkstra from `source` that can stop and go on later, ties broken by cell so every machine
+// builds the same field
+#[derive(Clone, Debug, Default)]
+struct Search {
+    source: Option<usize>,
+    distance: Vec<u32>,
+    open: BinaryHeap<Reverse<(u32, usize)>>,
+}
+
+/// The way to each faction's leader from anywhere on the plaza, around the obstacles.
+///
+/// The plaza is cut into cells of about `flow_cell_size`, cells near an obstacle are blocked,
+/// and every faction gets the distance of each cell to its leader's. When a leader moves to
+/// another cell its field is rebuilt a few thousand cells per update, followers keep to the
+/// last complete field meanwhile. Plazas without obstacles have no fields, followers head
+/// straight for their leader there.
+#[derive(Clone, Debug, Default)]
+pub struct FlowFields {
+    min: Vec2,
+    cell_size: f32,
+    width: usize,
+    height: usize,
+    blocked: Vec<bool>,
+    fields: BTreeMap<Faction, FlowField>,
+}
+
+impl FlowFields {
+    /// The grid over the plaza of `config`, set up by [`setup_match`](crate::setup_match).
+    pub fn new(config: &GameConfig, obstacles: &Obstacles) -> Self {
+        if obstacles.is_empty() {
+            return FlowFields::default();
+        }
+        let extent = config.boundary().half_extents();
+        let cell_size = config
+            .flow_cell_size
+            .max(extent.max_element() * 2.0 / MAX_CELLS_PER_SIDE);
+        let width = (extent.x * 2.0 / cell_size).ceil() as usize;
+        let height = (extent.y * 2.0 / cell_size).ceil() as usize;
+        let mut fields = FlowFields {
+            min: -extent,
+            cell_size,
+            width,
+            height,
+            blocked: Vec::with_capacity(width * height),
+            fields: BTreeMap::new(),
+        };
+        // an obstacle anywhere in the cell, even a thin wall through its corner
+        let reach = cell_size * std::f32::consts::FRAC_1_SQRT_2;
+        for cell in 0..width * height {
+            let center = fields.center(cell);
+            let blocked = obstacles.edges_near(center, reach).next().is_some();
+            fields.blocked.push(blocked);
+        }
+        fields
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocked.is_empty()
+    }
+
+    fn cell_of(&self, position: Vec2) -> Option<usize> {
+        let cell = ((position - self.min) / self.cell_size).floor();
+        if cell.x < 0.0 || cell.y < 0.0 {
+            return None;
+        }
+        let (x, y) = (cell.x as usize, cell.y as usize);
+        (x < self.width && y < self.height).then(|| y * self.width + x)
+    }
+
+    fn center(&self, cell: usize) -> Vec2 {
+        let (x, y) = (cell % self.width, cell / self.width);
+        self.min + (Vec2::new(x as f32, y as f32) + 0.5) * self.cell_size
+    }
+
+    // helper: the cells next to `cell` with the cost of stepping there, not cutting the corner
+    // of a blocked cell
+    fn neighbors(&self, cell: usize) -> impl Iterator<Item = (usize, u32)> + '_ {
+        let (x, y) = ((cell % self.width) as isize, (cell / self.width) as isize);
+        NEIGHBORS.iter().filter_map(move |(dx, dy)| {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx < 0 || ny < 0 || nx >= self.width as isize || ny >= self.height as isize {
+                return None;
+            }
+            let index = |x: isize, y: isize| y as usize * self.width + x as usize;
+            let next = index(nx, ny);
+            if self.blocked[next] {
+                return None;
+            }
+            if *dx != 0 && *dy != 0 {
+                if self.blocked[index(nx, y)] || self.blocked[index(x, ny)] {
+                    return None;
+                }
+                Some((next, DIAGONAL_COST))
+            } else {
+                Some((next, STRAIGHT_COST))
+            }
+        })
+    }
+
+    /// Keep the field of `faction` up to date with its leader, now at `leader`. A faction's first
+    /// field is built at once, later ones over as many updates as they take.
+    pub fn update(&mut self, faction: Faction, leader: Vec2) {
+        let source = match self.cell_of(leader) {
+            Some(source) => source,
+            None => {
+                self.fields.remove(&faction);
+                return;
+            }
+        };
+        let (mut field, mut budget) = match self.fields.remove(&faction) {
+            Some(field) => (field, CELLS_PER_UPDATE),
+            None => (FlowField::default(), usize::MAX),
+        };
+        loop {
+            let next = match field.next.source {
+                Some(next) => next,
+                None if !field.distance.is_empty() && field.source == source => break,
+                None => {
+                    self.start(&mut field.next, source);
+                    source
+                }
+            };
+            if !self.advance(&mut field.next, &mut budget) {
+                break;
+            }
+            // done, the last field's memory goes to the next one
+            std::mem::swap(&mut field.distance, &mut field.next.distance);
+            field.source = next;
+            field.next.source = None;
+        }
+        self.fields.insert(faction, field);
+    }
+
+    // helper: start `search` over from `source`, reusing its memory
+    fn start(&self, search: &mut Search, source: usize) {
+        search.source = Some(source);
+        search.distance.clear();
+        search.distance.resize(self.width * self.height, UNREACHED);
+        search.distance[source] = 0;
+        search.open.clear();
+        search.open.push(Reverse((0, source)));
+    }
+
+    // helper: go on with `search` for at most `budget` cells, taken off the budget, returns
+    // whether it is done
+    fn advance(&self, search: &mut Search, budget: &mut usize) -> bool {
+        let _span = info_span!("flow_field_search").entered();
+        while *budget > 0 {
+            let Reverse((cost, cell)) = match search.open.pop() {
+                Some(open) => open,
+                None => return true,
+            };
+            *budget -= 1;
+            if cost > search.distance[cell] {
+                continue;
+            }
+            for (next, step) in self.neighbors(cell) {
+                if cost + step < search.distance[next] {
+                    search.distance[next] = cost + step;
+                    search.open.push(Reverse((cost + step, next)));
+                }
+            }
+        }
+        search.open.is_empty()
+    }
+
+    /// Which way a follower of `faction` at `position` should go to reach its leader: toward the
+    /// center of the neighboring cell closest to it. None without a field, in the leader's cell
+    /// or where the leader cannot be reached, the follower heads straight for it then.
+    pub fn direction(&self, faction: Faction, position: Vec2) -> Option<Vec2> {
+        let field = self.fields.get(&faction)?;
+        let cell = self.cell_of(position)?;
+        if cell == field.source {
+            return None;
+        }
+        let mut best = (field.distance[cell], None);
+        for (next, _) in self.neighbors(cell) {
+            if field.distance[next] < best.0 {
+                best = (field.distance[next], Some(next));
+            }
+        }
+        best.1
+            .and_then(|next| (self.center(next) - position).try_normalize())
+    }
+}
+
+// system: keep the flow field of every faction with a leader up to date, and drop the rest
+pub fn update_flow_fields_system(
+    mut flow: ResMut<FlowFields>,
+    pawn_query: Query<(&Actor, &Transform), With<Pawn>>,
+) {
+    if flow.is_empty() {
+        return;
+    }
+    let leaders: BTreeMap<Faction, Vec2> = pawn_query
+        .iter()
+        .map(|(actor, tr)| (actor.faction, tr.translation.truncate()))
+        .collect();
+    flow.fields
+        .retain(|faction, _| leaders.contains_key(faction));
+    for (faction, leader) in leaders {
+        flow.update(faction, leader);
+    }
+}
@@ -0,0 +1,241 @@
+//! Headless crowd simulation for Crowded Plaza.
+//!
+//! Everything in here runs on `MinimalPlugins` alone: no window, renderer or GPU required.
+//! The game client adds its visuals on top of the entities spawned by [`setup_match`].
+//!
+//! The simulation advances in fixed ticks of [`TICK_SECONDS`] in its own [`SimStage`], so a
+//! match plays the same at any frame rate. Headless drivers that step the app themselves can
+//! switch the [`SimClock`] to [`TickMode::Manual`] to run one tick per update, as
+//! [`headless_match`] does.
+//!
+//! ```no_run
+//! use bevy::prelude::*;
+//! use crowded_plaza_sim::{setup_match, SimClock, SimulationPlugin, TickMode};
+//!
+//! let mut app = App::new();
+//! app.add_plugins(MinimalPlugins)
+//!     .add_plugin(SimulationPlugin)
+//!     .insert_resource(SimClock::new(TickMode::Manual))
+//!     .add_startup_system(setup_match);
+//! for _ in 0..600 {
+//!     app.update();
+//! }
+//! ```
+mod actor;
+mod boundary;
+mod brain;
+mod config;
+mod diagnostics;
+mod env;
+mod faction;
+mod flow;
+mod obstacle;
+mod replay;
+mod rng;
+mod setup;
+mod spatial;
+mod tick;
+mod zone;
+
+use bevy::{prelude::*, utils::Duration};
+
+pub use crate::{
+    actor::{
+        change_direction_actor_system, follow_pawn_system, move_actor_system, move_pawn_system,
+        repulse_actor_system, steer_player_system, Actor, Pawn, PlayerController, PlayerInput,
+    },
+    boundary::{BoundaryBehavior, BoundaryEdge, BoundaryShape},
+    brain::{
+        change_direction_opponent_system, DefendCrowdBrain, Difficulty, FleeLargerBrain,
+        HuntSmallerBrain, OpponentBrain, OpponentController, Perception, SeekNeutralBrain,
+        Sighting, Strategy, WanderBrain,
+    },
+    config::{
+        apply_game_config_system, ConfigError, GameConfig, GameConfigHandle, GameConfigLoader,
+        GameConfigOverrides, GameConfigPlugin, MAX_OPPONENT_COUNT, MAX_PLAYER_COUNT,
+    },
+    diagnostics::{record_tick_stats_system, SimDiagnosticsPlugin, TickStats},
+    env::{EnvSettings, Observation, SimEnv, Step, OBSERVATION_CHANNELS},
+    faction::{
+        change_actor_faction_system, Conversion, Faction, FactionInfo, FactionRegistry, MatchEvent,
+    },
+    flow::{update_flow_fields_system, FlowFields},
+    obstacle::{Obstacle, ObstacleKind, Obstacles, PlazaMap, PlazaMapLoader},
+    replay::{
+        playback_input_system, record_config_system, Heading, Replay, ReplayError, ReplayPlayback,
+        ReplayRecorder,
+    },
+    rng::{SimRng, SimSeed},
+    setup::{countdown, setup_match, Countdown},
+    spatial::{ActorSpace, SpatialBackend, SpatialIndex, UniformGrid},
+    tick::{
+        rebuild_actor_space_system, sim_tick_criteria, store_previous_translation_system,
+        PreviousTranslation, SimClock, SimStage, TickMode,
+    },
+    zone::{safe_zone_system, GameMode, SafeZone},
+};
+
+pub const TICK_SECONDS: f32 = 1.0 / 60.0;
+pub const MAX_TICKS_PER_UPDATE: u32 = 5;
+/// Ticks run per app update while the [`SimClock`] seeks.
+pub const SEEK_TICKS_PER_UPDATE: u32 = 240;
+/// Wall time the ticks of an app update get by default, see [`SimClock::budget`]. Leaves a
+/// 30 fps frame some time to render.
+pub const UPDATE_TICK_BUDGET: Duration = Duration::from_millis(20);
+// actors per task of the systems that run on every core, small enough to spread a few
+// thousand actors
+pub(crate) const PAR_BATCH_SIZE: usize = 256;
+
+/// Simulation systems run in the order of these labels within a tick, so a match only depends
+/// on its seed and the player's input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, SystemLabel)]
+pub enum SimLabel {
+    Snapshot,
+    Playback,
+    Input,
+    Wander,
+    Steer,
+    Flow,
+    Follow,
+    Repulse,
+    Move,
+    Convert,
+    Zone,
+    Countdown,
+}
+
+/// Runs the crowd simulation: wandering, following, repulsion, obstacles, faction conversion, the
+/// safe zone and the match timer.
+///
+/// Systems only act on entities spawned by [`setup_match`], so the plugin can stay installed
+/// between matches. Outcomes are reported through [`MatchEvent`]. Systems that feed input to
+/// the simulation, like [`PlayerInput`], should run in `CoreStage::Update`, the [`SimStage`]
+/// runs right after it.
+#[derive(Default)]
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        // the spatial index is rebuilt every tick instead of by a `bevy_spatial` plugin, which
+        // updates it once per frame
+        app.init_resource::<ActorSpace>()
+            .init_resource::<GameConfig>()
+            .init_resource::<FactionRegistry>()
+            .init_resource::<Obstacles>()
+            .init_resource::<FlowFields>()
+            .init_resource::<SimSeed>()
+            .init_resource::<SimRng>()
+            .init_resource::<SimClock>()
+            .init_resource::<PlayerInput>()
+            .init_resource::<TickStats>()
+            .add_event::<MatchEvent>()
+            .add_stage_after(
+                CoreStage::Update,
+                SimStage,
+                SystemStage::parallel().with_run_criteria(sim_tick_criteria),
+            )
+            .add_system_to_stage(
+                SimStage,
+                store_previous_translation_system.label(SimLabel::Snapshot),
+            )
+            .add_system_to_stage(
+                SimStage,
+                rebuild_actor_space_system.label(SimLabel::Snapshot),
+            )
+            .add_system_to_stage(
+                SimStage,
+                playback_input_system
+                    .label(SimLabel::Playback)
+                    .after(SimLabel::Snapshot),
+            )
+            .add_system_to_stage(
+                SimStage,
+                record_config_system
+                    .label(SimLabel::Playback)
+                    .after(SimLabel::Snapshot),
+            )
+            .add_system_to_stage(
+                SimStage,
+                steer_player_system
+                    .label(SimLabel::Input)
+                    .after(SimLabel::Playback),
+            )
+            .add_system_to_stage(
+                SimStage,
+                change_direction_actor_system
+                    .label(SimLabel::Wander)
+                    .after(SimLabel::Input),
+            )
+            .add_system_to_stage(
+                SimStage,
+                change_direction_opponent_system
+                    .label(SimLabel::Steer)
+                    .after(SimLabel::Wander),
+            )
+            .add_system_to_stage(
+                SimStage,
+                update_flow_fields_system
+                    .label(SimLabel::Flow)
+                    .after(SimLabel::Steer),
+            )
+            .add_system_to_stage(
+                SimStage,
+                follow_pawn_system
+                    .label(SimLabel::Follow)
+                    .after(SimLabel::Flow),
+            )
+            .add_system_to_stage(
+                SimStage,
+                repulse_actor_system
+                    .label(SimLabel::Repulse)
+                    .after(SimLabel::Follow),
+            )
+            .add_system_to_stage(
+                SimStage,
+                move_actor_system
+                    .label(SimLabel::Move)
+                    .after(SimLabel::Repulse),
+            )
+            .add_system_to_stage(
+                SimStage,
+                move_pawn_system
+                    .label(SimLabel::Move)
+                    .after(SimLabel::Repulse),
+            )
+            .add_system_to_stage(
+                SimStage,
+                change_actor_faction_system
+                    .label(SimLabel::Convert)
+                    .after(SimLabel::Move),
+            )
+            .add_system_to_stage(
+                SimStage,
+                safe_zone_system
+                    .label(SimLabel::Zone)
+                    .after(SimLabel::Convert),
+            )
+            .add_system_to_stage(
+                SimStage,
+                countdown.label(SimLabel::Countdown).after(SimLabel::Zone),
+            )
+            .add_system_to_stage(SimStage, record_tick_stats_system.after(SimLabel::Convert));
+    }
+}
+
+/// An app playing a match with `config` on `MinimalPlugins`, one tick per update, for drivers
+/// that step the simulation themselves. `None` picks a random seed.
+///
+/// The match is set up on the first update, which runs its first tick too. Start the clock
+/// `paused` to set the match up without ticking, then unpause the [`SimClock`].
+pub fn headless_match(config: GameConfig, seed: Option<u64>, paused: bool) -> App {
+    let mut clock = SimClock::new(TickMode::Manual);
+    clock.paused = paused;
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(SimulationPlugin)
+        .insert_resource(config)
+        .insert_resource(SimSeed(seed))
+        .insert_resource(clock)
+        .add_startup_system(setup_match);
+    app
+}
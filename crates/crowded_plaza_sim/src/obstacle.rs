@@ -0,0 +1,227 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    boundary::{polygon_contains, polygon_edge},
+    config::check_range,
+    BoundaryEdge, ConfigError,
+};
+
+// how far outside the edge actors are put back when they ran into an obstacle, so they are
+// not inside again through rounding
+const OBSTACLE_SKIN: f32 = 0.01;
+
+/// What an obstacle is, only the game client tells them apart.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObstacleKind {
+    #[default]
+    Building,
+    Fountain,
+    Wall,
+}
+
+/// A static collider on the plaza. Actors steer around it and cannot pass through.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Obstacle {
+    pub kind: ObstacleKind,
+    /// Corners in units, in order, either way round.
+    pub corners: Vec<(f32, f32)>,
+}
+
+/// The layout of a plaza, loaded from a `.map.ron` file.
+///
+/// ```text
+/// (
+///     obstacles: [
+///         (kind: fountain, corners: [(-2.0, -2.0), (2.0, -2.0), (2.0, 2.0), (-2.0, 2.0)]),
+///     ],
+///     spawn_zones: [[(-15.0, -15.0), (15.0, -15.0), (15.0, -5.0), (-15.0, -5.0)]],
+/// )
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, TypeUuid)]
+#[uuid = "5b0f3c59-8d1e-4f0a-9a43-6c2e7d1b4e21"]
+#[serde(default, deny_unknown_fields)]
+pub struct PlazaMap {
+    pub obstacles: Vec<Obstacle>,
+    /// Polygons in units actors spawn in, in proportion to their area. Without any, actors
+    /// spawn within `action_region` of the center. Nobody spawns inside an obstacle either way.
+    pub spawn_zones: Vec<Vec<(f32, f32)>>,
+}
+
+// helper: whether the corners make a polygon the simulation can handle
+fn check_polygon(corners: &[(f32, f32)], value: &impl std::fmt::Debug) -> Result<(), ConfigError> {
+    let points = to_points(corners);
+    // twice the signed area
+    let area: f32 = points
+        .iter()
+        .enumerate()
+        .map(|(i, a)| a.perp_dot(points[(i + 1) % points.len()]))
+        .sum();
+    if points.len() < 3 || !area.is_finite() || area.abs() < f32::EPSILON {
+        return Err(ConfigError::OutOfRange {
+            field: "map",
+            value: format!("{:?}", value),
+            expected: "polygons of at least 3 corners around some area",
+        });
+    }
+    Ok(())
+}
+
+// helper: the corners of a polygon from a map
+fn to_points(corners: &[(f32, f32)]) -> Vec<Vec2> {
+    corners.iter().map(|(x, y)| Vec2::new(*x, *y)).collect()
+}
+
+impl PlazaMap {
+    /// Parse and validate a map from RON.
+    pub fn from_ron(bytes: &[u8]) -> Result<Self, ConfigError> {
+        let map: PlazaMap =
+            ron::de::from_bytes(bytes).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        map.validate()?;
+        Ok(map)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        check_range(
+            "map",
+            self.obstacles.len(),
+            0,
+            1000,
+            "at most 1000 obstacles",
+        )?;
+        for obstacle in self.obstacles.iter() {
+            check_polygon(&obstacle.corners, obstacle)?;
+        }
+        for zone in self.spawn_zones.iter() {
+            check_polygon(zone, zone)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct PlazaMapLoader;
+
+impl AssetLoader for PlazaMapLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let map = PlazaMap::from_ron(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(map));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["map.ron"]
+    }
+}
+
+// an obstacle ready for collision checks, with the box around it to skip it quickly
+#[derive(Clone, Debug)]
+struct Collider {
+    corners: Vec<Vec2>,
+    min: Vec2,
+    max: Vec2,
+}
+
+impl Collider {
+    fn near(&self, position: Vec2, distance: f32) -> bool {
+        position.cmpge(self.min - distance).all() && position.cmple(self.max + distance).all()
+    }
+}
+
+/// The obstacles of the current match, set up by [`setup_match`](crate::setup_match) from the
+/// config's map.
+#[derive(Clone, Debug, Default)]
+pub struct Obstacles {
+    colliders: Vec<Collider>,
+}
+
+impl Obstacles {
+    pub fn new(map: &PlazaMap) -> Self {
+        let colliders = map
+            .obstacles
+            .iter()
+            .map(|obstacle| {
+                let corners = to_points(&obstacle.corners);
+                let min = corners
+                    .iter()
+                    .fold(Vec2::splat(f32::INFINITY), |a, b| a.min(*b));
+                let max = corners
+                    .iter()
+                    .fold(Vec2::splat(f32::NEG_INFINITY), |a, b| a.max(*b));
+                Collider { corners, min, max }
+            })
+            .collect();
+        Obstacles { colliders }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colliders.is_empty()
+    }
+
+    /// Whether `position` is inside any obstacle.
+    pub fn contains(&self, position: Vec2) -> bool {
+        self.colliders.iter().any(|collider| {
+            collider.near(position, 0.0) && polygon_contains(&collider.corners, position)
+        })
+    }
+
+    /// The closest point on the edge of each obstacle within `distance` of `position`, the
+    /// normal pointing out of the obstacle and the depth positive inside it.
+    pub fn edges_near(
+        &self,
+        position: Vec2,
+        distance: f32,
+    ) -> impl Iterator<Item = BoundaryEdge> + '_ {
+        self.colliders
+            .iter()
+            .filter(move |collider| collider.near(position, distance))
+            .map(move |collider| polygon_edge(&collider.corners, position))
+            .filter(move |edge| edge.depth > -distance)
+    }
+
+    /// `position`, or just outside the edge of the obstacle it is inside of, like actors that
+    /// move into one are put back.
+    pub fn push_out(&self, position: Vec2) -> Vec2 {
+        self.edges_near(position, 0.0)
+            .fold(position, |_, edge| edge.point + edge.normal * OBSTACLE_SKIN)
+    }
+
+    /// `steer` without the part heading into obstacles closer than `margin`, plus a push away
+    /// from them growing from nothing at `margin` to `push` on their edge.
+    pub fn steer_around(&self, position: Vec2, steer: Vec2, margin: f32, push: f32) -> Vec2 {
+        let mut steer = steer;
+        for edge in self.edges_near(position, margin) {
+            let into = steer.dot(edge.normal);
+            if into < 0.0 {
+                steer -= edge.normal * into;
+            }
+            steer += edge.normal * push * (1.0 + edge.depth / margin);
+        }
+        steer
+    }
+}
+
+// helper: put an actor that moved into an obstacle back on its edge, sliding along it
+pub(crate) fn apply_obstacles(obstacles: &Obstacles, translation: &mut Vec3, velocity: &mut Vec3) {
+    let position = translation.truncate();
+    for edge in obstacles.edges_near(position, 0.0) {
+        *translation = (edge.point + edge.normal * OBSTACLE_SKIN).extend(translation.z);
+        let into = velocity.truncate().dot(edge.normal);
+        if into < 0.0 {
+            *velocity -= (edge.normal * into).extend(0.0);
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use bevy::prelude::*;
+use std::{f32::consts::TAU, fmt};
+
+use crate::{ConfigError, GameConfig, PlayerInput, SimClock};
+
+const MAGIC: &[u8; 4] = b"CPRP";
+// 2: the config gained the boundary, the map and the flocking fields, and matches with the
+// same config play differently since
+// 3: config changes during the match, e.g. a hot-reloaded config file, are recorded too
+const FORMAT_VERSION: u8 = 3;
+
+/// A steering direction, quantized so a recorded match replays bit for bit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Heading(pub u16);
+
+impl Heading {
+    /// The heading closest to `direction`, which does not need to be normalized.
+    pub fn from_direction(direction: Vec2) -> Self {
+        let turns = direction.y.atan2(direction.x) / TAU;
+        Heading((turns.rem_euclid(1.0) * 65536.0).round() as u32 as u16)
+    }
+
+    /// Unit vector pointing along the heading.
+    pub fn direction(&self) -> Vec2 {
+        let angle = self.0 as f32 / 65536.0 * TAU;
+        Vec2::new(angle.cos(), angle.sin())
+    }
+}
+
+/// Everything needed to play a match again: the seed, the config and its changes, and the
+/// player's steering.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    pub config: GameConfig,
+    /// The tick the match ended on.
+    pub end_tick: u64,
+    /// `(tick, heading)` for every tick the player steered, in tick order.
+    pub inputs: Vec<(u64, Heading)>,
+    /// `(tick, config)` for every tick the config changed on, in tick order. The config applies
+    /// from that tick on.
+    pub config_changes: Vec<(u64, GameConfig)>,
+}
+
+/// Why a replay file could not be read.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReplayError {
+    /// Not a replay file.
+    BadMagic,
+    /// Written by a newer version of the game.
+    UnsupportedVersion(u8),
+    /// The file ends early.
+    Truncated,
+    /// The recorded config does not load anymore.
+    Config(ConfigError),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::BadMagic => write!(f, "not a replay file"),
+            ReplayError::UnsupportedVersion(version) => {
+                write!(f, "unsupported replay format version {}", version)
+            }
+            ReplayError::Truncated => write!(f, "replay file is truncated"),
+            ReplayError::Config(e) => write!(f, "replay has an invalid config: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+// helper: LEB128, most ticks and deltas fit in a byte or two
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+// helper: a config as its RON length and text
+fn write_config(bytes: &mut Vec<u8>, config: &GameConfig) {
+    let config = ron::to_string(config).expect("game config always serializes");
+    write_varint(bytes, config.len() as u64);
+    bytes.extend_from_slice(config.as_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ReplayError> {
+        if self.bytes.len() < len {
+            return Err(ReplayError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn varint(&mut self) -> Result<u64, ReplayError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(ReplayError::Truncated)
+    }
+
+    fn config(&mut self) -> Result<GameConfig, ReplayError> {
+        let len = self.varint()? as usize;
+        GameConfig::from_ron(self.take(len)?).map_err(ReplayError::Config)
+    }
+}
+
+impl Replay {
+    /// Encode as `CPRP`, format version, seed, end tick, the config as RON, the inputs as tick
+    /// deltas and headings, then the config changes as tick deltas and RON. Integers are little
+    /// endian or LEB128 varints.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let config = ron::to_string(&self.config).expect("game config always serializes");
+        let mut bytes = Vec::with_capacity(32 + config.len() + self.inputs.len() * 3);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(FORMAT_VERSION);
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        write_varint(&mut bytes, self.end_tick);
+        write_config(&mut bytes, &self.config);
+        write_varint(&mut bytes, self.inputs.len() as u64);
+        let mut last_tick = 0;
+        for (tick, heading) in self.inputs.iter() {
+            write_varint(&mut bytes, tick - last_tick);
+            bytes.extend_from_slice(&heading.0.to_le_bytes());
+            last_tick = *tick;
+        }
+        write_varint(&mut bytes, self.config_changes.len() as u64);
+        let mut last_tick = 0;
+        for (tick, config) in self.config_changes.iter() {
+            write_varint(&mut bytes, tick - last_tick);
+            write_config(&mut bytes, config);
+            last_tick = *tick;
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ReplayError> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(ReplayError::BadMagic);
+        }
+        let version = reader.take(1)?[0];
+        if version != FORMAT_VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+        let seed = u64::from_le_bytes(reader.take(8)?.try_into().unwrap());
+        let end_tick = reader.varint()?;
+        let config = reader.config()?;
+        let input_count = reader.varint()? as usize;
+        // don't trust the count for the allocation, every input takes at least 3 bytes
+        let mut inputs = Vec::with_capacity(input_count.min(reader.bytes.len() / 3));
+        let mut tick = 0;
+        for _ in 0..input_count {
+            tick = reader.varint()?.saturating_add(tick);
+            let heading = u16::from_le_bytes(reader.take(2)?.try_into().unwrap());
+            inputs.push((tick, Heading(heading)));
+        }
+        let change_count = reader.varint()? as usize;
+        // every change takes at least 2 bytes
+        let mut config_changes = Vec::with_capacity(change_count.min(reader.bytes.len() / 2));
+        let mut tick = 0;
+        for _ in 0..change_count {
+            tick = reader.varint()?.saturating_add(tick);
+            config_changes.push((tick, reader.config()?));
+        }
+        Ok(Replay {
+            seed,
+            config,
+            end_tick,
+            inputs,
+            config_changes,
+        })
+    }
+
+    /// The heading the player steered to on `tick`, if any.
+    pub fn input_at(&self, tick: u64) -> Option<Heading> {
+        self.inputs
+            .binary_search_by_key(&tick, |(input_tick, _)| *input_tick)
+            .ok()
+            .map(|index| self.inputs[index].1)
+    }
+
+    /// The config the match changed to on `tick`, if any.
+    pub fn config_change_at(&self, tick: u64) -> Option<&GameConfig> {
+        self.config_changes
+            .binary_search_by_key(&tick, |(change_tick, _)| *change_tick)
+            .ok()
+            .map(|index| &self.config_changes[index].1)
+    }
+}
+
+/// While this resource exists, every steering input applied to the player and every change of
+/// the [`GameConfig`] is recorded. Start it right after [`setup_match`](crate::setup_match), with
+/// the match's seed and config.
+pub struct ReplayRecorder(pub Replay);
+
+impl ReplayRecorder {
+    pub fn new(seed: u64, config: GameConfig) -> Self {
+        ReplayRecorder(Replay {
+            seed,
+            config,
+            end_tick: 0,
+            inputs: Vec::new(),
+            config_changes: Vec::new(),
+        })
+    }
+
+    // helper: the config the recorded match has now
+    fn current_config(&self) -> &GameConfig {
+        self.0
+            .config_changes
+            .last()
+            .map_or(&self.0.config, |(_, config)| config)
+    }
+}
+
+/// While this resource exists, the player's steering and the config changes come from the replay
+/// instead of [`PlayerInput`] set by the game and its config files.
+pub struct ReplayPlayback(pub Replay);
+
+// system: feed the recorded steering and config of this tick to the simulation
+pub fn playback_input_system(
+    clock: Res<SimClock>,
+    playback: Option<Res<ReplayPlayback>>,
+    mut input: ResMut<PlayerInput>,
+    mut config: ResMut<GameConfig>,
+) {
+    if let Some(playback) = playback {
+        input.steer.clear();
+        if let Some(heading) = playback.0.input_at(clock.tick()) {
+            input.steer.insert(0, heading);
+        }
+        if let Some(change) = playback.0.config_change_at(clock.tick()) {
+            *config = change.clone();
+        }
+    }
+}
+
+// system: record the config when it changed since the last tick, e.g. a hot reload
+pub fn record_config_system(
+    config: Res<GameConfig>,
+    clock: Res<SimClock>,
+    recorder: Option<ResMut<ReplayRecorder>>,
+) {
+    if let Some(mut recorder) = recorder {
+        if config.is_changed() && *config != *recorder.current_config() {
+            recorder
+                .0
+                .config_changes
+                .push((clock.tick(), config.clone()));
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use rand::{thread_rng, Error, Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// Seed for the next match, `None` rolls a fresh one every match.
+///
+/// Insert this before the match starts to make it reproducible: the same seed with the same
+/// player input always plays out the same way.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SimSeed(pub Option<u64>);
+
+/// The random number generator every random decision of a match draws from.
+///
+/// It is reseeded by [`crate::setup_match`], so systems must never fall back to `thread_rng()`.
+pub struct SimRng {
+    seed: u64,
+    rng: ChaCha8Rng,
+}
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        SimRng {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    /// Create a generator from `seed`, or from a random seed if there is none.
+    pub fn from_sim_seed(seed: SimSeed) -> Self {
+        Self::new(seed.0.unwrap_or_else(|| thread_rng().gen()))
+    }
+
+    /// The seed this generator started from, report it to reproduce a match.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl Default for SimRng {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl RngCore for SimRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
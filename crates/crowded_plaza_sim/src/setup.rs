@@ -0,0 +1,225 @@
+use bevy::prelude::*;
+use rand::Rng;
+use std::time::Duration;
+
+use crate::{
+    boundary::polygon_contains, Actor, ActorSpace, Conversion, Faction, FactionRegistry,
+    FlowFields, GameConfig, MatchEvent, Obstacles, OpponentController, Pawn, PlayerController,
+    PlazaMap, PreviousTranslation, SafeZone, SimClock, SimRng, SimSeed, Strategy, TICK_SECONDS,
+};
+
+// random points tried for a spawn outside of obstacles, the last one is pushed out of the
+// obstacle it is in
+const SPAWN_ATTEMPTS: usize = 64;
+
+pub struct Countdown {
+    pub main_timer: Timer,
+}
+
+// a spawn zone of the map, with the box around it to pick points in
+struct SpawnZone {
+    corners: Vec<Vec2>,
+    min: Vec2,
+    max: Vec2,
+    area: f32,
+}
+
+impl SpawnZone {
+    fn for_map(map: &PlazaMap) -> Vec<SpawnZone> {
+        map.spawn_zones
+            .iter()
+            .map(|zone| {
+                let corners: Vec<Vec2> = zone.iter().map(|(x, y)| Vec2::new(*x, *y)).collect();
+                let min = corners
+                    .iter()
+                    .fold(Vec2::splat(f32::INFINITY), |a, b| a.min(*b));
+                let max = corners
+                    .iter()
+                    .fold(Vec2::splat(f32::NEG_INFINITY), |a, b| a.max(*b));
+                let area = corners
+                    .iter()
+                    .enumerate()
+                    .map(|(i, a)| a.perp_dot(corners[(i + 1) % corners.len()]))
+                    .sum::<f32>()
+                    .abs()
+                    * 0.5;
+                SpawnZone {
+                    corners,
+                    min,
+                    max,
+                    area,
+                }
+            })
+            .collect()
+    }
+}
+
+// helper: a random point within `region` of the center, or in one of the `zones` picked by
+// area, and outside of obstacles. Draws the same numbers as before maps when there are neither
+fn spawn_point(
+    rng: &mut SimRng,
+    region: f32,
+    zones: &[SpawnZone],
+    obstacles: &Obstacles,
+) -> (f32, f32) {
+    let total_area: f32 = zones.iter().map(|zone| zone.area).sum();
+    let mut point = (0.0, 0.0);
+    for _ in 0..SPAWN_ATTEMPTS {
+        point = if zones.is_empty() {
+            (
+                rng.gen_range(-region..region),
+                rng.gen_range(-region..region),
+            )
+        } else {
+            let mut pick = rng.gen_range(0.0..total_area);
+            let zone = zones
+                .iter()
+                .find(|zone| {
+                    pick -= zone.area;
+                    pick < 0.0
+                })
+                .unwrap_or(&zones[zones.len() - 1]);
+            let mut inside = zone.min;
+            for _ in 0..SPAWN_ATTEMPTS {
+                inside = Vec2::new(
+                    rng.gen_range(zone.min.x..=zone.max.x),
+                    rng.gen_range(zone.min.y..=zone.max.y),
+                );
+                if polygon_contains(&zone.corners, inside) {
+                    break;
+                }
+            }
+            (inside.x, inside.y)
+        };
+        if !obstacles.contains(Vec2::new(point.0, point.1)) {
+            return point;
+        }
+    }
+    let point = obstacles.push_out(Vec2::new(point.0, point.1));
+    (point.x, point.y)
+}
+
+// system: spawn the simulated part of a match, actors, opponent pawns and the player pawn
+#[allow(clippy::too_many_arguments)]
+pub fn setup_match(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    mut registry: ResMut<FactionRegistry>,
+    seed: Res<SimSeed>,
+    mut rng: ResMut<SimRng>,
+    mut clock: ResMut<SimClock>,
+    mut spatial: ResMut<ActorSpace>,
+    mut obstacles: ResMut<Obstacles>,
+    mut flow: ResMut<FlowFields>,
+) {
+    let region: f32 = config.action_region;
+
+    // every match reseeds, so a reported seed is enough to reproduce it
+    // logged rather than printed, headless drivers may speak a protocol on stdout
+    *rng = SimRng::from_sim_seed(*seed);
+    info!("Match seed {0}", rng.seed());
+    clock.reset();
+    // empty until the first tick, the backend may have changed since the last match
+    *spatial = ActorSpace::for_config(&config);
+    *obstacles = Obstacles::new(&config.map);
+    *flow = FlowFields::new(&config, &obstacles);
+    let zones = SpawnZone::for_map(&config.map);
+
+    // timer count down
+    let timer = Timer::from_seconds(config.game_time, false);
+    let count_down = Countdown { main_timer: timer };
+    commands.insert_resource(count_down);
+    match SafeZone::for_config(&config) {
+        Some(zone) => commands.insert_resource(zone),
+        None => commands.remove_resource::<SafeZone>(),
+    }
+
+    // factions, opponent count may have changed since the last match
+    *registry = FactionRegistry::new(
+        config.actor_count,
+        config.player_count,
+        config.opponent_count,
+    );
+
+    // actors
+    for _ in 0..config.actor_count {
+        let (x, y) = spawn_point(&mut rng, region, &zones, &obstacles);
+        let dir = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
+        commands
+            .spawn_bundle(TransformBundle::from_transform(Transform::from_xyz(
+                x, y, 0.0,
+            )))
+            .insert(Actor {
+                faction: Faction::Neutral,
+                velocity: dir.normalize() * config.wander_speed,
+                accleration: Vec3::new(0.0, 0.0, 0.0),
+            })
+            .insert(PreviousTranslation(Vec3::new(x, y, 0.0)))
+            .insert(Conversion::default());
+    }
+
+    // opponents
+    for id in 0..config.opponent_count as u32 {
+        // strategies take turns, bots without one wander
+        let strategy = config
+            .opponent_strategies
+            .get(id as usize % config.opponent_strategies.len().max(1))
+            .copied()
+            .unwrap_or(Strategy::Wander);
+        let (x, y) = spawn_point(&mut rng, region, &zones, &obstacles);
+        let dir = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
+        let leader = commands
+            .spawn_bundle(TransformBundle::from_transform(Transform::from_xyz(
+                x, y, 0.0,
+            )))
+            .insert(Actor {
+                faction: Faction::Bot(id),
+                velocity: dir.normalize() * config.pawn_speed,
+                accleration: Vec3::new(0.0, 0.0, 0.0),
+            })
+            .insert(PreviousTranslation(Vec3::new(x, y, 0.0)))
+            .insert(Conversion::default())
+            .insert(Pawn {})
+            .insert(OpponentController::new(
+                strategy.brain(&config),
+                config.opponent_difficulty,
+            ))
+            .id();
+        registry.get_mut(Faction::Bot(id)).unwrap().leader = Some(leader);
+    }
+
+    // players, the first one starts near the center, other seats anywhere
+    for id in 0..config.player_count as u32 {
+        let (x, y) = if id == 0 {
+            spawn_point(&mut rng, 5.0, &[], &obstacles)
+        } else {
+            spawn_point(&mut rng, region, &zones, &obstacles)
+        };
+        let dir = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
+        let leader = commands
+            .spawn_bundle(TransformBundle::from_transform(Transform::from_xyz(
+                x, y, 0.0,
+            )))
+            .insert(Actor {
+                faction: Faction::Player(id),
+                velocity: dir.normalize() * config.pawn_speed,
+                accleration: Vec3::new(0.0, 0.0, 0.0),
+            })
+            .insert(PreviousTranslation(Vec3::new(x, y, 0.0)))
+            .insert(Conversion::default())
+            .insert(Pawn {})
+            .insert(PlayerController(id))
+            .id();
+        registry.get_mut(Faction::Player(id)).unwrap().leader = Some(leader);
+    }
+}
+
+// system: tick the match timer, if there is a match running
+pub fn countdown(countdown: Option<ResMut<Countdown>>, mut match_events: EventWriter<MatchEvent>) {
+    if let Some(mut countdown) = countdown {
+        let tick = Duration::from_secs_f32(TICK_SECONDS);
+        if countdown.main_timer.tick(tick).just_finished() {
+            match_events.send(MatchEvent::TimeUp);
+        }
+    }
+}
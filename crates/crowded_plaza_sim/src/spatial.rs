@@ -0,0 +1,321 @@
+use bevy::prelude::*;
+use bevy_spatial::{KDTreeAccess2D, KDTreePlugin2D, SpatialAccess};
+use serde::{Deserialize, Serialize};
+use std::{
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{Actor, GameConfig};
+
+// a grid has at most this many cells per point, actors that stray far apart get larger cells
+const MAX_CELLS_PER_POINT: usize = 4;
+
+/// Finds the actors near a point. Positions are taken on the plane, `z` is ignored and comes
+/// back as 0.
+pub trait SpatialIndex: Send + Sync {
+    /// Replace every point in the index.
+    fn recreate(&mut self, points: Vec<(Vec3, Entity)>);
+
+    /// Every point closer than `distance` to `loc`.
+    fn within_distance(&self, loc: Vec3, distance: f32) -> Vec<(Vec3, Entity)>;
+
+    /// The `k` points nearest to `loc`, nearest first.
+    fn k_nearest_neighbour(&self, loc: Vec3, k: usize) -> Vec<(Vec3, Entity)>;
+}
+
+impl SpatialIndex for KDTreeAccess2D<Actor> {
+    fn recreate(&mut self, points: Vec<(Vec3, Entity)>) {
+        SpatialAccess::recreate(self, points);
+    }
+
+    fn within_distance(&self, loc: Vec3, distance: f32) -> Vec<(Vec3, Entity)> {
+        SpatialAccess::within_distance(self, loc, distance)
+    }
+
+    fn k_nearest_neighbour(&self, loc: Vec3, k: usize) -> Vec<(Vec3, Entity)> {
+        SpatialAccess::k_nearest_neighbour(self, loc, k)
+    }
+}
+
+/// The [`SpatialIndex`] of a match, as picked in the [`GameConfig`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpatialBackend {
+    /// A kd-tree, how actors always found their neighbors.
+    #[default]
+    KdTree,
+    /// A [`UniformGrid`] with cells of [`GameConfig::neighbor_threshold`].
+    UniformGrid,
+}
+
+impl FromStr for SpatialBackend {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "kd_tree" => Ok(SpatialBackend::KdTree),
+            "uniform_grid" => Ok(SpatialBackend::UniformGrid),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A grid of square cells over the points, with the points of each cell next to each other in
+/// memory. Rebuilding is a counting sort, and a query reads a few runs of points instead of
+/// walking a tree. Best when most queries reach about one cell around the point.
+///
+/// It finds the same points as the kd-tree, but in cell order, so a match plays differently
+/// with each of them.
+pub struct UniformGrid {
+    cell_size: f32,
+    // `cell_size`, or larger while the points are spread too far for that many cells
+    cell: f32,
+    // corner of the first cell
+    origin: Vec2,
+    columns: usize,
+    rows: usize,
+    // where the points of each cell start in `points`, row by row, and where the last one ends
+    cell_starts: Vec<u32>,
+    // sorted by cell, in the order they were given within a cell
+    points: Vec<(Vec2, Entity)>,
+    // cell of every point while rebuilding
+    point_cells: Vec<u32>,
+}
+
+impl UniformGrid {
+    pub fn new(cell_size: f32) -> Self {
+        UniformGrid {
+            cell_size,
+            cell: cell_size,
+            origin: Vec2::ZERO,
+            columns: 0,
+            rows: 0,
+            cell_starts: vec![0],
+            points: Vec::new(),
+            point_cells: Vec::new(),
+        }
+    }
+
+    // helper: the column and row `loc` falls into, outside of the grid too
+    fn cell_of(&self, loc: Vec2) -> (i64, i64) {
+        let offset = (loc - self.origin) / self.cell;
+        (offset.x.floor() as i64, offset.y.floor() as i64)
+    }
+
+    // helper: the points of the cells `first..=last` of `row`, which lie next to each other
+    fn run(&self, row: i64, first: i64, last: i64) -> &[(Vec2, Entity)] {
+        let first = first.max(0);
+        let last = last.min(self.columns as i64 - 1);
+        if row < 0 || row >= self.rows as i64 || first > last {
+            return &[];
+        }
+        let row_start = row as usize * self.columns;
+        let start = self.cell_starts[row_start + first as usize] as usize;
+        let end = self.cell_starts[row_start + last as usize + 1] as usize;
+        &self.points[start..end]
+    }
+}
+
+// helper: squared distance on the plane, summed like the kd-tree does so both agree at the edge
+fn distance_squared(a: Vec2, b: Vec2) -> f32 {
+    let dx = a.x - b.x;
+    let dy = a.y - b.y;
+    dx * dx + dy * dy
+}
+
+impl SpatialIndex for UniformGrid {
+    fn recreate(&mut self, points: Vec<(Vec3, Entity)>) {
+        self.points.clear();
+        self.point_cells.clear();
+        self.cell_starts.clear();
+        self.cell_starts.push(0);
+        self.columns = 0;
+        self.rows = 0;
+        if points.is_empty() {
+            return;
+        }
+
+        let (min, max) = points.iter().fold(
+            (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+            |(min, max), (position, _)| {
+                (min.min(position.truncate()), max.max(position.truncate()))
+            },
+        );
+        let extent = max - min;
+        self.origin = min;
+        self.cell = self.cell_size;
+        let max_cells = points.len() * MAX_CELLS_PER_POINT;
+        loop {
+            self.columns = (extent.x / self.cell) as usize + 1;
+            self.rows = (extent.y / self.cell) as usize + 1;
+            if self.columns.saturating_mul(self.rows) <= max_cells {
+                break;
+            }
+            self.cell *= 2.0;
+        }
+
+        // count the points of every cell, one cell ahead, then sum up to where each cell starts
+        self.cell_starts.resize(self.columns * self.rows + 1, 0);
+        for (position, _) in points.iter() {
+            let (column, row) = self.cell_of(position.truncate());
+            // the far edge rounds into the last cell
+            let column = (column as usize).min(self.columns - 1);
+            let row = (row as usize).min(self.rows - 1);
+            let cell = row * self.columns + column;
+            self.point_cells.push(cell as u32);
+            self.cell_starts[cell + 1] += 1;
+        }
+        for cell in 1..self.cell_starts.len() {
+            self.cell_starts[cell] += self.cell_starts[cell - 1];
+        }
+
+        let mut next = self.cell_starts.clone();
+        self.points
+            .resize(points.len(), (Vec2::ZERO, Entity::from_raw(0)));
+        for ((position, entity), cell) in points.into_iter().zip(self.point_cells.iter()) {
+            let index = &mut next[*cell as usize];
+            self.points[*index as usize] = (position.truncate(), entity);
+            *index += 1;
+        }
+    }
+
+    fn within_distance(&self, loc: Vec3, distance: f32) -> Vec<(Vec3, Entity)> {
+        let loc = loc.truncate();
+        let (first_column, first_row) = self.cell_of(loc - Vec2::splat(distance));
+        let (last_column, last_row) = self.cell_of(loc + Vec2::splat(distance));
+        let radius_squared = distance * distance;
+        let mut found = Vec::new();
+        for row in first_row.max(0)..=last_row.min(self.rows as i64 - 1) {
+            for (position, entity) in self.run(row, first_column, last_column) {
+                if distance_squared(*position, loc) < radius_squared {
+                    found.push((position.extend(0.0), *entity));
+                }
+            }
+        }
+        found
+    }
+
+    fn k_nearest_neighbour(&self, loc: Vec3, k: usize) -> Vec<(Vec3, Entity)> {
+        if k == 0 || self.points.is_empty() {
+            return Vec::new();
+        }
+        let loc = loc.truncate();
+        // squared distance, position and entity, nearest first
+        let mut nearest: Vec<(f32, Vec2, Entity)> = Vec::with_capacity(k + 1);
+
+        // rings of cells around the one `loc` is in, until no closer point can be left
+        let (column, row) = self.cell_of(loc);
+        let last_ring = [
+            column,
+            self.columns as i64 - 1 - column,
+            row,
+            self.rows as i64 - 1 - row,
+        ]
+        .into_iter()
+        .map(i64::abs)
+        .max()
+        .unwrap_or(0);
+        for ring in 0..=last_ring {
+            if nearest.len() == k {
+                // every point in this ring is at least this far along one axis
+                let reach = (ring - 1).max(0) as f32 * self.cell;
+                if reach * reach > nearest[k - 1].0 {
+                    break;
+                }
+            }
+            for ring_row in row - ring..=row + ring {
+                let runs = if ring_row == row - ring || ring_row == row + ring {
+                    [self.run(ring_row, column - ring, column + ring), &[][..]]
+                } else {
+                    [
+                        self.run(ring_row, column - ring, column - ring),
+                        self.run(ring_row, column + ring, column + ring),
+                    ]
+                };
+                for (position, entity) in runs.into_iter().flatten() {
+                    let distance = distance_squared(*position, loc);
+                    if nearest.len() == k && distance >= nearest[k - 1].0 {
+                        continue;
+                    }
+                    // after the points as near, so the first one found wins a tie
+                    let index = nearest.partition_point(|(other, _, _)| *other <= distance);
+                    nearest.insert(index, (distance, *position, *entity));
+                    nearest.truncate(k);
+                }
+            }
+        }
+        nearest
+            .into_iter()
+            .map(|(_, position, entity)| (position.extend(0.0), entity))
+            .collect()
+    }
+}
+
+/// The neighbors of every actor, rebuilt from their positions at the start of each tick by
+/// [`rebuild_actor_space_system`](crate::rebuild_actor_space_system). Queries go to the
+/// [`SpatialIndex`] of the [`SpatialBackend`] it was made with, and are counted for
+/// diagnostics.
+pub struct ActorSpace {
+    backend: SpatialBackend,
+    index: Box<dyn SpatialIndex>,
+    // systems query in parallel
+    queries: AtomicUsize,
+}
+
+impl ActorSpace {
+    /// An empty index, `cell_size` is only used by the [`UniformGrid`].
+    pub fn new(backend: SpatialBackend, cell_size: f32) -> Self {
+        let index: Box<dyn SpatialIndex> = match backend {
+            SpatialBackend::KdTree => {
+                Box::new(KDTreeAccess2D::from(KDTreePlugin2D::<Actor>::default()))
+            }
+            SpatialBackend::UniformGrid => Box::new(UniformGrid::new(cell_size)),
+        };
+        ActorSpace {
+            backend,
+            index,
+            queries: AtomicUsize::new(0),
+        }
+    }
+
+    /// An empty index for the match `config` sets up.
+    pub fn for_config(config: &GameConfig) -> Self {
+        ActorSpace::new(config.spatial_index, config.neighbor_threshold)
+    }
+
+    pub fn backend(&self) -> SpatialBackend {
+        self.backend
+    }
+
+    /// Neighbor queries since the index was last rebuilt.
+    pub fn queries(&self) -> usize {
+        self.queries.load(Ordering::Relaxed)
+    }
+
+    /// Replace every point in the index, see [`SpatialIndex::recreate`].
+    pub fn recreate(&mut self, points: Vec<(Vec3, Entity)>) {
+        *self.queries.get_mut() = 0;
+        self.index.recreate(points);
+    }
+
+    /// See [`SpatialIndex::within_distance`].
+    pub fn within_distance(&self, loc: Vec3, distance: f32) -> Vec<(Vec3, Entity)> {
+        let _span = info_span!("within_distance").entered();
+        self.queries.fetch_add(1, Ordering::Relaxed);
+        self.index.within_distance(loc, distance)
+    }
+
+    /// See [`SpatialIndex::k_nearest_neighbour`].
+    pub fn k_nearest_neighbour(&self, loc: Vec3, k: usize) -> Vec<(Vec3, Entity)> {
+        let _span = info_span!("k_nearest_neighbour").entered();
+        self.queries.fetch_add(1, Ordering::Relaxed);
+        self.index.k_nearest_neighbour(loc, k)
+    }
+}
+
+impl Default for ActorSpace {
+    fn default() -> Self {
+        ActorSpace::for_config(&GameConfig::default())
+    }
+}
@@ -0,0 +1,181 @@
+use bevy::{
+    ecs::schedule::ShouldRun,
+    prelude::*,
+    utils::{Duration, Instant},
+};
+
+use crate::{
+    Actor, ActorSpace, MAX_TICKS_PER_UPDATE, SEEK_TICKS_PER_UPDATE, TICK_SECONDS,
+    UPDATE_TICK_BUDGET,
+};
+
+/// The stage all simulation systems run in, once per fixed tick.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, StageLabel)]
+pub struct SimStage;
+
+/// How the [`SimClock`] advances.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TickMode {
+    /// Tick as often as real time has passed, catching up at most `MAX_TICKS_PER_UPDATE` ticks per app update.
+    RealTime,
+    /// Run exactly one tick per app update, for headless drivers that step the app themselves.
+    Manual,
+}
+
+/// Drives the fixed timestep of the simulation, see [`SimStage`].
+pub struct SimClock {
+    pub mode: TickMode,
+    /// How much faster than real time to tick in [`TickMode::RealTime`].
+    pub speed: f32,
+    pub paused: bool,
+    /// No ticks run past this one, e.g. the end of a replay.
+    pub last_tick: Option<u64>,
+    /// Wall time the ticks of one app update may take, at least one tick runs. Ticks that don't
+    /// fit are dropped in real time and put off to the next update while seeking, so a large
+    /// crowd slows the match down instead of the frame rate. `None` runs every tick that is due.
+    pub budget: Option<Duration>,
+    seek_target: Option<u64>,
+    tick: u64,
+    accumulator: f64,
+    ticks_this_update: u32,
+    update_started: Instant,
+    looping: bool,
+}
+
+impl SimClock {
+    pub fn new(mode: TickMode) -> Self {
+        SimClock {
+            mode,
+            speed: 1.0,
+            paused: false,
+            last_tick: None,
+            budget: Some(UPDATE_TICK_BUDGET),
+            seek_target: None,
+            tick: 0,
+            accumulator: 0.0,
+            ticks_this_update: 0,
+            update_started: Instant::now(),
+            looping: false,
+        }
+    }
+
+    /// Ticks run since the match started.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Ticks the last app update ran.
+    pub fn ticks_this_update(&self) -> u32 {
+        self.ticks_this_update
+    }
+
+    /// How far real time is into the next tick, from 0 to 1, for interpolating rendered positions.
+    pub fn overstep_fraction(&self) -> f32 {
+        (self.accumulator / TICK_SECONDS as f64) as f32
+    }
+
+    /// Run ticks as fast as possible until `tick`, ignoring pause and speed. Seeking backwards
+    /// needs the match to be set up again first, which keeps the seek.
+    pub fn seek(&mut self, tick: u64) {
+        self.seek_target = Some(tick);
+    }
+
+    pub fn is_seeking(&self) -> bool {
+        self.seek_target.is_some()
+    }
+
+    /// Start counting from zero again, called when a match is set up. Speed, pause and a
+    /// pending seek are kept.
+    pub fn reset(&mut self) {
+        self.tick = 0;
+        self.accumulator = 0.0;
+    }
+}
+
+impl Default for SimClock {
+    fn default() -> Self {
+        Self::new(TickMode::RealTime)
+    }
+}
+
+/// Where an actor was at the start of the current tick, rendering interpolates from here to its `Transform`.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct PreviousTranslation(pub Vec3);
+
+// run criteria: loop the simulation stage once for every fixed tick that is due
+pub fn sim_tick_criteria(time: Res<Time>, mut clock: ResMut<SimClock>) -> ShouldRun {
+    let at_end = matches!(clock.last_tick, Some(last) if clock.tick >= last);
+    if matches!(clock.seek_target, Some(target) if clock.tick >= target || at_end) {
+        clock.seek_target = None;
+    }
+    let seeking = clock.is_seeking();
+
+    if !clock.looping {
+        clock.ticks_this_update = 0;
+        clock.update_started = Instant::now();
+        if !seeking && !clock.paused && !at_end {
+            match clock.mode {
+                TickMode::RealTime => {
+                    clock.accumulator += time.delta_seconds_f64() * clock.speed as f64
+                }
+                TickMode::Manual => clock.accumulator = TICK_SECONDS as f64,
+            }
+        }
+    }
+
+    // faster playback needs more ticks per update to keep up
+    let max_ticks = MAX_TICKS_PER_UPDATE * clock.speed.max(1.0).ceil() as u32;
+    let step = TICK_SECONDS as f64;
+    let over_budget = clock.ticks_this_update > 0
+        && clock
+            .budget
+            .is_some_and(|budget| clock.update_started.elapsed() >= budget);
+    let due = if at_end || over_budget {
+        false
+    } else if seeking {
+        // spread long seeks over several updates, so the app stays responsive
+        clock.accumulator = 0.0;
+        clock.ticks_this_update < SEEK_TICKS_PER_UPDATE
+    } else {
+        !clock.paused && clock.accumulator >= step && clock.ticks_this_update < max_ticks
+    };
+
+    if due {
+        if !seeking {
+            clock.accumulator -= step;
+        }
+        clock.ticks_this_update += 1;
+        clock.tick += 1;
+        clock.looping = true;
+        ShouldRun::YesAndCheckAgain
+    } else {
+        if !seeking && (over_budget || clock.ticks_this_update >= max_ticks) {
+            // too slow to keep up, drop the backlog instead of spiraling
+            clock.accumulator = clock.accumulator.min(step);
+        }
+        clock.looping = false;
+        ShouldRun::No
+    }
+}
+
+// system: remember where every actor starts this tick
+pub fn store_previous_translation_system(
+    mut actor_query: Query<(&Transform, &mut PreviousTranslation)>,
+) {
+    for (tr, mut previous) in actor_query.iter_mut() {
+        previous.0 = tr.translation;
+    }
+}
+
+// system: rebuild the spatial index from this tick's positions, so neighbor queries don't depend on frame rate
+pub fn rebuild_actor_space_system(
+    mut spatial: ResMut<ActorSpace>,
+    actor_query: Query<(Entity, &Transform), With<Actor>>,
+) {
+    spatial.recreate(
+        actor_query
+            .iter()
+            .map(|(entity, tr)| (tr.translation, entity))
+            .collect(),
+    );
+}
@@ -0,0 +1,136 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{f32::consts::SQRT_2, fmt, str::FromStr};
+
+use crate::{
+    Actor, Faction, FactionRegistry, GameConfig, MatchEvent, Pawn, SimClock, TICK_SECONDS,
+};
+
+/// How a match is played.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GameMode {
+    /// The largest faction when time is up wins.
+    #[default]
+    Classic,
+    /// A [`SafeZone`] shrinks over the match. Actors caught outside go neutral, and a pawn
+    /// caught outside is eliminated along with its whole faction.
+    BattleRoyale,
+}
+
+impl FromStr for GameMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "classic" => Ok(GameMode::Classic),
+            "battle_royale" => Ok(GameMode::BattleRoyale),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for GameMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameMode::Classic => write!(f, "classic"),
+            GameMode::BattleRoyale => write!(f, "battle_royale"),
+        }
+    }
+}
+
+/// The circle around the center of the plaza actors have to stay in, in a
+/// [`GameMode::BattleRoyale`] match. Inserted by [`setup_match`](crate::setup_match).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SafeZone {
+    /// Radius when the match starts, around every spawn point.
+    pub start_radius: f32,
+    /// Radius when time is up.
+    pub end_radius: f32,
+    /// Seconds it takes to shrink, the match time.
+    pub duration: f32,
+    /// Current radius.
+    pub radius: f32,
+}
+
+impl SafeZone {
+    /// The zone at the start of a match, `None` unless the config is for a battle royale.
+    pub fn for_config(config: &GameConfig) -> Option<Self> {
+        if config.mode != GameMode::BattleRoyale {
+            return None;
+        }
+        // actors spawn anywhere in the square of `action_region`, the zone starts on its corners
+        let start_radius = config.action_region * SQRT_2;
+        Some(SafeZone {
+            start_radius,
+            end_radius: config.zone_end_radius.min(start_radius),
+            duration: config.game_time,
+            radius: start_radius,
+        })
+    }
+
+    /// Shrink linearly, from the start radius at the first tick to the end radius once time is up.
+    pub fn shrink_to(&mut self, tick: u64) {
+        let progress = (tick as f32 * TICK_SECONDS / self.duration).clamp(0.0, 1.0);
+        self.radius = self.start_radius + (self.end_radius - self.start_radius) * progress;
+    }
+
+    pub fn contains(&self, position: Vec3) -> bool {
+        position.truncate().length_squared() <= self.radius * self.radius
+    }
+}
+
+// system: shrink the safe zone, neutralize actors outside of it and eliminate pawns outside
+// with their factions, if the match has a zone
+pub fn safe_zone_system(
+    mut commands: Commands,
+    clock: Res<SimClock>,
+    zone: Option<ResMut<SafeZone>>,
+    mut registry: ResMut<FactionRegistry>,
+    mut match_events: EventWriter<MatchEvent>,
+    mut actor_query: Query<(Entity, &Transform, &mut Actor, Option<&Pawn>)>,
+) {
+    let mut zone = match zone {
+        Some(zone) => zone,
+        None => return,
+    };
+    zone.shrink_to(clock.tick());
+
+    // pawns first, their followers go neutral wherever they are. A pawn converted this tick is
+    // no longer its faction's leader but still has `Pawn` until the commands are applied
+    let mut eliminated: Vec<Faction> = Vec::new();
+    for (entity, tr, actor, pawn) in actor_query.iter() {
+        let leading = registry
+            .get(actor.faction)
+            .is_some_and(|info| info.leader == Some(entity));
+        if pawn.is_some() && leading && !zone.contains(tr.translation) {
+            debug!(
+                "Remove pawn for faction {0:?}, outside the zone",
+                actor.faction
+            );
+            if let Some(info) = registry.get_mut(actor.faction) {
+                info.leader = None;
+            }
+            match_events.send(MatchEvent::PawnEliminated {
+                faction: actor.faction,
+            });
+            commands.entity(entity).remove::<Pawn>();
+            eliminated.push(actor.faction);
+        }
+    }
+
+    for (_, tr, mut actor, _) in actor_query.iter_mut() {
+        if actor.faction.is_neutral()
+            || (zone.contains(tr.translation) && !eliminated.contains(&actor.faction))
+        {
+            continue;
+        }
+        if let Some(info) = registry.get_mut(actor.faction) {
+            info.count -= 1;
+        }
+        if let Some(info) = registry.get_mut(Faction::Neutral) {
+            info.count += 1;
+        }
+        actor.faction = Faction::Neutral;
+    }
+}
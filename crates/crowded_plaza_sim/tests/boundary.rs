@@ -0,0 +1,190 @@
+use bevy::prelude::*;
+use crowded_plaza_sim::{
+    headless_match, Actor, BoundaryBehavior, BoundaryShape, ConfigError, GameConfig, Heading,
+    PlayerInput,
+};
+
+fn shapes() -> Vec<BoundaryShape> {
+    vec![
+        BoundaryShape::Rectangle {
+            half_width: 10.0,
+            half_height: 6.0,
+        },
+        BoundaryShape::Circle { radius: 8.0 },
+        // clockwise, with a notch
+        BoundaryShape::Polygon(vec![
+            (-8.0, -8.0),
+            (-8.0, 8.0),
+            (0.0, 3.0),
+            (8.0, 8.0),
+            (8.0, -8.0),
+        ]),
+    ]
+}
+
+#[test]
+fn finds_the_edge_inside_and_out() {
+    for shape in shapes() {
+        assert!(shape.contains(Vec2::ZERO));
+        for position in [
+            Vec2::new(30.0, 1.0),
+            Vec2::new(-2.0, -40.0),
+            Vec2::new(1.0, 8.5),
+        ] {
+            assert!(!shape.contains(position), "{:?} {}", shape, position);
+            let edge = shape.edge(position);
+            assert!(edge.depth < 0.0);
+            assert!((edge.normal.length() - 1.0).abs() < 1e-4);
+            // outward, the position is beyond the edge
+            assert!((position - edge.point).dot(edge.normal) > 0.0);
+
+            let wrapped = shape.wrap(position);
+            assert!(shape.contains(wrapped), "{:?} {}", shape, wrapped);
+        }
+        assert!(shape.edge(Vec2::new(0.5, -1.0)).depth > 0.0);
+    }
+    // the notch is closer than the far sides
+    let notch = shapes()[2].edge(Vec2::new(0.0, 1.0));
+    assert_eq!(notch.point, Vec2::new(0.0, 3.0));
+    assert!(notch.normal.y > 0.0);
+}
+
+#[test]
+fn rejects_shapes_off_the_origin() {
+    let mut config = GameConfig::default();
+    config
+        .set_field("boundary_shape", "circle(radius: 1.5)")
+        .unwrap();
+    assert_eq!(config.boundary(), BoundaryShape::Circle { radius: 30.0 });
+    assert_eq!(config.validate(), Ok(()));
+
+    config
+        .set_field(
+            "boundary_shape",
+            "polygon([(1.0, 1.0), (2.0, 1.0), (1.0, 2.0)])",
+        )
+        .unwrap();
+    assert!(matches!(
+        config.validate(),
+        Err(ConfigError::OutOfRange {
+            field: "boundary_shape",
+            ..
+        })
+    ));
+    assert!(config.set_field("boundary_shape", "hexagon").is_err());
+}
+
+// a triangle around the origin reaching `size` along both axes
+fn triangle(size: f32) -> BoundaryShape {
+    BoundaryShape::Polygon(vec![(-size, -size), (size, -size), (0.0, size)])
+}
+
+#[test]
+fn rejects_shapes_past_the_limits() {
+    let shapes = |size: f32| {
+        [
+            BoundaryShape::Rectangle {
+                half_width: size,
+                half_height: 1.0,
+            },
+            BoundaryShape::Rectangle {
+                half_width: 1.0,
+                half_height: size,
+            },
+            BoundaryShape::Circle { radius: size },
+            triangle(size),
+        ]
+    };
+    for size in [0.05, 1.0, 2.0] {
+        for shape in shapes(size) {
+            let config = GameConfig {
+                boundary_shape: shape.clone(),
+                ..default()
+            };
+            assert_eq!(config.validate(), Ok(()), "{:?}", shape);
+        }
+    }
+    for size in [0.0, 0.04, 2.01, 50.0, f32::NAN] {
+        for shape in shapes(size) {
+            let config = GameConfig {
+                boundary_shape: shape.clone(),
+                ..default()
+            };
+            assert!(
+                matches!(
+                    config.validate(),
+                    Err(ConfigError::OutOfRange {
+                        field: "boundary_shape",
+                        ..
+                    })
+                ),
+                "{:?}",
+                shape
+            );
+        }
+    }
+    // a sliver of a polygon is too thin even if it is long enough
+    let config = GameConfig {
+        boundary_shape: BoundaryShape::Polygon(vec![(-1.0, -0.01), (1.0, -0.01), (0.0, 0.01)]),
+        ..default()
+    };
+    assert!(config.validate().is_err());
+}
+
+// a small plaza, with the player running into the edge the whole time
+fn run_into_the_edge(shape: BoundaryShape, behavior: BoundaryBehavior) -> App {
+    let mut app = headless_match(
+        GameConfig {
+            actor_count: 300,
+            action_region: 5.0,
+            pawn_speed: 4.0,
+            boundary_shape: shape,
+            boundary_behavior: behavior,
+            ..default()
+        },
+        Some(8),
+        false,
+    );
+    for _ in 0..400 {
+        app.world
+            .resource_mut::<PlayerInput>()
+            .steer
+            .insert(0, Heading::from_direction(Vec2::new(1.0, 0.3)));
+        app.update();
+    }
+    app
+}
+
+#[test]
+fn keeps_actors_on_the_plaza() {
+    for shape in [
+        BoundaryShape::default(),
+        BoundaryShape::Circle { radius: 1.0 },
+        BoundaryShape::Polygon(vec![(-1.0, -1.0), (1.2, -0.8), (0.0, 1.3)]),
+    ] {
+        for behavior in [
+            BoundaryBehavior::Bounce,
+            BoundaryBehavior::Wrap,
+            BoundaryBehavior::Steer,
+        ] {
+            let mut app = run_into_the_edge(shape.clone(), behavior);
+            let boundary = app.world.resource::<GameConfig>().boundary();
+            // steering only pushes, anyone may be a little over the edge
+            let slack = match behavior {
+                BoundaryBehavior::Steer => 1.0,
+                _ => 1e-3,
+            };
+            for (tr, actor) in app.world.query::<(&Transform, &Actor)>().iter(&app.world) {
+                let edge = boundary.edge(tr.translation.truncate());
+                assert!(
+                    edge.depth > -slack,
+                    "{:?} {:?} {:?} at {}",
+                    shape,
+                    behavior,
+                    actor.faction,
+                    tr.translation
+                );
+            }
+        }
+    }
+}
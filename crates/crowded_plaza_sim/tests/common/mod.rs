@@ -0,0 +1,42 @@
+// not every test uses every helper
+#![allow(dead_code)]
+
+use bevy::prelude::*;
+use crowded_plaza_sim::{Actor, Faction, FactionRegistry};
+use std::collections::BTreeMap;
+
+/// Every actor's faction and position as bits, by entity, and the size of every faction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outcome {
+    pub actors: Vec<(Entity, Faction, [u32; 3])>,
+    pub counts: BTreeMap<Faction, i32>,
+}
+
+impl Outcome {
+    pub fn of(app: &mut App) -> Self {
+        let mut actors: Vec<_> = app
+            .world
+            .query::<(Entity, &Actor, &Transform)>()
+            .iter(&app.world)
+            .map(|(entity, actor, tr)| {
+                let position = tr.translation.to_array().map(f32::to_bits);
+                (entity, actor.faction, position)
+            })
+            .collect();
+        actors.sort_by_key(|(entity, ..)| *entity);
+        let counts = app
+            .world
+            .resource::<FactionRegistry>()
+            .iter()
+            .map(|(faction, info)| (faction, info.count))
+            .collect();
+        Outcome { actors, counts }
+    }
+
+    pub fn positions(&self) -> Vec<[u32; 3]> {
+        self.actors
+            .iter()
+            .map(|(_, _, position)| *position)
+            .collect()
+    }
+}
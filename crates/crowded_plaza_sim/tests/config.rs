@@ -0,0 +1,143 @@
+use crowded_plaza_sim::{ConfigError, GameConfig, GameConfigOverrides};
+
+// the tuning the game shipped with before it had a config file
+#[test]
+fn defaults_keep_the_original_tuning() {
+    let config = GameConfig::default();
+    assert_eq!(config.pawn_speed, 1.2);
+    assert_eq!(config.wander_speed, 0.3);
+    assert_eq!(config.repulsion_factor, 3.6);
+    assert_eq!(config.align_factor, 0.6);
+    assert_eq!(config.attract_factor, 1.08);
+    assert_eq!(config.validate(), Ok(()));
+}
+
+// values just past what each field allows, set one at a time on the defaults
+const OUT_OF_RANGE: &[(&str, &[&str])] = &[
+    ("pawn_speed", &["0.0", "101", "NaN"]),
+    ("wander_speed", &["-0.1", "1.3", "NaN"]),
+    ("opponent_move_scale", &["0.0", "101", "NaN"]),
+    ("neighbor_threshold", &["0.0", "11", "NaN"]),
+    ("repulsion_threshold", &["-0.1", "11", "NaN"]),
+    ("repulsion_factor", &["-0.1", "1001", "NaN"]),
+    ("align_factor", &["-0.1", "61", "NaN"]),
+    ("attract_factor", &["-0.1", "1001", "NaN"]),
+    ("flock_radius", &["-0.1", "11", "NaN"]),
+    ("cohesion_factor", &["-0.1", "1001", "NaN"]),
+    ("flock_align_factor", &["-0.1", "61", "NaN"]),
+    ("actor_count", &["-1", "1000001"]),
+    ("player_count", &["0", "33"]),
+    ("opponent_count", &["-1", "101"]),
+    ("action_region", &["0.5", "1001", "NaN"]),
+    (
+        "boundary_shape",
+        &[
+            "circle(radius: 0.01)",
+            "rectangle(half_width: 1.0, half_height: 3.0)",
+            "polygon([(1.0, 1.0), (2.0, 1.0), (2.0, 2.0)])",
+        ],
+    ),
+    ("boundary_margin", &["0.0", "101", "NaN"]),
+    ("boundary_force", &["-0.1", "1001", "NaN"]),
+    ("obstacle_margin", &["0.0", "11", "NaN"]),
+    ("flow_cell_size", &["0.1", "11", "NaN"]),
+    ("game_time", &["0.5", "3601", "NaN"]),
+    ("zone_end_radius", &["0.1", "1001", "NaN"]),
+    ("clear_color", &["1a1a1", "black"]),
+    ("light_color", &["#fdfff4"]),
+    ("ambient_color", &[""]),
+];
+
+#[test]
+fn rejects_every_field_out_of_range() {
+    for (field, values) in OUT_OF_RANGE {
+        for value in values.iter() {
+            let mut config = GameConfig::default();
+            config.set_field(field, value).unwrap();
+            match config.validate() {
+                Err(ConfigError::OutOfRange {
+                    field: rejected, ..
+                }) => {
+                    assert_eq!(rejected, *field, "{}={}", field, value)
+                }
+                other => panic!("{}={} gave {:?}", field, value, other),
+            }
+        }
+    }
+}
+
+#[test]
+fn rejects_unknown_fields() {
+    let mut config = GameConfig::default();
+    for field in ["pawn_sped", "map", ""] {
+        assert_eq!(
+            config.set_field(field, "1.0"),
+            Err(ConfigError::UnknownField(field.to_string()))
+        );
+    }
+    assert!(matches!(
+        config.set_field("actor_count", "many"),
+        Err(ConfigError::InvalidValue { .. })
+    ));
+
+    // overrides skip what they can't apply and keep the rest
+    config.apply_overrides(&GameConfigOverrides(vec![
+        ("actor_cout".to_string(), "10".to_string()),
+        ("actor_count".to_string(), "10".to_string()),
+    ]));
+    assert_eq!(
+        config,
+        GameConfig {
+            actor_count: 10,
+            ..GameConfig::default()
+        }
+    );
+    assert!(matches!(
+        GameConfig::from_ron(b"(actor_cout: 10)"),
+        Err(ConfigError::Parse(_))
+    ));
+}
+
+// every field of the config can be set by name, except the map which comes from its own file
+#[test]
+fn lists_every_field_set_field_accepts() {
+    let json = serde_json::to_value(GameConfig::default()).unwrap();
+    let mut fields: Vec<&str> = json
+        .as_object()
+        .unwrap()
+        .keys()
+        .map(String::as_str)
+        .filter(|field| *field != "map")
+        .collect();
+    let mut listed = GameConfig::FIELDS.to_vec();
+    fields.sort_unstable();
+    listed.sort_unstable();
+    assert_eq!(fields, listed);
+
+    for field in GameConfig::FIELDS {
+        let result = GameConfig::default().set_field(field, "?");
+        assert!(
+            !matches!(result, Err(ConfigError::UnknownField(_))),
+            "{}",
+            field
+        );
+    }
+}
+
+#[test]
+fn round_trips_through_ron() {
+    let config = GameConfig::default();
+    let ron = ron::to_string(&config).unwrap();
+    assert_eq!(GameConfig::from_ron(ron.as_bytes()), Ok(config.clone()));
+    assert_eq!(GameConfig::from_ron(b"()"), Ok(config.clone()));
+
+    // the shipped config spells out the defaults
+    let path = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../assets/config/game.config.ron"
+    );
+    assert_eq!(
+        GameConfig::from_ron(&std::fs::read(path).unwrap()),
+        Ok(config)
+    );
+}
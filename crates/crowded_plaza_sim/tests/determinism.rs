@@ -0,0 +1,36 @@
+mod common;
+
+use bevy::prelude::*;
+use crowded_plaza_sim::{headless_match, GameConfig, Heading, PlayerInput};
+
+use common::Outcome;
+
+// where every actor ended up after the player ran in circles
+fn play(seed: u64) -> Outcome {
+    let mut app = headless_match(
+        GameConfig {
+            actor_count: 800,
+            opponent_count: 3,
+            ..default()
+        },
+        Some(seed),
+        false,
+    );
+    for tick in 0..600 {
+        let angle = tick as f32 / 100.0;
+        app.world.resource_mut::<PlayerInput>().steer.insert(
+            0,
+            Heading::from_direction(Vec2::new(angle.cos(), angle.sin())),
+        );
+        app.update();
+    }
+    Outcome::of(&mut app)
+}
+
+#[test]
+fn same_seed_plays_the_same() {
+    let outcome = play(21);
+    assert!(outcome.counts.len() > 1);
+    assert_eq!(play(21), outcome);
+    assert_ne!(play(22).positions(), outcome.positions());
+}
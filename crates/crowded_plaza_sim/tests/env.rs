@@ -0,0 +1,130 @@
+use crowded_plaza_sim::{ConfigError, EnvSettings, GameConfig, SimEnv, OBSERVATION_CHANNELS};
+use std::{
+    io::{BufRead, BufReader, Write},
+    process::{Command, Stdio},
+};
+
+fn small_match() -> GameConfig {
+    GameConfig {
+        actor_count: 300,
+        opponent_count: 2,
+        game_time: 1.0,
+        ..GameConfig::default()
+    }
+}
+
+#[test]
+fn observes_a_grid_around_the_pawn() {
+    let settings = EnvSettings {
+        grid_size: 7,
+        cell_size: 2.0,
+        ..EnvSettings::default()
+    };
+    assert_eq!(settings.validate(), Ok(()));
+    let mut env = SimEnv::new(small_match(), settings);
+    let observation = env.reset(1);
+    assert_eq!(observation.grid_size, 7);
+    assert_eq!(observation.grid.len(), OBSERVATION_CHANNELS.len() * 7 * 7);
+    assert_eq!(observation.own_count, 1);
+    assert!(observation.grid.iter().any(|density| *density > 0.0));
+    // the pawn isn't counted in its own cell
+    let own_center = 7 * 7 + 3 * 7 + 3;
+    assert_eq!(observation.grid[own_center], 0.0);
+}
+
+#[test]
+fn rejects_even_grids() {
+    for grid_size in [0, 10, 257] {
+        let settings = EnvSettings {
+            grid_size,
+            ..EnvSettings::default()
+        };
+        assert!(matches!(
+            settings.validate(),
+            Err(ConfigError::OutOfRange {
+                field: "grid_size",
+                ..
+            })
+        ));
+    }
+}
+
+#[test]
+fn reset_plays_the_seed_again() {
+    let mut env = SimEnv::new(small_match(), EnvSettings::default());
+    let mut episode = |seed| {
+        let mut observations = vec![serde_json::to_string(&env.reset(seed)).unwrap()];
+        for step in 0..10 {
+            let action = [(step as f32).cos(), (step as f32).sin()];
+            observations.push(serde_json::to_string(&env.step(action)).unwrap());
+        }
+        observations
+    };
+    let first = episode(7);
+    assert_eq!(episode(7), first);
+    assert_ne!(episode(8), first);
+}
+
+#[test]
+fn done_when_time_runs_out() {
+    let settings = EnvSettings::default();
+    let ticks_per_step = settings.ticks_per_step as usize;
+    let mut env = SimEnv::new(small_match(), settings);
+    let observation = env.reset(3);
+    assert_eq!(observation.time_left, 1.0);
+
+    let mut steps = 0;
+    while !env.step([0.0, 0.0]).done {
+        steps += 1;
+        assert!(
+            steps * ticks_per_step < 120,
+            "still going after {} steps",
+            steps
+        );
+    }
+    assert!(steps * ticks_per_step >= 50, "done after {} steps", steps);
+    let step = env.step([1.0, 0.0]);
+    assert!(step.done);
+    assert_eq!(step.reward, 0.0);
+    env.reset(3);
+    assert!(!env.step([1.0, 0.0]).done);
+}
+
+// the trainer's side of the JSON lines protocol
+#[test]
+fn answers_malformed_requests_with_an_error() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_crowded_plaza_env"))
+        .args(["--grid_size", "5"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let spaces: serde_json::Value = serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+    assert_eq!(
+        spaces["spaces"]["observation"],
+        serde_json::json!([3, 5, 5])
+    );
+    let mut request = |line: &str| -> serde_json::Value {
+        writeln!(stdin, "{}", line).unwrap();
+        serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap()
+    };
+
+    for line in [
+        "not json",
+        r#"{"cmd":"jump"}"#,
+        r#"{"cmd":"step","action":[1.0]}"#,
+        r#"{"cmd":"reset","seed":-1}"#,
+    ] {
+        assert!(request(line)["error"].is_string(), "{}", line);
+    }
+    // still serving
+    let observation = &request(r#"{"cmd":"reset","seed":42}"#)["observation"];
+    assert_eq!(observation["grid"].as_array().unwrap().len(), 3 * 5 * 5);
+    assert!(request(r#"{"cmd":"step","action":[1.0,0.0]}"#)["done"].is_boolean());
+
+    writeln!(stdin, r#"{{"cmd":"close"}}"#).unwrap();
+    assert!(child.wait().unwrap().success());
+}
@@ -0,0 +1,48 @@
+use bevy::prelude::*;
+use crowded_plaza_sim::{headless_match, Actor, Faction, GameConfig, Pawn};
+
+// share of the followers with another actor of their crowd closer than `distance`
+fn stacked(app: &mut App, distance: f32) -> f32 {
+    let crowd: Vec<(Faction, Vec2)> = app
+        .world
+        .query_filtered::<(&Actor, &Transform), Without<Pawn>>()
+        .iter(&app.world)
+        .filter(|(actor, _)| !actor.faction.is_neutral())
+        .map(|(actor, tr)| (actor.faction, tr.translation.truncate()))
+        .collect();
+    assert!(crowd.len() > 100);
+    let stacked = crowd
+        .iter()
+        .enumerate()
+        .filter(|(i, (faction, position))| {
+            crowd
+                .iter()
+                .enumerate()
+                .any(|(j, (other, other_position))| {
+                    *i != j && faction == other && position.distance(*other_position) < distance
+                })
+        })
+        .count();
+    stacked as f32 / crowd.len() as f32
+}
+
+// one large crowd, each actor 0.1 in radius in the game client
+#[test]
+fn crowds_form_blobs_instead_of_stacking() {
+    let mut app = headless_match(
+        GameConfig {
+            actor_count: 4000,
+            opponent_count: 0,
+            ..default()
+        },
+        Some(3),
+        false,
+    );
+    for _ in 0..900 {
+        app.update();
+    }
+    // not stacked, most are further apart than their radius
+    assert!(stacked(&mut app, 0.1) < 0.4);
+    // but nearly none is on its own
+    assert!(stacked(&mut app, 0.6) > 0.95);
+}
@@ -0,0 +1,93 @@
+use bevy::prelude::*;
+use crowded_plaza_sim::{
+    Faction, FlowFields, GameConfig, Obstacle, ObstacleKind, Obstacles, PlazaMap,
+};
+
+// a wall across the plaza with a gap on its right end
+fn walled_plaza() -> GameConfig {
+    GameConfig {
+        map: PlazaMap {
+            obstacles: vec![Obstacle {
+                kind: ObstacleKind::Wall,
+                corners: vec![(-25.0, -0.5), (15.0, -0.5), (15.0, 0.5), (-25.0, 0.5)],
+            }],
+            ..default()
+        },
+        ..default()
+    }
+}
+
+#[test]
+fn plazas_without_obstacles_have_no_fields() {
+    let config = GameConfig::default();
+    let mut flow = FlowFields::new(&config, &Obstacles::new(&config.map));
+    assert!(flow.is_empty());
+    flow.update(Faction::Player(0), Vec2::ZERO);
+    assert_eq!(
+        flow.direction(Faction::Player(0), Vec2::new(5.0, 5.0)),
+        None
+    );
+}
+
+#[test]
+fn leads_around_the_wall() {
+    let config = walled_plaza();
+    let mut flow = FlowFields::new(&config, &Obstacles::new(&config.map));
+    let faction = Faction::Player(0);
+    flow.update(faction, Vec2::new(0.0, 8.0));
+
+    // behind the wall, heading for the gap instead of straight into the wall
+    let direction = flow.direction(faction, Vec2::new(0.0, -6.0)).unwrap();
+    assert!(direction.x > 0.5, "{}", direction);
+
+    // following the field gets there
+    let mut position = Vec2::new(-10.0, -6.0);
+    for _ in 0..200 {
+        match flow.direction(faction, position) {
+            Some(direction) => position += direction * 0.5,
+            None => break,
+        }
+        assert!(position.y < -0.5 || position.y > 0.5 || position.x > 15.0);
+    }
+    assert!(position.distance(Vec2::new(0.0, 8.0)) < 2.0, "{}", position);
+
+    // in sight of the leader, nearly straight at it
+    let direction = flow.direction(faction, Vec2::new(-6.0, 8.0)).unwrap();
+    assert!(direction.x > 0.9, "{}", direction);
+    assert_eq!(flow.direction(Faction::Bot(0), Vec2::ZERO), None);
+}
+
+// 200 by 200 cells, more than one update gets through
+#[test]
+fn rebuilds_large_fields_over_several_updates() {
+    let config = GameConfig {
+        flow_cell_size: 0.25,
+        ..walled_plaza()
+    };
+    let mut flow = FlowFields::new(&config, &Obstacles::new(&config.map));
+    let faction = Faction::Player(0);
+    let follower = Vec2::new(0.0, 4.0);
+    flow.update(faction, Vec2::new(0.0, 8.0));
+    assert!(flow.direction(faction, follower).unwrap().y > 0.9);
+
+    // the leader went behind the wall, the way there is around it
+    let mut updates = 1;
+    flow.update(faction, Vec2::new(0.0, -8.0));
+    while flow.direction(faction, follower).unwrap().y > 0.9 {
+        flow.update(faction, Vec2::new(0.0, -8.0));
+        updates += 1;
+        assert!(updates < 20);
+    }
+    assert!(updates > 1);
+    assert!(flow.direction(faction, follower).unwrap().x > 0.5);
+
+    // and the same as built at once
+    let mut at_once = FlowFields::new(&config, &Obstacles::new(&config.map));
+    at_once.update(faction, Vec2::new(0.0, -8.0));
+    for position in [follower, Vec2::new(-20.0, 20.0), Vec2::new(10.0, -3.0)] {
+        assert_eq!(
+            flow.direction(faction, position),
+            at_once.direction(faction, position)
+        );
+    }
+}
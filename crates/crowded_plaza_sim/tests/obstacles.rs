@@ -0,0 +1,116 @@
+use bevy::prelude::*;
+use crowded_plaza_sim::{
+    headless_match, Actor, ConfigError, GameConfig, Heading, Obstacle, ObstacleKind, Obstacles,
+    PlayerController, PlayerInput, PlazaMap,
+};
+
+fn fountain_square() -> PlazaMap {
+    let path = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../assets/maps/fountain_square.map.ron"
+    );
+    PlazaMap::from_ron(&std::fs::read(path).unwrap()).unwrap()
+}
+
+#[test]
+fn rejects_flat_obstacles() {
+    assert!(!fountain_square().obstacles.is_empty());
+
+    let mut config = GameConfig {
+        map: PlazaMap {
+            obstacles: vec![Obstacle {
+                kind: ObstacleKind::Wall,
+                corners: vec![(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)],
+            }],
+            ..default()
+        },
+        ..default()
+    };
+    assert!(matches!(
+        config.validate(),
+        Err(ConfigError::OutOfRange { field: "map", .. })
+    ));
+    config.map.obstacles[0].corners = vec![(0.0, 0.0), (1.0, 0.0)];
+    assert!(config.validate().is_err());
+    assert!(PlazaMap::from_ron(b"(obstacles: [(kind: tower)])").is_err());
+}
+
+// the player runs across the plaza the whole match, through whatever is in the way
+#[test]
+fn keeps_actors_out_of_obstacles() {
+    let map = fountain_square();
+    let mut app = headless_match(
+        GameConfig {
+            actor_count: 500,
+            map: map.clone(),
+            ..default()
+        },
+        Some(5),
+        false,
+    );
+    let obstacles = Obstacles::new(&map);
+    for tick in 0..600 {
+        let direction = if tick < 300 {
+            Vec2::new(0.2, 1.0)
+        } else {
+            Vec2::new(-1.0, -0.4)
+        };
+        app.world
+            .resource_mut::<PlayerInput>()
+            .steer
+            .insert(0, Heading::from_direction(direction));
+        app.update();
+        for (tr, actor) in app.world.query::<(&Transform, &Actor)>().iter(&app.world) {
+            assert!(
+                !obstacles.contains(tr.translation.truncate()),
+                "{:?} in an obstacle at {} on tick {}",
+                actor.faction,
+                tr.translation,
+                tick
+            );
+        }
+        if tick == 0 {
+            let mut player = app
+                .world
+                .query_filtered::<&Transform, With<PlayerController>>();
+            assert!(
+                player.iter(&app.world).next().unwrap().translation.length() < 5.0 * 2f32.sqrt()
+            );
+        }
+    }
+}
+
+// every point of the spawn zone is in the way, so every spawn falls back to its last try
+#[test]
+fn spawns_outside_an_obstacle_covering_the_spawn_zone() {
+    let map = PlazaMap {
+        obstacles: vec![Obstacle {
+            kind: ObstacleKind::Wall,
+            corners: vec![(-10.0, -10.0), (10.0, -10.0), (10.0, 10.0), (-10.0, 10.0)],
+        }],
+        spawn_zones: vec![vec![(-5.0, -5.0), (5.0, -5.0), (5.0, 5.0), (-5.0, 5.0)]],
+    };
+    let mut app = headless_match(
+        GameConfig {
+            actor_count: 100,
+            opponent_count: 3,
+            player_count: 2,
+            map: map.clone(),
+            ..default()
+        },
+        Some(5),
+        true,
+    );
+    app.update();
+    let obstacles = Obstacles::new(&map);
+    let mut actors = app.world.query::<(&Transform, &Actor)>();
+    assert_eq!(actors.iter(&app.world).count(), 105);
+    for (tr, actor) in actors.iter(&app.world) {
+        assert!(
+            !obstacles.contains(tr.translation.truncate()),
+            "{:?} spawned in an obstacle at {}",
+            actor.faction,
+            tr.translation
+        );
+    }
+}
@@ -0,0 +1,130 @@
+mod common;
+
+use bevy::prelude::*;
+use crowded_plaza_sim::{
+    headless_match, GameConfig, Heading, PlayerInput, Replay, ReplayError, ReplayPlayback,
+    ReplayRecorder,
+};
+
+use common::Outcome;
+
+const SEED: u64 = 8;
+
+fn config() -> GameConfig {
+    GameConfig {
+        actor_count: 600,
+        opponent_count: 2,
+        ..default()
+    }
+}
+
+// a match where the player turns every now and then, recorded from the start, with the config
+// reloaded as `reload` on tick 200
+fn record(reload: Option<GameConfig>) -> (Replay, Outcome) {
+    let mut app = headless_match(config(), Some(SEED), false);
+    app.insert_resource(ReplayRecorder::new(SEED, config()));
+    for tick in 0..400 {
+        if let (200, Some(reload)) = (tick, reload.as_ref()) {
+            app.insert_resource(reload.clone());
+        }
+        if tick % 25 == 0 {
+            let angle = tick as f32 * 0.7;
+            app.world.resource_mut::<PlayerInput>().steer.insert(
+                0,
+                Heading::from_direction(Vec2::new(angle.cos(), angle.sin())),
+            );
+        }
+        app.update();
+    }
+    let mut replay = app.world.remove_resource::<ReplayRecorder>().unwrap().0;
+    replay.end_tick = 400;
+    (replay, Outcome::of(&mut app))
+}
+
+// helper: play `replay` from start to end
+fn play(replay: Replay) -> Outcome {
+    let mut app = headless_match(replay.config.clone(), Some(replay.seed), false);
+    let ticks = replay.end_tick;
+    app.insert_resource(ReplayPlayback(replay));
+    for _ in 0..ticks {
+        app.update();
+    }
+    Outcome::of(&mut app)
+}
+
+#[test]
+fn replays_to_the_same_end() {
+    let (replay, outcome) = record(None);
+    assert_eq!(replay.inputs.len(), 16);
+    assert!(replay.config_changes.is_empty());
+
+    let bytes = replay.to_bytes();
+    let loaded = Replay::from_bytes(&bytes).unwrap();
+    assert_eq!(loaded, replay);
+    assert_eq!(play(loaded), outcome);
+}
+
+#[test]
+fn replays_a_config_reloaded_while_recording() {
+    let reload = GameConfig {
+        pawn_speed: config().pawn_speed * 1.5,
+        repulsion_threshold: config().repulsion_threshold * 2.0,
+        ..config()
+    };
+    let (replay, outcome) = record(Some(reload.clone()));
+    assert_eq!(replay.config_changes.len(), 1);
+    assert_eq!(replay.config_changes[0].1, reload);
+
+    let loaded = Replay::from_bytes(&replay.to_bytes()).unwrap();
+    assert_eq!(loaded, replay);
+    assert_eq!(play(loaded), outcome);
+
+    // without the reload the match ends differently
+    let stale = Replay {
+        config_changes: Vec::new(),
+        ..replay
+    };
+    assert_ne!(play(stale), outcome);
+}
+
+#[test]
+fn rejects_broken_files() {
+    let replay = Replay {
+        seed: 3,
+        config: config(),
+        end_tick: 90,
+        inputs: vec![(0, Heading(0)), (4, Heading(300)), (900, Heading(65535))],
+        config_changes: vec![(
+            60,
+            GameConfig {
+                actor_count: 900,
+                ..config()
+            },
+        )],
+    };
+    let bytes = replay.to_bytes();
+
+    assert_eq!(Replay::from_bytes(b""), Err(ReplayError::BadMagic));
+    assert_eq!(
+        Replay::from_bytes(b"PK\x03\x04 not a replay"),
+        Err(ReplayError::BadMagic)
+    );
+
+    let mut newer = bytes.clone();
+    newer[4] += 1;
+    assert_eq!(
+        Replay::from_bytes(&newer),
+        Err(ReplayError::UnsupportedVersion(newer[4]))
+    );
+
+    // cut anywhere after the magic
+    for len in 4..bytes.len() {
+        assert_eq!(
+            Replay::from_bytes(&bytes[..len]),
+            Err(ReplayError::Truncated),
+            "cut at {}",
+            len
+        );
+    }
+    assert_eq!(Replay::from_bytes(&bytes), Ok(replay));
+}
@@ -0,0 +1,183 @@
+use bevy::prelude::*;
+use crowded_plaza_sim::{ActorSpace, SpatialBackend, SpatialIndex, UniformGrid};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+// a crowd, a tight cluster, points on top of each other and one far off
+fn points() -> Vec<(Vec3, Entity)> {
+    let mut rng = ChaCha8Rng::seed_from_u64(3);
+    let mut points: Vec<Vec3> = (0..2000)
+        .map(|_| Vec3::new(rng.gen_range(-20.0..20.0), rng.gen_range(-20.0..20.0), 0.0))
+        .collect();
+    points.extend((0..200).map(|_| {
+        Vec3::new(
+            5.0 + rng.gen_range(-0.3..0.3),
+            -3.0 + rng.gen_range(-0.3..0.3),
+            0.0,
+        )
+    }));
+    points.extend([Vec3::new(1.0, 1.0, 0.0); 3]);
+    points.push(Vec3::new(400.0, -250.0, 0.0));
+    points
+        .into_iter()
+        .enumerate()
+        .map(|(id, position)| (position, Entity::from_raw(id as u32)))
+        .collect()
+}
+
+fn index(backend: SpatialBackend) -> ActorSpace {
+    let mut index = ActorSpace::new(backend, 0.5);
+    index.recreate(points());
+    index
+}
+
+fn sorted(mut found: Vec<(Vec3, Entity)>) -> Vec<(Vec3, Entity)> {
+    found.sort_by_key(|(_, entity)| *entity);
+    found
+}
+
+fn queries() -> Vec<Vec3> {
+    let mut queries: Vec<Vec3> = points().iter().step_by(7).map(|(loc, _)| *loc).collect();
+    // between points and outside of the crowd
+    queries.extend([
+        Vec3::new(0.13, -7.7, 0.0),
+        Vec3::new(-60.0, 0.0, 0.0),
+        Vec3::new(1000.0, 1000.0, 0.0),
+    ]);
+    queries
+}
+
+#[test]
+fn grid_finds_what_the_tree_finds() {
+    let tree = index(SpatialBackend::KdTree);
+    let grid = index(SpatialBackend::UniformGrid);
+    for loc in queries() {
+        for distance in [0.0, 0.2, 0.5, 1.3, 6.0, 50.0] {
+            assert_eq!(
+                sorted(grid.within_distance(loc, distance)),
+                sorted(tree.within_distance(loc, distance)),
+                "within {} of {}",
+                distance,
+                loc
+            );
+        }
+    }
+}
+
+#[test]
+fn grid_finds_the_nearest_points() {
+    let tree = index(SpatialBackend::KdTree);
+    let grid = index(SpatialBackend::UniformGrid);
+    let distances = |found: Vec<(Vec3, Entity)>, loc: Vec3| -> Vec<f32> {
+        found
+            .iter()
+            .map(|(position, _)| position.distance_squared(loc))
+            .collect()
+    };
+    for loc in queries() {
+        for k in [0, 1, 2, 5, 40] {
+            let nearest = grid.k_nearest_neighbour(loc, k);
+            assert_eq!(nearest.len(), k);
+            // ties may come in another order
+            assert_eq!(
+                distances(nearest, loc),
+                distances(tree.k_nearest_neighbour(loc, k), loc),
+                "{} nearest to {}",
+                k,
+                loc
+            );
+        }
+    }
+    assert_eq!(grid.k_nearest_neighbour(Vec3::ZERO, 5000).len(), 2204);
+}
+
+#[test]
+fn grid_starts_empty() {
+    let mut grid = ActorSpace::new(SpatialBackend::UniformGrid, 0.5);
+    assert!(grid.within_distance(Vec3::ZERO, 10.0).is_empty());
+    assert!(grid.k_nearest_neighbour(Vec3::ZERO, 2).is_empty());
+    grid.recreate(vec![(Vec3::new(2.0, 2.0, 0.0), Entity::from_raw(7))]);
+    grid.recreate(Vec::new());
+    assert!(grid.within_distance(Vec3::ZERO, 10.0).is_empty());
+}
+
+const RADIUS: f32 = 5.0;
+
+// random points, some exactly `RADIUS` from a query point and some on the far edge of the grid
+fn edge_points() -> (Vec<(Vec3, Entity)>, Vec<Vec3>) {
+    let mut rng = ChaCha8Rng::seed_from_u64(9);
+    let mut points: Vec<Vec3> = (0..1500)
+        .map(|_| Vec3::new(rng.gen_range(-30.0..30.0), rng.gen_range(-30.0..30.0), 0.0))
+        .collect();
+    let center = Vec3::new(2.0, -1.0, 0.0);
+    // 3-4-5 triangles, so the distances are exact
+    points.extend(
+        [(5.0, 0.0), (0.0, -5.0), (-3.0, 4.0), (4.0, -3.0)]
+            .map(|(x, y)| center + Vec3::new(x, y, 0.0)),
+    );
+    // the corner the grid ends at, and along its last row and column
+    let corner = Vec3::new(30.0, 30.0, 0.0);
+    points.extend([
+        corner,
+        corner - Vec3::new(0.5, 0.0, 0.0),
+        corner - Vec3::new(0.0, 0.5, 0.0),
+        Vec3::new(-30.0, 30.0, 0.0),
+        Vec3::new(30.0, -30.0, 0.0),
+    ]);
+    let queries = vec![
+        center,
+        corner,
+        corner + Vec3::new(RADIUS, 0.0, 0.0),
+        corner + Vec3::new(0.25, 0.25, 0.0),
+        Vec3::new(30.0, 0.0, 0.0),
+        Vec3::new(-30.0, 30.0, 0.0),
+    ];
+    let points = points
+        .into_iter()
+        .enumerate()
+        .map(|(id, position)| (position, Entity::from_raw(id as u32)))
+        .collect();
+    (points, queries)
+}
+
+#[test]
+fn grid_agrees_with_the_tree_on_the_edges() {
+    let (points, queries) = edge_points();
+    let mut tree = ActorSpace::new(SpatialBackend::KdTree, 0.5);
+    tree.recreate(points.clone());
+    let mut grid = UniformGrid::new(0.5);
+    grid.recreate(points);
+
+    // the points exactly on the radius are left out by both
+    let around_center = grid.within_distance(queries[0], RADIUS);
+    assert!(around_center
+        .iter()
+        .all(|(position, _)| position.distance(queries[0]) < RADIUS));
+
+    let distances = |found: Vec<(Vec3, Entity)>, loc: Vec3| -> Vec<f32> {
+        found
+            .iter()
+            .map(|(position, _)| position.distance_squared(loc))
+            .collect()
+    };
+    for loc in queries {
+        for distance in [0.0, 0.5, RADIUS, RADIUS + 1e-4, 45.0] {
+            assert_eq!(
+                sorted(grid.within_distance(loc, distance)),
+                sorted(tree.within_distance(loc, distance)),
+                "within {} of {}",
+                distance,
+                loc
+            );
+        }
+        for k in [1, 3, 8, 100] {
+            assert_eq!(
+                distances(grid.k_nearest_neighbour(loc, k), loc),
+                distances(tree.k_nearest_neighbour(loc, k), loc),
+                "{} nearest to {}",
+                k,
+                loc
+            );
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use bevy::{diagnostic::Diagnostics, diagnostic::DiagnosticsPlugin, prelude::*};
+use crowded_plaza_sim::{
+    headless_match, Actor, Faction, FactionRegistry, GameConfig, SimDiagnosticsPlugin, SimLabel,
+    SimStage, TickStats,
+};
+
+// repulsion pairs of the tick counted the slow way, right before repulsion
+#[derive(Default)]
+struct CloseSameFactionPairs(usize);
+
+fn count_close_pairs_system(
+    config: Res<GameConfig>,
+    actor_query: Query<(&Transform, &Actor)>,
+    mut pairs: ResMut<CloseSameFactionPairs>,
+) {
+    let actors: Vec<_> = actor_query.iter().collect();
+    pairs.0 = 0;
+    for (i, (tr, actor)) in actors.iter().enumerate() {
+        for (other_tr, other) in actors[i + 1..].iter() {
+            if !actor.faction.is_neutral()
+                && actor.faction == other.faction
+                && tr.translation.distance(other_tr.translation) < config.repulsion_threshold
+            {
+                pairs.0 += 1;
+            }
+        }
+    }
+}
+
+fn measured_match() -> App {
+    let mut app = headless_match(
+        GameConfig {
+            actor_count: 500,
+            ..default()
+        },
+        Some(5),
+        false,
+    );
+    app.add_plugin(DiagnosticsPlugin)
+        .add_plugin(SimDiagnosticsPlugin);
+    app
+}
+
+#[test]
+fn counts_what_a_tick_did() {
+    let mut app = measured_match();
+    app.update();
+    let neutral = app
+        .world
+        .resource::<FactionRegistry>()
+        .count(Faction::Neutral);
+
+    let mut conversions = 0;
+    for _ in 0..120 {
+        app.update();
+        // repulsion and conversion each look around every actor
+        let actors = app.world.query::<&Actor>().iter(&app.world).count();
+        let stats = app.world.resource::<TickStats>();
+        assert!(stats.neighbor_queries >= 2 * actors);
+        conversions += stats.conversions;
+    }
+    let converted = neutral
+        - app
+            .world
+            .resource::<FactionRegistry>()
+            .count(Faction::Neutral);
+    assert!(converted > 0);
+    // actors also change between the leading factions
+    assert!(conversions >= converted as usize);
+}
+
+#[test]
+fn adds_measurements_for_every_faction() {
+    let mut app = measured_match();
+    for _ in 0..30 {
+        app.update();
+    }
+    let diagnostics = app.world.resource::<Diagnostics>();
+    let queries = diagnostics
+        .get(SimDiagnosticsPlugin::NEIGHBOR_QUERIES)
+        .unwrap();
+    assert_eq!(
+        queries.value(),
+        Some(app.world.resource::<TickStats>().neighbor_queries as f64)
+    );
+    for (faction, info) in app.world.resource::<FactionRegistry>().iter() {
+        let actors = diagnostics
+            .get(SimDiagnosticsPlugin::faction_actors(faction))
+            .unwrap();
+        assert_eq!(actors.value(), Some(info.count as f64));
+    }
+}
+
+#[test]
+fn counts_every_repulsion_pair_once() {
+    let mut app = measured_match();
+    app.init_resource::<CloseSameFactionPairs>()
+        .add_system_to_stage(
+            SimStage,
+            count_close_pairs_system
+                .after(SimLabel::Follow)
+                .before(SimLabel::Repulse),
+        );
+    let mut pairs = 0;
+    for _ in 0..120 {
+        app.update();
+        let expected = app.world.resource::<CloseSameFactionPairs>().0;
+        assert_eq!(app.world.resource::<TickStats>().repulsion_pairs, expected);
+        pairs += expected;
+    }
+    // the crowds got close enough to push
+    assert!(pairs > 0);
+}
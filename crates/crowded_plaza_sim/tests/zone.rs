@@ -0,0 +1,142 @@
+use bevy::{ecs::event::Events, prelude::*};
+use crowded_plaza_sim::{
+    headless_match, Actor, Faction, FactionRegistry, GameConfig, GameMode, MatchEvent, Pawn,
+    SafeZone,
+};
+
+fn short_match(mode: GameMode) -> App {
+    headless_match(
+        GameConfig {
+            actor_count: 500,
+            game_time: 5.0,
+            mode,
+            ..default()
+        },
+        Some(11),
+        false,
+    )
+}
+
+#[test]
+fn classic_matches_have_no_zone() {
+    let mut app = short_match(GameMode::Classic);
+    app.update();
+    assert!(app.world.get_resource::<SafeZone>().is_none());
+}
+
+#[test]
+fn zone_shrinks_and_neutralizes_actors_outside() {
+    let mut app = short_match(GameMode::BattleRoyale);
+    app.update();
+    let zone = *app.world.resource::<SafeZone>();
+    assert_eq!(zone.start_radius, 20.0 * std::f32::consts::SQRT_2);
+    assert_eq!(zone.end_radius, 3.0);
+
+    let mut eliminated = Vec::new();
+    let mut reader = app.world.resource::<Events<MatchEvent>>().get_reader();
+    let mut last_radius = zone.radius;
+    for _ in 0..330 {
+        app.update();
+        let zone = *app.world.resource::<SafeZone>();
+        assert!(zone.radius <= last_radius);
+        last_radius = zone.radius;
+
+        for (tr, actor) in app.world.query::<(&Transform, &Actor)>().iter(&app.world) {
+            assert!(actor.faction.is_neutral() || zone.contains(tr.translation));
+        }
+        for event in reader.iter(app.world.resource::<Events<MatchEvent>>()) {
+            if let MatchEvent::PawnEliminated { faction } = event {
+                eliminated.push(*faction);
+            }
+        }
+    }
+    assert_eq!(last_radius, 3.0);
+    // one event per eliminated faction
+    let mut once = eliminated.clone();
+    once.sort();
+    once.dedup();
+    assert_eq!(once.len(), eliminated.len());
+
+    // eliminated factions are gone for good, the rest still has a pawn in the zone
+    let registry = app.world.resource::<FactionRegistry>().clone();
+    let pawns: Vec<Faction> = app
+        .world
+        .query_filtered::<&Actor, With<Pawn>>()
+        .iter(&app.world)
+        .map(|actor| actor.faction)
+        .collect();
+    assert!(!eliminated.is_empty());
+    for (faction, info) in registry.iter() {
+        if faction.is_neutral() {
+            continue;
+        }
+        assert_eq!(eliminated.contains(&faction), info.leader.is_none());
+        assert_eq!(eliminated.contains(&faction), !pawns.contains(&faction));
+    }
+    let mut counts = std::collections::BTreeMap::new();
+    for actor in app.world.query::<&Actor>().iter(&app.world) {
+        *counts.entry(actor.faction).or_insert(0) += 1;
+    }
+    for (faction, info) in registry.iter() {
+        assert_eq!(counts.get(&faction).copied().unwrap_or(0), info.count);
+    }
+}
+
+#[test]
+fn pawns_converted_outside_the_zone_are_eliminated_once() {
+    let mut app = short_match(GameMode::BattleRoyale);
+    app.update();
+    // until the zone is well within the plaza, which would put back actors past its edge
+    while app.world.resource::<SafeZone>().radius > 15.0 {
+        app.update();
+    }
+    let outside = app.world.resource::<SafeZone>().radius + 2.0;
+
+    // strand a pawn outside the zone among another faction, which converts it on the next tick
+    let (pawn, faction) = app
+        .world
+        .query_filtered::<(Entity, &Actor), With<Pawn>>()
+        .iter(&app.world)
+        .map(|(entity, actor)| (entity, actor.faction))
+        .next()
+        .unwrap();
+    let other = app
+        .world
+        .resource::<FactionRegistry>()
+        .iter()
+        .map(|(faction, _)| faction)
+        .find(|other| !other.is_neutral() && *other != faction)
+        .unwrap();
+    let crowd: Vec<Entity> = app
+        .world
+        .query_filtered::<(Entity, &Actor), Without<Pawn>>()
+        .iter(&app.world)
+        .filter(|(_, actor)| actor.faction.is_neutral())
+        .map(|(entity, _)| entity)
+        .take(8)
+        .collect();
+    for entity in crowd.iter().copied().chain([pawn]) {
+        let mut actor_entity = app.world.entity_mut(entity);
+        actor_entity.get_mut::<Transform>().unwrap().translation = Vec3::new(outside, 0.0, 0.0);
+        let mut actor = actor_entity.get_mut::<Actor>().unwrap();
+        actor.velocity = Vec3::ZERO;
+        if entity != pawn {
+            actor.faction = other;
+        }
+    }
+    let mut registry = app.world.resource_mut::<FactionRegistry>();
+    registry.get_mut(faction).unwrap().count = 1;
+    registry.get_mut(other).unwrap().count += crowd.len() as i32;
+    registry.get_mut(Faction::Neutral).unwrap().count -= crowd.len() as i32;
+
+    let mut reader = app.world.resource::<Events<MatchEvent>>().get_reader();
+    app.update();
+    let eliminated: Vec<Faction> = reader
+        .iter(app.world.resource::<Events<MatchEvent>>())
+        .filter_map(|event| match event {
+            MatchEvent::PawnEliminated { faction } => Some(*faction),
+            MatchEvent::TimeUp => None,
+        })
+        .collect();
+    assert_eq!(eliminated, vec![faction]);
+}
@@ -0,0 +1,140 @@
+//! Per frame stats of every span in a `trace_chrome` trace, or how they changed between two
+//! traces.
+//!
+//! ```text
+//! cargo run --release -p crowded_plaza_trace -- trace-1650000000.json [--warmup 60]
+//!     [--filter rotate_cube,crowded_plaza] [--top 30] [--min_ms 0.01]
+//! cargo run --release -p crowded_plaza_trace -- before.json after.json [--threshold 10]
+//! ```
+//!
+//! Spans are listed by mean time per frame, or by how much that changed. `--filter` keeps the
+//! spans whose name contains one of the words, `crowded_plaza` keeps the gameplay systems. Either
+//! file can also be a summary `many_cubes` wrote, those already left out the warmup frames.
+//!
+//! With `--threshold`, it exits with an error if a span got slower by more than that many
+//! percent.
+use crowded_plaza_trace::{parse_trace, SpanStats, TraceSummary};
+
+// helper: the value of `--name value`, parsed
+fn flag<T: std::str::FromStr>(name: &str) -> Option<T> {
+    let flag = format!("--{}", name);
+    let value = std::env::args().skip_while(|arg| *arg != flag).nth(1)?;
+    match value.parse() {
+        Ok(value) => Some(value),
+        Err(_) => panic!("invalid value `{}` for {}", value, flag),
+    }
+}
+
+// helper: the arguments that are neither flags nor their values
+fn positional_args() -> Vec<String> {
+    let mut args = std::env::args().skip(1);
+    let mut positional = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg.starts_with("--") {
+            args.next();
+        } else {
+            positional.push(arg);
+        }
+    }
+    positional
+}
+
+// a trace, or a summary of one
+fn load(path: &str, warmup: usize) -> TraceSummary {
+    let bytes = std::fs::read(path).unwrap_or_else(|e| panic!("cannot read {}: {}", path, e));
+    if bytes.iter().find(|byte| !byte.is_ascii_whitespace()) == Some(&b'{') {
+        return serde_json::from_slice(&bytes).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    }
+    let spans = parse_trace(&bytes).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    TraceSummary::new(&spans, warmup)
+}
+
+fn main() {
+    let paths = positional_args();
+    let warmup = flag("warmup").unwrap_or(60);
+    let filter: Vec<String> = flag::<String>("filter")
+        .map(|filter| filter.split(',').map(str::to_string).collect())
+        .unwrap_or_default();
+    let top = flag("top").unwrap_or(30);
+    let min_ms = flag("min_ms").unwrap_or(0.01);
+    let shown = |name: &str, stats: &[&SpanStats]| {
+        (filter.is_empty() || filter.iter().any(|word| name.contains(word.as_str())))
+            && stats.iter().any(|stats| stats.mean_ms >= min_ms)
+    };
+
+    match paths.as_slice() {
+        [path] => {
+            let summary = load(path, warmup);
+            println!(
+                "{} frames, {:.2} ms a frame (p50 {:.2}, p95 {:.2}, max {:.2})",
+                summary.frames,
+                summary.frame.mean_ms,
+                summary.frame.p50_ms,
+                summary.frame.p95_ms,
+                summary.frame.max_ms
+            );
+            let mut spans: Vec<_> = summary
+                .spans
+                .iter()
+                .filter(|(name, stats)| shown(name, &[stats]))
+                .collect();
+            spans.sort_by(|a, b| b.1.mean_ms.total_cmp(&a.1.mean_ms));
+            println!(
+                "{:>9} {:>9} {:>9} {:>9} {:>12}  span",
+                "mean ms", "p50 ms", "p95 ms", "max ms", "calls/frame"
+            );
+            for (name, stats) in spans.into_iter().take(top) {
+                println!(
+                    "{:>9.3} {:>9.3} {:>9.3} {:>9.3} {:>12.1}  {}",
+                    stats.mean_ms,
+                    stats.p50_ms,
+                    stats.p95_ms,
+                    stats.max_ms,
+                    stats.calls as f64 / summary.frames.max(1) as f64,
+                    name
+                );
+            }
+        }
+        [base, new] => {
+            let diff = load(base, warmup).diff(&load(new, warmup));
+            println!(
+                "{:>9} {:>9} {:>8} {:>9} {:>9}  span",
+                "base ms", "new ms", "change", "base p95", "new p95"
+            );
+            let spans = diff
+                .iter()
+                .enumerate()
+                // the frame first, whatever the filter
+                .filter(|(i, span)| *i == 0 || shown(&span.name, &[&span.base, &span.new]))
+                .take(top + 1);
+            for (_, span) in spans {
+                let change = match span.change() {
+                    Some(change) => format!("{:+.1}%", change * 100.0),
+                    None => "new".to_string(),
+                };
+                println!(
+                    "{:>9.3} {:>9.3} {:>8} {:>9.3} {:>9.3}  {}",
+                    span.base.mean_ms,
+                    span.new.mean_ms,
+                    change,
+                    span.base.p95_ms,
+                    span.new.p95_ms,
+                    span.name
+                );
+            }
+
+            if let Some(threshold) = flag::<f64>("threshold") {
+                let slower = diff
+                    .iter()
+                    .filter(|span| shown(&span.name, &[&span.base, &span.new]))
+                    .filter(|span| span.change().is_some_and(|c| c * 100.0 > threshold))
+                    .count();
+                if slower > 0 {
+                    eprintln!("{} spans got slower by more than {}%", slower, threshold);
+                    std::process::exit(1);
+                }
+            }
+        }
+        _ => panic!("expected one trace, or two to compare"),
+    }
+}
@@ -0,0 +1,223 @@
+//! Reads the traces bevy writes with its `trace_chrome` feature, `trace-<unix time>.json` in the
+//! working directory, and sums up what each span costs per frame, so benchmarks don't need a
+//! trip to [ui.perfetto.dev](https://ui.perfetto.dev/).
+//!
+//! ```text
+//! let spans = parse_trace(&std::fs::read("trace-1650000000.json")?)?;
+//! let summary = TraceSummary::new(&spans, 60);
+//! println!("{:.2} ms a frame", summary.frame.mean_ms);
+//! ```
+//!
+//! Spans are named like perfetto shows them, with their fields: `frame`, `stage: name=Update`,
+//! `system: name="bevy_transform::transform_propagate_system::transform_propagate_system"`,
+//! `rotate_cube: name="rotate_cube"`.
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+/// The span bevy wraps every app update in.
+pub const FRAME_SPAN: &str = "frame";
+
+/// One run of a span, times in microseconds since the trace started.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    pub name: String,
+    pub start: f64,
+    pub duration: f64,
+}
+
+/// Why a trace could not be read.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TraceError {
+    /// Not a JSON array of trace events.
+    Malformed(String),
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceError::Malformed(e) => write!(f, "malformed trace: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TraceError {}
+
+// the parts of a trace event a span needs, `B` begins one on a thread and `E` ends it
+#[derive(Deserialize)]
+struct TraceEvent {
+    ph: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    ts: f64,
+    #[serde(default)]
+    tid: u64,
+}
+
+/// Every span of a trace that ended, by start time.
+pub fn parse_trace(bytes: &[u8]) -> Result<Vec<Span>, TraceError> {
+    let text = std::str::from_utf8(bytes).map_err(|e| TraceError::Malformed(e.to_string()))?;
+    // the trace is only closed if the app exits cleanly, perfetto reads it either way
+    let mut text = text.trim_end().trim_end_matches(',').to_string();
+    if !text.ends_with(']') {
+        text.push(']');
+    }
+    let events: Vec<TraceEvent> =
+        serde_json::from_str(&text).map_err(|e| TraceError::Malformed(e.to_string()))?;
+
+    let mut open: BTreeMap<u64, Vec<(String, f64)>> = BTreeMap::new();
+    let mut spans = Vec::new();
+    for event in events {
+        match event.ph.as_str() {
+            "B" => {
+                // spans without fields still get the `: ` before them
+                let name = match event.name.strip_suffix(": ") {
+                    Some(name) => name.to_string(),
+                    None => event.name,
+                };
+                open.entry(event.tid).or_default().push((name, event.ts));
+            }
+            "E" => {
+                if let Some((name, start)) = open.get_mut(&event.tid).and_then(|open| open.pop()) {
+                    spans.push(Span {
+                        name,
+                        start,
+                        duration: event.ts - start,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    spans.sort_by(|a, b| a.start.total_cmp(&b.start));
+    Ok(spans)
+}
+
+/// How long a span takes per frame, all its runs in a frame added up, in milliseconds.
+/// Frames it didn't run in count as 0.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SpanStats {
+    /// Runs over all frames.
+    pub calls: usize,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub max_ms: f64,
+}
+
+impl SpanStats {
+    /// Stats of one value per frame, in milliseconds.
+    pub fn from_frames(calls: usize, frames: &[f64]) -> Self {
+        if frames.is_empty() {
+            return SpanStats::default();
+        }
+        let mut sorted = frames.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        // nearest rank
+        let percentile = |p: f64| sorted[((p * sorted.len() as f64).ceil() as usize).max(1) - 1];
+        SpanStats {
+            calls,
+            mean_ms: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            p50_ms: percentile(0.5),
+            p95_ms: percentile(0.95),
+            max_ms: sorted[sorted.len() - 1],
+        }
+    }
+}
+
+/// Per frame stats of every span of a trace.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TraceSummary {
+    /// Frames measured.
+    pub frames: usize,
+    /// The whole frame.
+    pub frame: SpanStats,
+    /// Every other span by name, spans outside the measured frames are left out.
+    pub spans: BTreeMap<String, SpanStats>,
+}
+
+impl TraceSummary {
+    /// Sum up `spans`, leaving out the first `skip_frames` frames while shaders compile and
+    /// assets load.
+    pub fn new(spans: &[Span], skip_frames: usize) -> Self {
+        let frames: Vec<&Span> = spans
+            .iter()
+            .filter(|span| span.name == FRAME_SPAN)
+            .skip(skip_frames)
+            .collect();
+        let frame_ms: Vec<f64> = frames.iter().map(|frame| frame.duration / 1000.0).collect();
+
+        let mut per_frame: BTreeMap<&str, (usize, Vec<f64>)> = BTreeMap::new();
+        for span in spans.iter().filter(|span| span.name != FRAME_SPAN) {
+            // the last frame that started before the span, if the span started before it ended
+            let index = frames.partition_point(|frame| frame.start <= span.start);
+            let frame = match index.checked_sub(1) {
+                Some(frame) if span.start < frames[frame].start + frames[frame].duration => frame,
+                _ => continue,
+            };
+            let (calls, times) = per_frame
+                .entry(&span.name)
+                .or_insert_with(|| (0, vec![0.0; frames.len()]));
+            *calls += 1;
+            times[frame] += span.duration / 1000.0;
+        }
+
+        TraceSummary {
+            frames: frames.len(),
+            frame: SpanStats::from_frames(frames.len(), &frame_ms),
+            spans: per_frame
+                .into_iter()
+                .map(|(name, (calls, times))| {
+                    (name.to_string(), SpanStats::from_frames(calls, &times))
+                })
+                .collect(),
+        }
+    }
+
+    /// The frame and then every span of either summary, the biggest change in mean time first.
+    pub fn diff(&self, new: &TraceSummary) -> Vec<SpanDiff> {
+        let mut spans: Vec<SpanDiff> = self
+            .spans
+            .keys()
+            .chain(new.spans.keys())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|name| SpanDiff {
+                name: name.clone(),
+                base: self.spans.get(name).cloned().unwrap_or_default(),
+                new: new.spans.get(name).cloned().unwrap_or_default(),
+            })
+            .collect();
+        spans.sort_by(|a, b| {
+            let delta = |span: &SpanDiff| (span.new.mean_ms - span.base.mean_ms).abs();
+            delta(b).total_cmp(&delta(a))
+        });
+        spans.insert(
+            0,
+            SpanDiff {
+                name: FRAME_SPAN.to_string(),
+                base: self.frame.clone(),
+                new: new.frame.clone(),
+            },
+        );
+        spans
+    }
+}
+
+/// A span in two summaries, stats are zero in the one it's missing from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpanDiff {
+    pub name: String,
+    pub base: SpanStats,
+    pub new: SpanStats,
+}
+
+impl SpanDiff {
+    /// How much the mean per frame changed, `0.1` is 10% slower. `None` for new spans.
+    pub fn change(&self) -> Option<f64> {
+        (self.base.mean_ms > 0.0).then(|| self.new.mean_ms / self.base.mean_ms - 1.0)
+    }
+}
@@ -0,0 +1,87 @@
+use crowded_plaza_trace::{parse_trace, SpanStats, TraceError, TraceSummary};
+
+// three frames of 10, 20 and 30 ms, `work` runs on another thread in the first two and twice
+// in the last, the trace is cut off inside a fourth frame like bevy leaves it
+const TRACE: &str = r#"[{"ph":"M","pid":1,"name":"thread_name","tid":0,"args":{"name":"main"}},
+{"ph":"B","pid":1,"ts":0.0,"name":"frame: ","tid":0},
+{"ph":"B","pid":1,"ts":1000.0,"name":"work: name=\"a\"","tid":1},
+{"ph":"E","pid":1,"ts":3000.0,"name":"work: name=\"a\"","tid":1},
+{"ph":"E","pid":1,"ts":10000.0,"name":"frame: ","tid":0},
+{"ph":"B","pid":1,"ts":10000.0,"name":"frame: ","tid":0},
+{"ph":"B","pid":1,"ts":11000.0,"name":"work: name=\"a\"","tid":1},
+{"ph":"E","pid":1,"ts":15000.0,"name":"work: name=\"a\"","tid":1},
+{"ph":"E","pid":1,"ts":30000.0,"name":"frame: ","tid":0},
+{"ph":"B","pid":1,"ts":30000.0,"name":"frame: ","tid":0},
+{"ph":"B","pid":1,"ts":31000.0,"name":"work: name=\"a\"","tid":1},
+{"ph":"E","pid":1,"ts":32000.0,"name":"work: name=\"a\"","tid":1},
+{"ph":"B","pid":1,"ts":40000.0,"name":"work: name=\"a\"","tid":1},
+{"ph":"E","pid":1,"ts":45000.0,"name":"work: name=\"a\"","tid":1},
+{"ph":"E","pid":1,"ts":60000.0,"name":"frame: ","tid":0},
+{"ph":"B","pid":1,"ts":60000.0,"name":"frame: ","tid":0},
+{"ph":"B","pid":1,"ts":61000.0,"name":"work: name=\"a\"","tid":1},
+"#;
+
+#[test]
+fn parses_unclosed_traces() {
+    let spans = parse_trace(TRACE.as_bytes()).unwrap();
+    assert_eq!(spans.len(), 7);
+    assert_eq!(spans[0].name, "frame");
+    assert_eq!(spans[1].name, "work: name=\"a\"");
+    assert_eq!(spans[1].duration, 2000.0);
+    assert!(matches!(
+        parse_trace(b"{\"ph\":\"B\"}"),
+        Err(TraceError::Malformed(_))
+    ));
+}
+
+#[test]
+fn sums_up_spans_per_frame() {
+    let spans = parse_trace(TRACE.as_bytes()).unwrap();
+
+    let summary = TraceSummary::new(&spans, 0);
+    assert_eq!(summary.frames, 3);
+    assert_eq!(summary.frame.mean_ms, 20.0);
+    assert_eq!(summary.frame.p50_ms, 20.0);
+    assert_eq!(summary.frame.max_ms, 30.0);
+    let work = &summary.spans["work: name=\"a\""];
+    assert_eq!(work.calls, 4);
+    assert_eq!(work.mean_ms, 4.0);
+    assert_eq!(work.p95_ms, 6.0);
+
+    let summary = TraceSummary::new(&spans, 1);
+    assert_eq!(summary.frames, 2);
+    assert_eq!(summary.frame.mean_ms, 25.0);
+    assert_eq!(summary.spans["work: name=\"a\""].calls, 3);
+}
+
+#[test]
+fn diffs_summaries() {
+    let stats = |mean_ms| SpanStats {
+        mean_ms,
+        ..SpanStats::default()
+    };
+    let base = TraceSummary {
+        frame: stats(20.0),
+        spans: [("a", 4.0), ("b", 1.0), ("gone", 2.0)]
+            .into_iter()
+            .map(|(name, mean_ms)| (name.to_string(), stats(mean_ms)))
+            .collect(),
+        ..TraceSummary::default()
+    };
+    let new = TraceSummary {
+        frame: stats(25.0),
+        spans: [("a", 5.0), ("b", 4.0), ("added", 0.5)]
+            .into_iter()
+            .map(|(name, mean_ms)| (name.to_string(), stats(mean_ms)))
+            .collect(),
+        ..TraceSummary::default()
+    };
+
+    let diff = base.diff(&new);
+    let names: Vec<&str> = diff.iter().map(|span| span.name.as_str()).collect();
+    assert_eq!(names, ["frame", "b", "gone", "a", "added"]);
+    assert_eq!(diff[0].change(), Some(0.25));
+    assert_eq!(diff[1].change(), Some(3.0));
+    assert_eq!(diff[2].change(), Some(-1.0));
+    assert_eq!(diff[4].change(), None);
+}
@@ -1,9 +1,83 @@
+//! Rotates a grid of cubes, the bevy side of the performance comparison in BENCHMARK.md.
+//!
+//! ```text
+//! cargo run --release --example many_cubes -- [--width 200] [--height 200] [--depth 50]
+//!     [--frames 600] [--warmup 60] [--summary many_cubes.summary.json]
+//! ```
+//!
+//! Without `--frames` it runs until the window is closed. With it, it exits after `--warmup`
+//! plus `--frames` frames and sums up the spans of the measured frames from the `trace_chrome`
+//! trace into a JSON file, see [`TraceSummary`].
 use bevy::{
+    app::AppExit,
     diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin},
     prelude::*,
+    winit::WinitSettings,
 };
+use crowded_plaza_trace::{parse_trace, TraceSummary};
+use serde::Serialize;
+use std::time::SystemTime;
+
+// helper: the value of `--name value`, parsed
+fn flag<T: std::str::FromStr>(name: &str) -> Option<T> {
+    let flag = format!("--{}", name);
+    let value = std::env::args().skip_while(|arg| *arg != flag).nth(1)?;
+    match value.parse() {
+        Ok(value) => Some(value),
+        Err(_) => panic!("invalid value `{}` for {}", value, flag),
+    }
+}
+
+#[derive(Clone, Copy)]
+struct CubeGrid {
+    width: usize,
+    height: usize,
+    depth: usize,
+}
+
+// frames before measuring, then frames measured
+#[derive(Clone, Copy)]
+struct FrameCount {
+    warmup: usize,
+    frames: usize,
+}
+
+#[derive(Serialize)]
+struct BenchmarkSummary<'a> {
+    width: usize,
+    height: usize,
+    depth: usize,
+    cubes: usize,
+    trace: &'a str,
+    #[serde(flatten)]
+    summary: TraceSummary,
+}
+
 fn main() {
-    App::new()
+    let grid = CubeGrid {
+        width: flag("width").unwrap_or(200),
+        height: flag("height").unwrap_or(200),
+        depth: flag("depth").unwrap_or(50),
+    };
+    let frame_count = flag("frames").map(|frames| FrameCount {
+        warmup: flag("warmup").unwrap_or(60),
+        frames,
+    });
+    let summary_path =
+        flag::<String>("summary").unwrap_or_else(|| "many_cubes.summary.json".to_string());
+    let started = unix_seconds();
+
+    let mut app = App::new();
+    if let Some(frame_count) = frame_count {
+        // the trace is only written out once the app is dropped
+        app.insert_resource(WinitSettings {
+            return_from_run: true,
+            ..default()
+        })
+        .insert_resource(frame_count)
+        .add_system(exit_after_frames);
+    }
+    app.insert_resource(grid)
         .add_plugins(DefaultPlugins)
         .add_plugin(FrameTimeDiagnosticsPlugin::default())
         .add_plugin(LogDiagnosticsPlugin::default())
@@ -11,6 +85,56 @@ fn main() {
         .add_system(text_update_system)
         .add_system(rotate_cube)
         .run();
+
+    if let Some(frame_count) = frame_count {
+        write_summary(&summary_path, started, frame_count.warmup, &grid);
+    }
+}
+
+fn unix_seconds() -> u64 {
+    SystemTime::UNIX_EPOCH.elapsed().unwrap().as_secs()
+}
+
+fn exit_after_frames(
+    mut frame: Local<usize>,
+    frame_count: Res<FrameCount>,
+    mut exit: EventWriter<AppExit>,
+) {
+    *frame += 1;
+    if *frame >= frame_count.warmup + frame_count.frames {
+        exit.send(AppExit);
+    }
+}
+
+// the trace of this run is named after the second the app started in
+fn write_summary(path: &str, started: u64, warmup: usize, grid: &CubeGrid) {
+    let trace = (started..=unix_seconds())
+        .rev()
+        .map(|seconds| format!("trace-{}.json", seconds))
+        .find(|trace| std::path::Path::new(trace).exists())
+        .expect("no trace written, is the trace_chrome feature on?");
+    let bytes = std::fs::read(&trace).expect("cannot read trace");
+    let spans = parse_trace(&bytes).unwrap_or_else(|e| panic!("{}: {}", trace, e));
+    let summary = BenchmarkSummary {
+        width: grid.width,
+        height: grid.height,
+        depth: grid.depth,
+        cubes: cube_count(grid),
+        trace: &trace,
+        summary: TraceSummary::new(&spans, warmup),
+    };
+    let json = serde_json::to_string_pretty(&summary).unwrap();
+    std::fs::write(path, json).expect("cannot write summary");
+    info!(
+        "{} frames of {} cubes, {:.2} ms a frame, summary in {}",
+        summary.summary.frames, summary.cubes, summary.summary.frame.mean_ms, path
+    );
+}
+
+// every 10th row and column is left out
+fn cube_count(grid: &CubeGrid) -> usize {
+    let kept = |n: usize| n - n.div_ceil(10);
+    kept(grid.width) * kept(grid.height) * grid.depth
 }
 
 #[derive(Component)]
@@ -20,27 +144,25 @@ struct CubeComponent {}
 struct FpsText;
 
 fn setup(
+    grid: Res<CubeGrid>,
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     asset_server: Res<AssetServer>,
 ) {
-    const WIDTH: usize = 200;
-    const HEIGHT: usize = 200;
-    const DEPTH: usize = 50;
     let mesh = meshes.add(Mesh::from(shape::Cube { size: 1.0 }));
     let material = materials.add(StandardMaterial {
         base_color: Color::WHITE,
         ..default()
     });
 
-    for x in 0..WIDTH {
-        for y in 0..HEIGHT {
+    for x in 0..grid.width {
+        for y in 0..grid.height {
             // introduce spaces to break any kind of moiré pattern
             if x % 10 == 0 || y % 10 == 0 {
                 continue;
             }
-            for z in 0..DEPTH {
+            for z in 0..grid.depth {
                 commands
                     .spawn_bundle(PbrBundle {
                         mesh: mesh.clone_weak(),
@@ -58,7 +180,7 @@ fn setup(
     }
     // camera
     commands.spawn_bundle(PerspectiveCameraBundle {
-        transform: Transform::from_xyz(WIDTH as f32, HEIGHT as f32, WIDTH as f32),
+        transform: Transform::from_xyz(grid.width as f32, grid.height as f32, grid.width as f32),
         ..default()
     });
 
@@ -71,7 +193,7 @@ fn setup(
         mesh,
         material,
         transform: Transform {
-            translation: Vec3::new(0.0, HEIGHT as f32 * 2.5, 0.0),
+            translation: Vec3::new(0.0, grid.height as f32 * 2.5, 0.0),
             scale: Vec3::splat(5.0),
             ..default()
         },
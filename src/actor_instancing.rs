@@ -0,0 +1,346 @@
+use bevy::{
+    core_pipeline::Opaque3d,
+    ecs::system::{lifetimeless::*, SystemParamItem},
+    pbr::{
+        MeshPipeline, MeshPipelineKey, MeshUniform, NotShadowCaster, SetMeshBindGroup,
+        SetMeshViewBindGroup,
+    },
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        mesh::{GpuBufferInfo, MeshVertexBufferLayout},
+        render_asset::RenderAssets,
+        render_component::{ExtractComponent, ExtractComponentPlugin},
+        render_phase::{
+            AddRenderCommand, DrawFunctions, EntityRenderCommand, RenderCommandResult, RenderPhase,
+            SetItemPipeline, TrackedRenderPass,
+        },
+        render_resource::*,
+        renderer::{RenderDevice, RenderQueue},
+        view::{ExtractedView, Msaa, NoFrustumCulling},
+        RenderApp, RenderStage,
+    },
+    utils::HashMap,
+};
+use bytemuck::{Pod, Zeroable};
+use crowded_plaza_sim::{
+    Actor, Faction, FactionRegistry, GameConfig, PreviousTranslation, SimClock,
+};
+use std::f32::consts::PI;
+
+use crate::{faction_color, online::ServerLink, overstep_fraction, ActorAssets, PawnMesh};
+
+/// Crowds of at least this many actors are drawn instanced, smaller ones keep a mesh per actor
+/// and cast shadows.
+pub const INSTANCED_ACTOR_COUNT: i32 = 10_000;
+
+const ACTOR_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x5c3b_8e0f_2a71_94d6);
+
+pub fn is_instanced(config: &GameConfig) -> bool {
+    config.actor_count >= INSTANCED_ACTOR_COUNT
+}
+
+// one drawn actor, as the shader reads it
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+struct ActorInstance {
+    position: Vec3,
+    // keeps the color aligned
+    padding: f32,
+    color: [f32; 4],
+}
+
+/// Every actor of a faction but its pawn, drawn with a single instanced draw call of the
+/// shared actor mesh.
+#[derive(Component)]
+pub struct ActorBatch {
+    faction: Faction,
+    color: [f32; 4],
+    instances: Vec<ActorInstance>,
+}
+
+impl ExtractComponent for ActorBatch {
+    type Query = &'static ActorBatch;
+    type Filter = ();
+
+    fn extract_component(batch: bevy::ecs::query::QueryItem<Self::Query>) -> Self {
+        ActorBatch {
+            faction: batch.faction,
+            color: batch.color,
+            instances: batch.instances.clone(),
+        }
+    }
+}
+
+/// Draws [`ActorBatch`]es. Crowds smaller than [`INSTANCED_ACTOR_COUNT`] don't spawn any.
+pub struct ActorInstancingPlugin;
+
+impl Plugin for ActorInstancingPlugin {
+    fn build(&self, app: &mut App) {
+        app.world.resource_mut::<Assets<Shader>>().set_untracked(
+            ACTOR_SHADER_HANDLE,
+            Shader::from_wgsl(include_str!("actor_instancing.wgsl")),
+        );
+        app.add_plugin(ExtractComponentPlugin::<ActorBatch>::default());
+        app.sub_app_mut(RenderApp)
+            .add_render_command::<Opaque3d, DrawActorBatch>()
+            .init_resource::<ActorBatchPipeline>()
+            .init_resource::<ActorBatchBuffers>()
+            .init_resource::<SpecializedMeshPipelines<ActorBatchPipeline>>()
+            .add_system_to_stage(RenderStage::Prepare, prepare_actor_batch_buffers)
+            .add_system_to_stage(RenderStage::Queue, queue_actor_batches);
+    }
+}
+
+/// Spawn an empty [`ActorBatch`] for every faction of the match.
+pub fn spawn_actor_batches(
+    commands: &mut Commands,
+    registry: &FactionRegistry,
+    assets: &ActorAssets,
+) {
+    for (faction, _) in registry.iter() {
+        commands
+            .spawn_bundle((
+                assets.actor_mesh.clone(),
+                Transform::from_rotation(Quat::from_rotation_x(PI * 0.5)),
+                GlobalTransform::default(),
+                ActorBatch {
+                    faction,
+                    color: faction_color(registry, faction).as_linear_rgba_f32(),
+                    instances: Vec::new(),
+                },
+                Visibility::default(),
+                ComputedVisibility::default(),
+            ))
+            // the instances are all over the plaza, not where the batch entity is
+            .insert(NoFrustumCulling)
+            .insert(NotShadowCaster);
+    }
+}
+
+// system: put every actor without the pawn mesh into the batch of its faction, between its last
+// two simulated positions
+pub fn update_actor_batches_system(
+    clock: Res<SimClock>,
+    link: Option<NonSend<ServerLink>>,
+    actor_query: Query<(&Actor, &Transform, &PreviousTranslation), Without<PawnMesh>>,
+    mut batch_query: Query<&mut ActorBatch>,
+) {
+    let mut batches: Vec<Mut<ActorBatch>> = batch_query.iter_mut().collect();
+    let mut batch_of = HashMap::default();
+    for (index, batch) in batches.iter_mut().enumerate() {
+        batch.instances.clear();
+        batch_of.insert(batch.faction, index);
+    }
+    let alpha = overstep_fraction(&clock, link.as_deref());
+    for (actor, tr, previous) in actor_query.iter() {
+        if let Some(index) = batch_of.get(&actor.faction) {
+            let batch = &mut batches[*index];
+            let color = batch.color;
+            batch.instances.push(ActorInstance {
+                position: previous.0.lerp(tr.translation, alpha),
+                padding: 0.0,
+                color,
+            });
+        }
+    }
+}
+
+// the instances of a batch on the gpu, for this frame
+#[derive(Component)]
+struct ActorBatchBuffer {
+    buffer: Buffer,
+    length: usize,
+}
+
+// the buffer of every faction's batch and how many instances fit, kept between frames as the
+// batch entities are extracted anew every frame
+#[derive(Default)]
+struct ActorBatchBuffers(HashMap<Faction, (Buffer, usize)>);
+
+fn prepare_actor_batch_buffers(
+    mut commands: Commands,
+    query: Query<(Entity, &ActorBatch)>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    mut buffers: ResMut<ActorBatchBuffers>,
+) {
+    let mut factions = Vec::new();
+    for (entity, batch) in query.iter() {
+        factions.push(batch.faction);
+        let length = batch.instances.len();
+        if length == 0 {
+            continue;
+        }
+        let contents: &[u8] = bytemuck::cast_slice(batch.instances.as_slice());
+        let buffer = match buffers.0.get(&batch.faction) {
+            Some((buffer, capacity)) if *capacity >= length => {
+                render_queue.write_buffer(buffer, 0, contents);
+                buffer.clone()
+            }
+            // grow with some room, crowds change a little every frame
+            _ => {
+                let capacity = length.next_power_of_two();
+                let buffer = render_device.create_buffer(&BufferDescriptor {
+                    label: Some("actor batch buffer"),
+                    size: (capacity * std::mem::size_of::<ActorInstance>()) as u64,
+                    usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+                render_queue.write_buffer(&buffer, 0, contents);
+                buffers.0.insert(batch.faction, (buffer.clone(), capacity));
+                buffer
+            }
+        };
+        commands
+            .entity(entity)
+            .insert(ActorBatchBuffer { buffer, length });
+    }
+    buffers.0.retain(|faction, _| factions.contains(faction));
+}
+
+#[allow(clippy::too_many_arguments)]
+fn queue_actor_batches(
+    opaque_3d_draw_functions: Res<DrawFunctions<Opaque3d>>,
+    batch_pipeline: Res<ActorBatchPipeline>,
+    msaa: Res<Msaa>,
+    mut pipelines: ResMut<SpecializedMeshPipelines<ActorBatchPipeline>>,
+    mut pipeline_cache: ResMut<PipelineCache>,
+    meshes: Res<RenderAssets<Mesh>>,
+    batches: Query<(Entity, &MeshUniform, &Handle<Mesh>, &ActorBatch)>,
+    mut views: Query<(&ExtractedView, &mut RenderPhase<Opaque3d>)>,
+) {
+    let draw_batch = opaque_3d_draw_functions
+        .read()
+        .get_id::<DrawActorBatch>()
+        .unwrap();
+    let msaa_key = MeshPipelineKey::from_msaa_samples(msaa.samples);
+
+    for (view, mut opaque_phase) in views.iter_mut() {
+        let view_row_2 = view.transform.compute_matrix().row(2);
+        for (entity, mesh_uniform, mesh_handle, batch) in batches.iter() {
+            let mesh = match meshes.get(mesh_handle) {
+                Some(mesh) if !batch.instances.is_empty() => mesh,
+                _ => continue,
+            };
+            let key = msaa_key | MeshPipelineKey::from_primitive_topology(mesh.primitive_topology);
+            let pipeline =
+                match pipelines.specialize(&mut pipeline_cache, &batch_pipeline, key, &mesh.layout)
+                {
+                    Ok(pipeline) => pipeline,
+                    Err(e) => {
+                        error!("{}", e);
+                        continue;
+                    }
+                };
+            opaque_phase.add(Opaque3d {
+                entity,
+                pipeline,
+                draw_function: draw_batch,
+                distance: view_row_2.dot(mesh_uniform.transform.col(3)),
+            });
+        }
+    }
+}
+
+struct ActorBatchPipeline {
+    mesh_pipeline: MeshPipeline,
+}
+
+impl FromWorld for ActorBatchPipeline {
+    fn from_world(world: &mut World) -> Self {
+        ActorBatchPipeline {
+            mesh_pipeline: world.resource::<MeshPipeline>().clone(),
+        }
+    }
+}
+
+impl SpecializedMeshPipeline for ActorBatchPipeline {
+    type Key = MeshPipelineKey;
+
+    fn specialize(
+        &self,
+        key: Self::Key,
+        layout: &MeshVertexBufferLayout,
+    ) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError> {
+        let mut descriptor = self.mesh_pipeline.specialize(key, layout)?;
+        descriptor.label = Some("actor_batch_pipeline".into());
+        descriptor.vertex.shader = ACTOR_SHADER_HANDLE.typed();
+        // locations 0 to 2 are the mesh's position, normal and uv
+        descriptor.vertex.buffers.push(VertexBufferLayout {
+            array_stride: std::mem::size_of::<ActorInstance>() as u64,
+            step_mode: VertexStepMode::Instance,
+            attributes: vec![
+                VertexAttribute {
+                    format: VertexFormat::Float32x4,
+                    offset: 0,
+                    shader_location: 3,
+                },
+                VertexAttribute {
+                    format: VertexFormat::Float32x4,
+                    offset: VertexFormat::Float32x4.size(),
+                    shader_location: 4,
+                },
+            ],
+        });
+        descriptor.fragment.as_mut().unwrap().shader = ACTOR_SHADER_HANDLE.typed();
+        descriptor.layout = Some(vec![
+            self.mesh_pipeline.view_layout.clone(),
+            self.mesh_pipeline.mesh_layout.clone(),
+        ]);
+        Ok(descriptor)
+    }
+}
+
+type DrawActorBatch = (
+    SetItemPipeline,
+    SetMeshViewBindGroup<0>,
+    SetMeshBindGroup<1>,
+    DrawMeshInstanced,
+);
+
+struct DrawMeshInstanced;
+
+impl EntityRenderCommand for DrawMeshInstanced {
+    type Param = (
+        SRes<RenderAssets<Mesh>>,
+        SQuery<Read<Handle<Mesh>>>,
+        SQuery<Read<ActorBatchBuffer>>,
+    );
+
+    #[inline]
+    fn render<'w>(
+        _view: Entity,
+        item: Entity,
+        (meshes, mesh_query, buffer_query): SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let (mesh_handle, batch_buffer) = match (mesh_query.get(item), buffer_query.get_inner(item))
+        {
+            (Ok(mesh_handle), Ok(batch_buffer)) => (mesh_handle, batch_buffer),
+            _ => return RenderCommandResult::Failure,
+        };
+        let gpu_mesh = match meshes.into_inner().get(mesh_handle) {
+            Some(gpu_mesh) => gpu_mesh,
+            None => return RenderCommandResult::Failure,
+        };
+
+        pass.set_vertex_buffer(0, gpu_mesh.vertex_buffer.slice(..));
+        pass.set_vertex_buffer(1, batch_buffer.buffer.slice(..));
+        match &gpu_mesh.buffer_info {
+            GpuBufferInfo::Indexed {
+                buffer,
+                index_format,
+                count,
+            } => {
+                pass.set_index_buffer(buffer.slice(..), 0, *index_format);
+                pass.draw_indexed(0..*count, 0, 0..batch_buffer.length as u32);
+            }
+            GpuBufferInfo::NonIndexed { vertex_count } => {
+                pass.draw(0..*vertex_count, 0..batch_buffer.length as u32);
+            }
+        }
+        RenderCommandResult::Success
+    }
+}
@@ -0,0 +1,41 @@
+#import bevy_pbr::mesh_view_bind_group
+#import bevy_pbr::mesh_struct
+
+// the batch entity's transform, it only turns the mesh upright
+[[group(1), binding(0)]]
+var<uniform> mesh: Mesh;
+
+struct Vertex {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] normal: vec3<f32>;
+    [[location(2)]] uv: vec2<f32>;
+
+    [[location(3)]] i_position: vec4<f32>;
+    [[location(4)]] i_color: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] world_normal: vec3<f32>;
+    [[location(1)]] color: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let world_position = mesh.model * vec4<f32>(vertex.position, 1.0)
+        + vec4<f32>(vertex.i_position.xyz, 0.0);
+
+    var out: VertexOutput;
+    out.clip_position = view.view_proj * world_position;
+    out.world_normal = (mesh.model * vec4<f32>(vertex.normal, 0.0)).xyz;
+    out.color = vertex.i_color;
+    return out;
+}
+
+// light from above, far cheaper than the clustered lights of the pbr shader
+[[stage(fragment)]]
+fn fragment(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let light = normalize(vec3<f32>(-0.3, 0.2, 1.0));
+    let diffuse = max(dot(normalize(in.world_normal), light), 0.0);
+    return vec4<f32>(in.color.rgb * (0.35 + 0.65 * diffuse), in.color.a);
+}
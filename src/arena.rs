@@ -0,0 +1,186 @@
+use bevy::{
+    pbr::NotShadowCaster,
+    prelude::*,
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+};
+use crowded_plaza_sim::{GameConfig, Obstacle, ObstacleKind};
+
+// corners of a circular boundary
+const OUTLINE_SEGMENTS: usize = 128;
+// width of the line on the edge of the plaza, in units
+const OUTLINE_WIDTH: f32 = 0.2;
+// just above the ground, under the safe zone
+const OUTLINE_HEIGHT: f32 = 0.005;
+
+// how tall each kind of obstacle is drawn, in units, and its color
+fn obstacle_look(kind: ObstacleKind) -> (f32, Color) {
+    match kind {
+        ObstacleKind::Building => (3.0, Color::rgb(0.55, 0.5, 0.45)),
+        ObstacleKind::Fountain => (0.6, Color::rgb(0.4, 0.6, 0.8)),
+        ObstacleKind::Wall => (1.2, Color::rgb(0.45, 0.45, 0.45)),
+    }
+}
+
+// helper: a flat line along the closed `points`, a quad per side, long enough to cover the
+// corners
+fn outline_mesh(points: &[Vec2]) -> Mesh {
+    let mut positions = Vec::with_capacity(points.len() * 4);
+    let mut indices = Vec::with_capacity(points.len() * 6);
+    for (i, a) in points.iter().enumerate() {
+        let b = points[(i + 1) % points.len()];
+        let along = (b - *a).normalize_or_zero() * OUTLINE_WIDTH * 0.5;
+        let across = along.perp();
+        let first = positions.len() as u32;
+        for corner in [
+            *a - along - across,
+            b + along - across,
+            b + along + across,
+            *a - along + across,
+        ] {
+            positions.push([corner.x, corner.y, 0.0]);
+        }
+        indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(
+        Mesh::ATTRIBUTE_NORMAL,
+        vec![[0.0, 0.0, 1.0]; positions.len()],
+    );
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0, 0.0]; positions.len()]);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+// helper: the edge of the plaza, where the boundary turns actors around
+pub fn spawn_boundary_outline(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    config: &GameConfig,
+) {
+    let outline = config.boundary().outline(OUTLINE_SEGMENTS);
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: meshes.add(outline_mesh(&outline)),
+            material: materials.add(StandardMaterial {
+                base_color: Color::rgb(0.3, 0.3, 0.3),
+                unlit: true,
+                cull_mode: None,
+                ..default()
+            }),
+            transform: Transform::from_xyz(0.0, 0.0, OUTLINE_HEIGHT),
+            ..default()
+        })
+        .insert(NotShadowCaster);
+}
+
+// helper: the triangles covering the polygon, by cutting off ears, so concave obstacles get a
+// proper top. `ccw` is the winding of the corners
+fn triangulate(points: &[Vec2], ccw: bool) -> Vec<u32> {
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut indices = Vec::with_capacity(points.len() * 3);
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let ear = (0..n).find(|&i| {
+            let [a, b, c] = [
+                points[remaining[(i + n - 1) % n]],
+                points[remaining[i]],
+                points[remaining[(i + 1) % n]],
+            ];
+            let turn = (b - a).perp_dot(c - b);
+            let convex = if ccw { turn > 0.0 } else { turn < 0.0 };
+            // no other corner inside the triangle
+            convex
+                && remaining.iter().all(|&j| {
+                    let p = points[j];
+                    p == a
+                        || p == b
+                        || p == c
+                        || !((b - a).perp_dot(p - a) * turn > 0.0
+                            && (c - b).perp_dot(p - b) * turn > 0.0
+                            && (a - c).perp_dot(p - c) * turn > 0.0)
+                })
+        });
+        // degenerate corners left, close what is left as a fan
+        let i = match ear {
+            Some(i) => i,
+            None => break,
+        };
+        indices.extend([
+            remaining[(i + n - 1) % n] as u32,
+            remaining[i] as u32,
+            remaining[(i + 1) % n] as u32,
+        ]);
+        remaining.remove(i);
+    }
+    for i in 1..remaining.len().saturating_sub(1) {
+        indices.extend([
+            remaining[0] as u32,
+            remaining[i] as u32,
+            remaining[i + 1] as u32,
+        ]);
+    }
+    indices
+}
+
+// helper: an obstacle pulled up from the ground to `height`, sides facing out and a flat top
+fn obstacle_mesh(corners: &[Vec2], height: f32) -> Mesh {
+    let winding: f32 = corners
+        .iter()
+        .enumerate()
+        .map(|(i, a)| a.perp_dot(corners[(i + 1) % corners.len()]))
+        .sum();
+    let ccw = winding > 0.0;
+    let mut positions = Vec::with_capacity(corners.len() * 5);
+    let mut normals = Vec::with_capacity(corners.len() * 5);
+    let mut indices = Vec::with_capacity(corners.len() * 9);
+    // top
+    for corner in corners {
+        positions.push([corner.x, corner.y, height]);
+        normals.push([0.0, 0.0, 1.0]);
+    }
+    indices.extend(triangulate(corners, ccw));
+    // sides, a quad each with its own normal
+    for (i, a) in corners.iter().enumerate() {
+        let b = corners[(i + 1) % corners.len()];
+        let side = (b - *a).perp().normalize_or_zero();
+        let outward = if ccw { -side } else { side };
+        let first = positions.len() as u32;
+        for (corner, z) in [(*a, 0.0), (b, 0.0), (b, height), (*a, height)] {
+            positions.push([corner.x, corner.y, z]);
+            normals.push([outward.x, outward.y, 0.0]);
+        }
+        indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0, 0.0]; positions.len()]);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+// helper: the buildings, fountains and walls of the config's map
+pub fn spawn_obstacles(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    config: &GameConfig,
+) {
+    for Obstacle { kind, corners } in config.map.obstacles.iter() {
+        let (height, color) = obstacle_look(*kind);
+        let corners: Vec<Vec2> = corners.iter().map(|(x, y)| Vec2::new(*x, *y)).collect();
+        commands.spawn_bundle(PbrBundle {
+            mesh: meshes.add(obstacle_mesh(&corners, height)),
+            material: materials.add(StandardMaterial {
+                base_color: color,
+                perceptual_roughness: 0.9,
+                // either winding of the corners
+                cull_mode: None,
+                ..default()
+            }),
+            ..default()
+        });
+    }
+}
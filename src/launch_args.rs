@@ -0,0 +1,32 @@
+// launch options, read from `--name value` on the command line, the `CROWDED_PLAZA_NAME`
+// environment variable, or `?name=value` in the page url on wasm
+pub fn launch_arg(name: &str) -> Option<String> {
+    let flag = format!("--{}", name);
+    let mut args = std::env::args().skip_while(|arg| *arg != flag);
+    if let Some(value) = args.nth(1) {
+        return Some(value);
+    }
+
+    let env_name = format!("CROWDED_PLAZA_{}", name.to_uppercase());
+    if let Ok(value) = std::env::var(env_name) {
+        return Some(value);
+    }
+
+    url_query_arg(name)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn url_query_arg(name: &str) -> Option<String> {
+    let search = web_sys::window()?.location().search().ok()?;
+    search
+        .trim_start_matches('?')
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_string())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn url_query_arg(_name: &str) -> Option<String> {
+    None
+}
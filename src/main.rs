@@ -1,150 +1,385 @@
+mod actor_instancing;
+mod arena;
 mod canvas_resize;
+mod launch_args;
+mod online;
+mod replay_viewer;
+mod safe_zone;
+use actor_instancing::{
+    is_instanced, spawn_actor_batches, update_actor_batches_system, ActorInstancingPlugin,
+};
+use arena::{spawn_boundary_outline, spawn_obstacles};
 use bevy::{
-    asset::AssetPlugin,
+    asset::{AssetPlugin, AssetServerSettings},
     core_pipeline::CorePipelinePlugin,
     diagnostic::{Diagnostics, DiagnosticsPlugin, FrameTimeDiagnosticsPlugin},
     input::{touch::TouchPhase, InputPlugin},
+    log::LogPlugin,
     math::vec3,
     pbr::PbrPlugin,
     prelude::*,
     render::{camera::Camera3d, RenderPlugin},
     sprite::SpritePlugin,
     text::TextPlugin,
+    transform::TransformSystem,
     ui::UiPlugin,
     window::{WindowMode, WindowPlugin},
     winit::WinitPlugin,
 };
-use bevy_spatial::{KDTreeAccess2D, KDTreePlugin2D, SpatialAccess};
-use rand::{prelude::ThreadRng, thread_rng, Rng};
-use std::{collections::HashMap, f32::consts::PI};
+use crowded_plaza_sim::{
+    setup_match, Actor, Faction, FactionRegistry, GameConfig, GameConfigHandle,
+    GameConfigOverrides, GameConfigPlugin, GameMode, Heading, MatchEvent, Pawn, PlayerController,
+    PlayerInput, PreviousTranslation, ReplayPlayback, SimClock, SimDiagnosticsPlugin, SimSeed,
+    SimulationPlugin,
+};
+use launch_args::launch_arg;
+use online::{
+    apply_snapshot_system, connect_to_server, next_match_system, predict_pawn_system,
+    receive_server_messages_system, setup_online_match, wait_for_match_system, ServerLink,
+};
+use replay_viewer::{
+    load_replay, replay_controls_system, restore_replay_config_system, save_replay_system,
+    setup_replay_ui, start_recording_system, update_replay_ui_system,
+};
+use safe_zone::update_zone_ring_system;
+use std::f32::consts::PI;
 
 #[derive(Clone, Eq, PartialEq, Debug, Hash)]
 enum GameState {
+    Loading,
+    // picking the game mode, offline only
+    Menu,
     Playing,
     GameOver,
+    // watching a recorded match, see `replay_viewer`
+    Replay,
+    // playing a match simulated by a server, see `online`
+    Online,
 }
 
+// the seat of the player on this machine, the first one unless a server gave us another
+#[derive(Default)]
+struct LocalSeat(u32);
+
+// a line of the in-game scoreboard
 #[derive(Component)]
-struct Actor {
-    faction: i32,
-    velocity: Vec3,
-    accleration: Vec3,
+enum ScoreboardRow {
+    // the faction at this place of the ranking
+    Rank(usize),
+    // the player's place, when they are not in the rows above
+    Player,
 }
 
+// a button of the start menu, starts a match in its mode
 #[derive(Component)]
-struct Pawn;
-#[derive(Component)]
-struct PlayerController;
+struct ModeButton(GameMode);
 
 #[derive(Component)]
-struct OpponentController;
+struct FpsText;
 
+// the simulation's diagnostics under the fps, F3 shows and hides them
 #[derive(Component)]
-struct FactionText {
-    faction: i32,
-}
+struct DebugText;
+
+// whether the debug overlay is shown, kept between matches
+struct DebugOverlay(bool);
 
+// drawn with the pawn mesh, which stays after the pawn is eliminated and loses `Pawn`
 #[derive(Component)]
-struct FpsText;
+pub struct PawnMesh;
 
-const OPPONENT_MOVE_SCALE: f32 = 1.0;
-const PAWN_SPEED: f32 = 0.02;
-const WANDER_SPEED: f32 = 0.005;
-const NEIGHBOR_THRESHOLD: f32 = 0.5;
-const REPULSION_THRESHOLD: f32 = 0.2;
-const REPULSION_FACTOR: f32 = 0.001;
-const ALIGN_FACTOR: f32 = 0.01;
-const ATTRACT_FACTOR: f32 = 0.0003;
-const ACTOR_COUNT: i32 = 2000;
-const OPPONENT_COUNT: i32 = 5;
-const ACTION_REGION: f32 = 20.0;
-const GAME_TIME: f32 = 30.0;
-const CLEAR_COLOR: Color = Color::rgb(0.1, 0.1, 0.1);
-const LIGHT_COLOR: &str = "fdfff4";
-const AMBIENT_COLOR: &str = "d7deff";
+// meshes every actor shares, the materials are shared per faction in the `FactionRegistry`
+pub struct ActorAssets {
+    actor_mesh: Handle<Mesh>,
+    pawn_mesh: Handle<Mesh>,
+}
 
 fn main() {
-    App::new()
-        .insert_resource(WindowDescriptor {
-            #[cfg(target_arch = "wasm32")]
-            canvas: Some("#game".to_string()),
-            mode: WindowMode::BorderlessFullscreen,
-            ..Default::default()
-        })
-        .add_plugins(MinimalPlugins)
-        .add_plugin(TransformPlugin::default())
-        .add_plugin(HierarchyPlugin::default())
-        .add_plugin(DiagnosticsPlugin::default())
-        .add_plugin(InputPlugin::default())
-        .add_plugin(WindowPlugin::default())
-        .add_plugin(AssetPlugin::default())
-        .add_plugin(crate::canvas_resize::CanvasResizePlugin::default())
-        .add_plugin(WinitPlugin::default())
-        .add_plugin(RenderPlugin::default())
-        .add_plugin(CorePipelinePlugin::default())
-        .add_plugin(PbrPlugin::default())
-        .add_plugin(SpritePlugin::default())
-        .add_plugin(TextPlugin::default())
-        .add_plugin(UiPlugin::default())
-        .add_plugin(KDTreePlugin2D::<Actor> { ..default() })
-        .add_plugin(FrameTimeDiagnosticsPlugin::default())
-        .insert_resource(ClearColor(CLEAR_COLOR))
-        .insert_resource(AmbientLight {
-            brightness: 0.2,
-            color: Color::hex(AMBIENT_COLOR).unwrap(),
-        })
-        .add_startup_system(setup_game)
-        .add_state(GameState::Playing)
-        .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(setup_playing))
-        .add_system_set(
-            SystemSet::on_update(GameState::Playing)
-                .with_system(change_direction_player_system)
-                .with_system(change_direction_actor_system)
-                .with_system(change_direction_opponent_system)
-                .with_system(move_actor_system)
-                .with_system(move_pawn_system)
-                .with_system(change_actor_faction_system)
-                .with_system(update_camera_lookat_system)
-                .with_system(follow_pawn_system)
-                .with_system(repulse_actor_system)
-                .with_system(update_ui_system)
-                .with_system(countdown)
-                .with_system(text_update_system),
-        )
-        .add_system_set(SystemSet::on_exit(GameState::Playing).with_system(teardown))
-        .add_system_set(SystemSet::on_enter(GameState::GameOver).with_system(display_score))
-        .add_system_set(SystemSet::on_update(GameState::GameOver).with_system(replay_button_system))
-        .add_system_set(SystemSet::on_exit(GameState::GameOver).with_system(teardown))
-        .run();
+    let mut app = App::new();
+    app.insert_resource(WindowDescriptor {
+        #[cfg(target_arch = "wasm32")]
+        canvas: Some("#game".to_string()),
+        mode: WindowMode::BorderlessFullscreen,
+        ..Default::default()
+    })
+    .add_plugins(MinimalPlugins)
+    .add_plugin(LogPlugin)
+    .add_plugin(TransformPlugin::default())
+    .add_plugin(HierarchyPlugin::default())
+    .add_plugin(DiagnosticsPlugin::default())
+    .add_plugin(InputPlugin::default())
+    .add_plugin(WindowPlugin::default())
+    // hot-reload the game config on native, wasm can't watch files
+    .insert_resource(AssetServerSettings {
+        watch_for_changes: cfg!(not(target_arch = "wasm32")),
+        ..default()
+    })
+    .add_plugin(AssetPlugin::default())
+    .add_plugin(crate::canvas_resize::CanvasResizePlugin::default())
+    .add_plugin(WinitPlugin::default())
+    .add_plugin(RenderPlugin::default())
+    .add_plugin(CorePipelinePlugin::default())
+    .add_plugin(PbrPlugin::default())
+    .add_plugin(ActorInstancingPlugin)
+    .add_plugin(SpritePlugin::default())
+    .add_plugin(TextPlugin::default())
+    .add_plugin(UiPlugin::default())
+    .add_plugin(SimulationPlugin)
+    .add_plugin(FrameTimeDiagnosticsPlugin::default())
+    .add_plugin(SimDiagnosticsPlugin)
+    .insert_resource(DebugOverlay(
+        launch_arg("debug_overlay").is_some_and(|show| show == "true"),
+    ))
+    .insert_resource(AmbientLight {
+        brightness: 0.2,
+        ..default()
+    })
+    .init_resource::<LocalSeat>()
+    .add_startup_system(setup_game)
+    .add_system(apply_config_colors_system)
+    .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(setup_menu))
+    .add_system_set(SystemSet::on_update(GameState::Menu).with_system(menu_button_system))
+    .add_system_set(SystemSet::on_exit(GameState::Menu).with_system(teardown))
+    .add_system_set(
+        SystemSet::on_enter(GameState::Playing)
+            .with_system(setup_match)
+            .with_system(setup_playing.after(setup_match))
+            .with_system(start_recording_system.after(setup_match)),
+    )
+    .add_system_set(
+        SystemSet::on_update(GameState::Playing)
+            .with_system(change_direction_player_system)
+            .with_system(attach_actor_visuals_system)
+            .with_system(update_actor_material_system)
+            .with_system(update_ui_system)
+            .with_system(update_zone_ring_system)
+            .with_system(match_event_system)
+            .with_system(text_update_system)
+            .with_system(debug_overlay_system),
+    )
+    // the simulation ticks after `Update`, so render from where this frame's ticks left off
+    .add_system_set_to_stage(
+        CoreStage::PostUpdate,
+        SystemSet::on_update(GameState::Playing)
+            .with_system(update_camera_lookat_system.before(TransformSystem::TransformPropagate))
+            .with_system(
+                interpolate_actor_transforms_system.after(TransformSystem::TransformPropagate),
+            )
+            .with_system(update_actor_batches_system),
+    )
+    .add_system_set(SystemSet::on_exit(GameState::Playing).with_system(teardown))
+    .add_system_set(
+        SystemSet::on_enter(GameState::GameOver)
+            .with_system(display_score)
+            .with_system(save_replay_system),
+    )
+    .add_system_set(SystemSet::on_update(GameState::GameOver).with_system(replay_button_system))
+    .add_system_set(SystemSet::on_exit(GameState::GameOver).with_system(teardown))
+    // the replay viewer draws the match like `Playing`, with replay controls instead of input
+    .add_system_set(
+        SystemSet::on_enter(GameState::Replay)
+            .with_system(restore_replay_config_system.before(setup_match))
+            .with_system(setup_match)
+            .with_system(setup_playing.after(setup_match))
+            .with_system(setup_replay_ui),
+    )
+    .add_system_set(
+        SystemSet::on_update(GameState::Replay)
+            .with_system(attach_actor_visuals_system)
+            .with_system(update_actor_material_system)
+            .with_system(update_ui_system)
+            .with_system(update_zone_ring_system)
+            .with_system(text_update_system)
+            .with_system(debug_overlay_system)
+            .with_system(replay_controls_system)
+            .with_system(update_replay_ui_system),
+    )
+    .add_system_set_to_stage(
+        CoreStage::PostUpdate,
+        SystemSet::on_update(GameState::Replay)
+            .with_system(update_camera_lookat_system.before(TransformSystem::TransformPropagate))
+            .with_system(
+                interpolate_actor_transforms_system.after(TransformSystem::TransformPropagate),
+            )
+            .with_system(update_actor_batches_system),
+    )
+    .add_system_set(SystemSet::on_exit(GameState::Replay).with_system(teardown))
+    // online the server simulates the match, we draw its snapshots and send our steering
+    .add_system_set(
+        SystemSet::on_enter(GameState::Online)
+            .with_system(setup_online_match)
+            .with_system(setup_playing.after(setup_online_match)),
+    )
+    .add_system_set(
+        SystemSet::on_update(GameState::Online)
+            .with_system(change_direction_player_system)
+            .with_system(apply_snapshot_system)
+            .with_system(
+                predict_pawn_system
+                    .after(change_direction_player_system)
+                    .after(apply_snapshot_system),
+            )
+            .with_system(attach_actor_visuals_system)
+            .with_system(update_actor_material_system)
+            .with_system(update_ui_system)
+            .with_system(update_zone_ring_system)
+            .with_system(match_event_system)
+            .with_system(next_match_system)
+            .with_system(text_update_system)
+            .with_system(debug_overlay_system),
+    )
+    .add_system_set_to_stage(
+        CoreStage::PostUpdate,
+        SystemSet::on_update(GameState::Online)
+            .with_system(update_camera_lookat_system.before(TransformSystem::TransformPropagate))
+            .with_system(
+                interpolate_actor_transforms_system.after(TransformSystem::TransformPropagate),
+            )
+            .with_system(update_actor_batches_system),
+    )
+    .add_system_set(SystemSet::on_exit(GameState::Online).with_system(teardown));
+
+    match load_replay() {
+        // play back with the recorded config, a config file or overrides would change the outcome
+        Some(replay) => {
+            let mut clock = SimClock::default();
+            clock.last_tick = Some(replay.end_tick);
+            app.insert_resource(replay.config.clone())
+                .insert_resource(SimSeed(Some(replay.seed)))
+                .insert_resource(clock)
+                .insert_resource(ReplayPlayback(replay))
+                .add_state(GameState::Replay);
+        }
+        None => match connect_to_server() {
+            // the server sends the config with every match
+            Some(link) => {
+                app.insert_non_send_resource(link)
+                    .add_system(receive_server_messages_system)
+                    .add_system_set(
+                        SystemSet::on_update(GameState::Loading).with_system(wait_for_match_system),
+                    )
+                    .add_state(GameState::Loading);
+            }
+            None => {
+                app.add_plugin(config_plugin())
+                    .insert_resource(config_overrides())
+                    .insert_resource(SimSeed(
+                        launch_arg("seed").and_then(|seed| seed.parse().ok()),
+                    ))
+                    .add_system_set(
+                        SystemSet::on_update(GameState::Loading)
+                            .with_system(wait_for_config_system),
+                    )
+                    .add_state(GameState::Loading);
+            }
+        },
+    }
+    app.run();
+}
+
+// rows of the scoreboard, the player's rank is added below when they are not in the top rows
+const SCOREBOARD_SIZE: usize = 5;
+
+// helper: how far rendering is between the previous and the current positions of actors
+fn overstep_fraction(clock: &SimClock, link: Option<&ServerLink>) -> f32 {
+    match link {
+        Some(link) => link.overstep_fraction(),
+        None => clock.overstep_fraction(),
+    }
+}
+
+// helper: a faction's registry color as a bevy color
+fn faction_color(registry: &FactionRegistry, faction: Faction) -> Color {
+    registry.get(faction).map_or(Color::BLACK, |info| {
+        let [r, g, b, a] = info.color;
+        Color::rgba(r, g, b, a)
+    })
 }
 
-type ActorSpace = KDTreeAccess2D<Actor>; // type alias for later
+// helper: the shared material of a faction, created in `setup_playing`
+fn faction_material(
+    registry: &FactionRegistry,
+    faction: Faction,
+) -> Option<Handle<StandardMaterial>> {
+    registry
+        .get(faction)
+        .and_then(|info| info.material.clone())
+        .map(|material| material.typed())
+}
+
+// helper: width of the square of ground, larger plazas need more of it, and it covers the
+// boundary
+fn ground_size(config: &GameConfig) -> f32 {
+    let boundary = config.boundary().half_extents().max_element() * 2.0;
+    (config.action_region * 2.5).max(100.0).max(boundary)
+}
+
+fn faction_name(registry: &FactionRegistry, faction: Faction) -> &str {
+    registry.get(faction).map_or("?", |info| info.name.as_str())
+}
+
+// the scoreboard lines to show: the top factions, and the player's rank if they are further down
+fn scoreboard_entries(ranking: &[(Faction, i32)], player: Faction) -> Vec<(usize, Faction, i32)> {
+    let mut entries: Vec<(usize, Faction, i32)> = ranking
+        .iter()
+        .take(SCOREBOARD_SIZE)
+        .enumerate()
+        .map(|(rank, (fac, count))| (rank, *fac, *count))
+        .collect();
+    if let Some(rank) = ranking.iter().position(|(fac, _)| *fac == player) {
+        if rank >= SCOREBOARD_SIZE {
+            entries.push((rank, player, ranking[rank].1));
+        }
+    }
+    entries
+}
 
-#[derive(Clone)]
-struct FactionMaterialHandles {
-    faction_id_to_materials: HashMap<i32, Handle<StandardMaterial>>,
+// config values can be overridden at launch, e.g. `?actor_count=5000` in the page url
+fn config_overrides() -> GameConfigOverrides {
+    GameConfigOverrides(
+        GameConfig::FIELDS
+            .iter()
+            .filter_map(|field| launch_arg(field).map(|value| (field.to_string(), value)))
+            .collect(),
+    )
 }
 
-#[derive(Clone)]
-struct FactionActorCount {
-    faction_id_to_count: HashMap<i32, i32>,
+// the config file given with `--config mega_plaza`, for `assets/config/mega_plaza.config.ron`,
+// and the map given with `--map fountain_square`, for `assets/maps/fountain_square.map.ron`
+fn config_plugin() -> GameConfigPlugin {
+    let defaults = GameConfigPlugin::default();
+    GameConfigPlugin {
+        path: launch_arg("config")
+            .map(|name| format!("config/{}.config.ron", name))
+            .unwrap_or(defaults.path),
+        map: launch_arg("map").map(|name| format!("maps/{}.map.ron", name)),
+    }
 }
 
-#[derive(Clone)]
-struct TeamNaming {
-    names: Vec<String>,
+// system: show the start menu once the config is loaded, or start the first match right away
+// when the mode was given at launch
+fn wait_for_config_system(
+    config_handle: Res<GameConfigHandle>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if config_handle.ready {
+        let next = match launch_arg("mode") {
+            Some(_) => GameState::Playing,
+            None => GameState::Menu,
+        };
+        state.set(next).unwrap();
+    }
 }
 
-fn get_color_by_faction(faction: i32) -> Color {
-    match faction {
-        -1 => Color::WHITE,
-        0 => Color::RED,
-        1 => Color::AZURE,
-        2 => Color::BEIGE,
-        3 => Color::GOLD,
-        4 => Color::GREEN,
-        5 => Color::CYAN,
-        _ => Color::BLACK,
+// system: keep clear and ambient colors in sync with the (hot-reloaded) config
+fn apply_config_colors_system(
+    config: Res<GameConfig>,
+    mut clear_color: ResMut<ClearColor>,
+    mut ambient_light: ResMut<AmbientLight>,
+) {
+    if config.is_changed() {
+        clear_color.0 = Color::hex(&config.clear_color).unwrap();
+        ambient_light.color = Color::hex(&config.ambient_color).unwrap();
     }
 }
 
@@ -153,21 +388,23 @@ fn change_direction_player_system(
     mouse_input: Res<Input<MouseButton>>,
     mut cursor_moved_events: EventReader<CursorMoved>,
     windows: ResMut<Windows>,
-    mut player_query: Query<&mut Actor, With<PlayerController>>,
+    seat: Res<LocalSeat>,
+    mut player_input: ResMut<PlayerInput>,
     mut touch_evr: EventReader<TouchInput>,
 ) {
     let window = windows.primary();
     let width = window.width();
     let height = window.height();
     let window_size = Vec2::new(width, height) / 2.0;
-    let mut player = player_query.single_mut();
     if mouse_input.pressed(MouseButton::Left) {
         for event in cursor_moved_events.iter() {
             let move_direction = Vec2::new(
                 event.position.y - window_size.y,
                 -event.position.x + window_size.x,
             );
-            player.velocity = move_direction.normalize().extend(0.0) * PAWN_SPEED;
+            player_input
+                .steer
+                .insert(seat.0, Heading::from_direction(move_direction));
         }
     }
 
@@ -179,279 +416,132 @@ fn change_direction_player_system(
                     event.position.y - window_size.y,
                     -event.position.x + window_size.x,
                 );
-                player.velocity = move_direction.normalize().extend(0.0) * PAWN_SPEED;
+                player_input
+                    .steer
+                    .insert(seat.0, Heading::from_direction(move_direction));
             }
             _ => {}
         }
     }
 }
 
-// helper: change a actor's direction with some chance
-fn random_change_direction(mut actor: &mut Actor, rng: &mut ThreadRng, speed: f32) {
-    let change_direction_random = rng.gen_range(0.0..1.0);
-    if change_direction_random < 0.01 {
-        let mut random_direction =
-            Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
-        random_direction = random_direction.normalize() * OPPONENT_MOVE_SCALE;
-        let new_direction = actor.velocity + random_direction;
-        actor.velocity = new_direction.normalize() * speed;
-    };
-}
-
-// system: actor with no faction will change direction randomly
-fn change_direction_actor_system(mut actor_query: Query<&mut Actor, Without<Pawn>>) {
-    let mut rng = thread_rng();
-    for mut actor in actor_query.iter_mut() {
-        if actor.faction != -1 {
-            continue;
-        }
-        random_change_direction(&mut actor, &mut rng, WANDER_SPEED);
-    }
-}
-
-// system: opponent's will change direction randomly
-fn change_direction_opponent_system(
-    mut opponent_query: Query<&mut Actor, With<OpponentController>>,
-) {
-    let mut rng = thread_rng();
-    for mut actor in opponent_query.iter_mut() {
-        random_change_direction(&mut actor, &mut rng, PAWN_SPEED);
-    }
-}
-
-// system: update actor's location with velocity, clamp velocity and damp acceleration
-fn move_actor_system(mut actor_query: Query<(&mut Transform, &mut Actor), Without<Pawn>>) {
-    for (mut tr, mut actor) in actor_query.iter_mut() {
-        tr.translation += actor.velocity;
-        let acc = actor.accleration;
-        actor.velocity += acc;
-        if actor.velocity.length() > PAWN_SPEED {
-            actor.velocity = actor.velocity.normalize() * PAWN_SPEED;
-        }
-        actor.accleration = acc * 0.5; // damping
-    }
-}
-
-// system: update pawn's location with velocity
-fn move_pawn_system(mut actor_query: Query<(&mut Transform, &Actor), With<Pawn>>) {
-    for (mut tr, actor) in actor_query.iter_mut() {
-        tr.translation += actor.velocity;
-    }
-}
-
-// system: faction's actor should follow leader pawn's dirion
-fn follow_pawn_system(
-    mut actor_query: Query<(&mut Actor, &Transform), Without<Pawn>>,
-    pawn_query: Query<(&Actor, &Pawn, &Transform)>,
-) {
-    let mut faction_to_velocity: HashMap<i32, Vec3> = HashMap::new();
-    let mut faction_to_position: HashMap<i32, Vec3> = HashMap::new();
-    for (pawn_actor, _, tr) in pawn_query.iter() {
-        faction_to_velocity.insert(pawn_actor.faction, pawn_actor.velocity);
-        faction_to_position.insert(pawn_actor.faction, tr.translation);
-    }
-    for (mut actor, tr) in actor_query.iter_mut() {
-        if actor.faction != -1 {
-            match faction_to_velocity.get(&actor.faction) {
-                Some(v) => {
-                    // align to leader pawn's direction, add to acceleration
-                    let acc = *v - actor.velocity;
-                    actor.accleration += acc * ALIGN_FACTOR;
-
-                    // move to leader pawn's position, add to acceleration
-                    let toward_pawn =
-                        *faction_to_position.get(&actor.faction).unwrap() - tr.translation;
-                    actor.accleration += toward_pawn * ATTRACT_FACTOR;
-                }
-                None => {}
-            }
-        }
-    }
-}
-
-// system: change actor's faction and visual according to it's surrounding majority faction
-fn change_actor_faction_system(
+// system: give newly spawned simulation actors a mesh and material, large crowds are drawn
+// instanced but for the pawns
+fn attach_actor_visuals_system(
     mut commands: Commands,
-    spatial_query: Res<ActorSpace>,
-    mut actor_set: Query<(
-        Entity,
-        &Transform,
-        &mut Actor,
-        &mut Handle<StandardMaterial>,
-        Option<&mut Pawn>,
-    )>,
-    faction_materials: Res<FactionMaterialHandles>,
-    mut faction_counts: ResMut<FactionActorCount>,
-    mut state: ResMut<State<GameState>>,
+    assets: Res<ActorAssets>,
+    config: Res<GameConfig>,
+    registry: Res<FactionRegistry>,
+    mut actor_query: Query<(Entity, &Actor, &mut Transform, Option<&Pawn>), Added<Actor>>,
 ) {
-    // https://github.com/bevyengine/bevy/issues/2495
-    let mut entity_id_to_faction: HashMap<Entity, i32> = HashMap::new();
-    // we cannot borrow actor_set twice as below we have to borrow it when do spatial query
-    // so cache entity_id_to_faction in this loop
-    for (entity, tr, _, _, _) in actor_set.iter() {
-        let mut faction_to_count: HashMap<i32, i32> = HashMap::new();
-        let neighbors = spatial_query.within_distance(tr.translation, NEIGHBOR_THRESHOLD);
-        for (_, neighbor_entity) in neighbors.iter() {
-            let (_, _, neighbor_actor, _, _) = actor_set.get(*neighbor_entity).unwrap();
-            if neighbor_actor.faction == -1 {
-                continue; // we skip neighbor no faction actor
-            }
-            if faction_to_count.contains_key(&neighbor_actor.faction) {
-                faction_to_count
-                    .entry(neighbor_actor.faction)
-                    .and_modify(|e| *e += 1);
-            } else {
-                faction_to_count.insert(neighbor_actor.faction, 1);
-            }
-        }
-        match faction_to_count
-            .drain()
-            .max_by(|(_, count1), (_, count2)| count1.cmp(&count2))
-        {
-            Some((faction, _)) => {
-                entity_id_to_faction.insert(entity, faction);
-            }
-            None => {}
-        }
-    }
-
-    for (entity, _, mut actor, mut mat, pawn) in actor_set.iter_mut() {
-        match entity_id_to_faction.get(&entity) {
-            Some(fac) => {
-                if *fac >= 0 && *fac != actor.faction {
-                    match pawn {
-                        Some(_) => {
-                            let faction_count = *faction_counts
-                                .faction_id_to_count
-                                .get(&actor.faction)
-                                .unwrap();
-                            if faction_count <= 1 {
-                                // this pawn is dead!
-                                println!(
-                                    "Remove pawn for faction {0}, with count {1}",
-                                    actor.faction, faction_count
-                                );
-                                if actor.faction == 0 {
-                                    // player gameover
-                                    state.set(GameState::GameOver).unwrap();
-                                }
-                                commands.entity(entity).remove::<Pawn>();
-                            }
-                        }
-                        None => {
-                            // this is a normal actor
-
-                            // update faction count
-                            if actor.faction != -1 {
-                                faction_counts
-                                    .faction_id_to_count
-                                    .entry(actor.faction)
-                                    .and_modify(|f| *f -= 1);
-                            }
-                            faction_counts
-                                .faction_id_to_count
-                                .entry(*fac)
-                                .and_modify(|f| *f += 1);
-
-                            // update actor faction
-                            actor.faction = *fac;
-                            actor.velocity = Vec3::new(0.0, 0.0, 0.0);
-                            *mat = faction_materials
-                                .faction_id_to_materials
-                                .get(&fac)
-                                .unwrap()
-                                .clone();
-                        }
-                    }
-                }
-            }
-            None => {}
-        }
+    let instanced = is_instanced(&config);
+    for (entity, actor, mut tr, pawn) in actor_query.iter_mut() {
+        tr.rotation = Quat::from_rotation_x(PI * 0.5);
+        let mut entity = commands.entity(entity);
+        match pawn {
+            Some(_) => entity.insert(assets.pawn_mesh.clone()).insert(PawnMesh),
+            None if instanced => continue,
+            None => entity.insert(assets.actor_mesh.clone()),
+        };
+        entity
+            .insert(faction_material(&registry, actor.faction).unwrap())
+            .insert(Visibility::default())
+            .insert(ComputedVisibility::default());
     }
 }
 
-// system: actor should seperate from each other when they are close
-fn repulse_actor_system(
-    spatial_query: Res<ActorSpace>,
-    mut actor_set: Query<(Entity, &Transform, &mut Actor)>,
+// system: switch an actor's material when it gets converted to another faction, or neutralized
+fn update_actor_material_system(
+    registry: Res<FactionRegistry>,
+    mut actor_query: Query<(&Actor, &mut Handle<StandardMaterial>), Changed<Actor>>,
 ) {
-    let mut entity_id_to_repulse: HashMap<Entity, Vec3> = HashMap::new();
-    for (entity, tr, actor) in actor_set.iter() {
-        // neighbor query include self
-        for (neighbor_pos, neighbor_entity) in spatial_query.k_nearest_neighbour(tr.translation, 2)
-        {
-            if neighbor_entity.id() != entity.id() {
-                match actor_set.get(neighbor_entity) {
-                    Ok((_, _, neighbor_actor)) => {
-                        if neighbor_actor.faction == -1 {
-                            continue; // we skip neighbor no faction actor
-                        }
-                        if neighbor_actor.faction == actor.faction
-                            && neighbor_pos.distance(tr.translation) < REPULSION_THRESHOLD
-                        {
-                            entity_id_to_repulse
-                                .insert(entity, (neighbor_pos - tr.translation).normalize());
-                        }
-                    }
-                    Err(_) => {}
-                }
+    for (actor, mut mat) in actor_query.iter_mut() {
+        if let Some(faction_material) = faction_material(&registry, actor.faction) {
+            if *mat != faction_material {
+                *mat = faction_material;
             }
         }
     }
-
-    for (entity, _, mut actor) in actor_set.iter_mut() {
-        match entity_id_to_repulse.get(&entity) {
-            Some(repul) => {
-                actor.accleration -= *repul * REPULSION_FACTOR;
-            }
-            None => {}
-        }
-    }
 }
 
 // update camera position to look at player
 // https://github.com/bevyengine/bevy/issues/2198
 fn update_camera_lookat_system(
-    player_query: Query<&Transform, (With<PlayerController>, Without<Camera3d>)>,
+    clock: Res<SimClock>,
+    link: Option<NonSend<ServerLink>>,
+    player_query: Query<(&Transform, &PreviousTranslation), With<PlayerController>>,
     mut camera_query: Query<&mut Transform, (With<Camera3d>, Without<PlayerController>)>,
 ) {
-    let pl = player_query.single();
+    // online the pawn shows up with the first snapshot
+    let (pl, previous) = match player_query.get_single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let alpha = overstep_fraction(&clock, link.as_deref());
+    let pl_translation = previous.0.lerp(pl.translation, alpha);
     let mut cam = camera_query.single_mut();
-    cam.translation = pl.translation + vec3(-5.0, 0.0, 10.0);
-    cam.look_at(pl.translation, vec3(0.0, 0.0, 1.0));
+    cam.translation = pl_translation + vec3(-5.0, 0.0, 10.0);
+    cam.look_at(pl_translation, vec3(0.0, 0.0, 1.0));
 }
 
-fn update_ui_system(
-    faction_count: Res<FactionActorCount>,
-    mut text_query: Query<(&mut Text, &FactionText)>,
+// system: draw actors between their last two simulated positions, the simulation keeps
+// `Transform` authoritative and we only touch the `GlobalTransform` used for rendering
+fn interpolate_actor_transforms_system(
+    clock: Res<SimClock>,
+    link: Option<NonSend<ServerLink>>,
+    mut actor_query: Query<(&Transform, &PreviousTranslation, &mut GlobalTransform)>,
 ) {
-    for (mut text, fac) in text_query.iter_mut() {
-        let count = faction_count.faction_id_to_count.get(&fac.faction).unwrap();
-        text.sections[2].value = format!("{0}", count);
+    let alpha = overstep_fraction(&clock, link.as_deref());
+    for (tr, previous, mut global) in actor_query.iter_mut() {
+        *global = GlobalTransform::from(Transform {
+            translation: previous.0.lerp(tr.translation, alpha),
+            ..*tr
+        });
     }
 }
 
-fn setup_game(mut commands: Commands) {
-    let mut faction_to_count: HashMap<i32, i32> = HashMap::new();
-    for fac in 0..(OPPONENT_COUNT + 1) {
-        faction_to_count.insert(fac, 1);
+fn update_ui_system(
+    registry: Res<FactionRegistry>,
+    seat: Res<LocalSeat>,
+    mut text_query: Query<(&mut Text, &ScoreboardRow)>,
+) {
+    let ranking = registry.ranking();
+    let player = Faction::Player(seat.0);
+    let player_rank = ranking.iter().position(|(fac, _)| *fac == player);
+    for (mut text, row) in text_query.iter_mut() {
+        let rank = match row {
+            ScoreboardRow::Rank(rank) => Some(*rank),
+            ScoreboardRow::Player => player_rank.filter(|rank| *rank >= SCOREBOARD_SIZE),
+        };
+        match rank.and_then(|rank| ranking.get(rank).map(|entry| (rank, entry))) {
+            Some((rank, (fac, count))) => {
+                text.sections[0].value =
+                    format!("#{0} {1}", rank + 1, faction_name(&registry, *fac));
+                text.sections[0].style.color = faction_color(&registry, *fac);
+                text.sections[1].value = ": ".to_string();
+                text.sections[2].value = format!("{0}", count);
+            }
+            None => {
+                for section in text.sections.iter_mut() {
+                    section.value.clear();
+                }
+            }
+        }
     }
-    let faction_count = FactionActorCount {
-        faction_id_to_count: faction_to_count,
-    };
-    commands.insert_resource(faction_count.clone());
-
-    let team_name = vec![
-        "Player".to_string(),
-        "Anderson".to_string(),
-        "Bob".to_string(),
-        "Cat".to_string(),
-        "Doug".to_string(),
-        "Eason".to_string(),
-    ];
+}
 
-    let naming = TeamNaming { names: team_name };
-    commands.insert_resource(naming);
+fn setup_game(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
+    commands.insert_resource(ActorAssets {
+        actor_mesh: meshes.add(Mesh::from(shape::Capsule {
+            radius: 0.1,
+            ..default()
+        })),
+        pawn_mesh: meshes.add(Mesh::from(shape::Capsule {
+            radius: 0.12,
+            depth: 1.2,
+            ..default()
+        })),
+    });
 
     // camera
     commands.spawn_bundle(PerspectiveCameraBundle {
@@ -465,122 +555,33 @@ fn setup_game(mut commands: Commands) {
 
 fn setup_playing(
     mut commands: Commands,
+    config: Res<GameConfig>,
     asset_server: Res<AssetServer>,
+    assets: Res<ActorAssets>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    mut count: ResMut<FactionActorCount>,
-    naming: ResMut<TeamNaming>,
+    mut registry: ResMut<FactionRegistry>,
 ) {
-    let region: f32 = ACTION_REGION;
-
-    // timer count down
-    let timer = Timer::from_seconds(GAME_TIME, false);
-    let count_down = Countdown { main_timer: timer };
-    commands.insert_resource(count_down);
-
-    // faction actor count
-    for fac in 0..(OPPONENT_COUNT + 1) {
-        count.faction_id_to_count.insert(fac, 1);
-    }
-
-    // material cache
-    let mut faction_to_materials: HashMap<i32, Handle<StandardMaterial>> = HashMap::new();
-    for fac in -1..(OPPONENT_COUNT + 1) {
-        faction_to_materials.insert(
-            fac,
-            materials.add(StandardMaterial {
-                base_color: get_color_by_faction(fac),
-                perceptual_roughness: 0.8,
-                ..default()
-            }),
-        );
-    }
-    let handles = FactionMaterialHandles {
-        faction_id_to_materials: faction_to_materials,
-    };
-    commands.insert_resource(handles.clone());
-
-    // actors
-    let mut rng = thread_rng();
-    for _ in 0..ACTOR_COUNT {
-        let x = rng.gen_range(-region..region);
-        let y = rng.gen_range(-region..region);
-        let dir = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
-        commands
-            .spawn_bundle(PbrBundle {
-                mesh: meshes.add(Mesh::from(shape::Capsule {
-                    radius: 0.1,
-                    ..default()
-                })),
-                material: materials.add(StandardMaterial {
-                    base_color: get_color_by_faction(-1),
-                    perceptual_roughness: 0.8,
-                    ..default()
-                }),
-                transform: Transform::from_xyz(x, y, 0.0)
-                    .with_rotation(Quat::from_rotation_x(PI * 0.5)),
-                ..default()
-            })
-            .insert(Actor {
-                faction: -1,
-                velocity: dir.normalize() * WANDER_SPEED,
-                accleration: Vec3::new(0.0, 0.0, 0.0),
-            });
+    // material cache, the factions were just set up by `setup_match`, neutrals included as
+    // actors go back to them when the safe zone catches them
+    for (_, info) in registry.iter_mut() {
+        let [r, g, b, a] = info.color;
+        let material = materials.add(StandardMaterial {
+            base_color: Color::rgba(r, g, b, a),
+            perceptual_roughness: 0.8,
+            ..default()
+        });
+        info.material = Some(material.clone_untyped());
     }
-
-    // opponents
-    for fac in 1..(OPPONENT_COUNT + 1) {
-        let x = rng.gen_range(-region..region);
-        let y = rng.gen_range(-region..region);
-        let dir = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
-        commands
-            .spawn_bundle(PbrBundle {
-                mesh: meshes.add(Mesh::from(shape::Capsule {
-                    radius: 0.12,
-                    depth: 1.2,
-                    ..default()
-                })),
-                material: handles.faction_id_to_materials.get(&fac).unwrap().clone(),
-                transform: Transform::from_xyz(x, y, 0.0)
-                    .with_rotation(Quat::from_rotation_x(PI * 0.5)),
-                ..default()
-            })
-            .insert(Actor {
-                faction: fac,
-                velocity: dir.normalize() * PAWN_SPEED,
-                accleration: Vec3::new(0.0, 0.0, 0.0),
-            })
-            .insert(Pawn {})
-            .insert(OpponentController {});
+    if is_instanced(&config) {
+        spawn_actor_batches(&mut commands, &registry, &assets);
     }
 
-    // player
-    let x = rng.gen_range(-5.0..5.0);
-    let y = rng.gen_range(-5.0..5.0);
-    let dir = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
-    commands
-        .spawn_bundle(PbrBundle {
-            mesh: meshes.add(Mesh::from(shape::Capsule {
-                radius: 0.12,
-                depth: 1.2,
-                ..default()
-            })),
-            material: handles.faction_id_to_materials.get(&0).unwrap().clone(),
-            transform: Transform::from_xyz(x, y, 0.0)
-                .with_rotation(Quat::from_rotation_x(PI * 0.5)),
-            ..default()
-        })
-        .insert(Actor {
-            faction: 0,
-            velocity: dir.normalize() * PAWN_SPEED,
-            accleration: Vec3::new(0.0, 0.0, 0.0),
-        })
-        .insert(Pawn {})
-        .insert(PlayerController);
-
     // ground
     commands.spawn_bundle(PbrBundle {
-        mesh: meshes.add(Mesh::from(shape::Plane { size: 100.0 })),
+        mesh: meshes.add(Mesh::from(shape::Plane {
+            size: ground_size(&config),
+        })),
         material: materials.add(StandardMaterial {
             base_color: Color::rgba(1.0, 1.0, 1.0, 1.0),
             perceptual_roughness: 0.8,
@@ -590,13 +591,15 @@ fn setup_playing(
             .with_rotation(Quat::from_rotation_x(PI * 0.5)),
         ..default()
     });
+    spawn_boundary_outline(&mut commands, &mut meshes, &mut materials, &config);
+    spawn_obstacles(&mut commands, &mut meshes, &mut materials, &config);
 
     // light
     commands.spawn_bundle(PointLightBundle {
         transform: Transform::from_xyz(0.0, 0.0, 8.0),
         point_light: PointLight {
             intensity: 3600.0,
-            color: Color::hex(LIGHT_COLOR).unwrap(),
+            color: Color::hex(&config.light_color).unwrap(),
             shadows_enabled: true,
             range: 100.0,
             ..default()
@@ -605,14 +608,17 @@ fn setup_playing(
     });
 
     // uis
-    for fac in 0..(OPPONENT_COUNT + 1) {
+    let rows = (0..SCOREBOARD_SIZE)
+        .map(ScoreboardRow::Rank)
+        .chain([ScoreboardRow::Player]);
+    for (line, row) in rows.enumerate() {
         commands
             .spawn_bundle(TextBundle {
                 style: Style {
                     align_self: AlignSelf::FlexEnd,
                     position_type: PositionType::Absolute,
                     position: Rect {
-                        top: Val::Px(30.0 + 30.0 * (fac as f32)),
+                        top: Val::Px(30.0 + 30.0 * (line as f32)),
                         right: Val::Px(15.0),
                         ..default()
                     },
@@ -623,7 +629,7 @@ fn setup_playing(
                     // Construct a `Vec` of `TextSection`s
                     sections: vec![
                         TextSection {
-                            value: naming.names.get(fac as usize).unwrap().to_string(),
+                            value: "".to_string(),
                             style: TextStyle {
                                 font: asset_server.load("fonts/FiraMono-Medium.ttf"),
                                 font_size: 20.0,
@@ -651,7 +657,7 @@ fn setup_playing(
                 },
                 ..default()
             })
-            .insert(FactionText { faction: fac });
+            .insert(row);
     }
 
     // fps text
@@ -685,6 +691,32 @@ fn setup_playing(
             ..default()
         })
         .insert(FpsText);
+
+    // debug overlay
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                align_self: AlignSelf::FlexEnd,
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(40.0),
+                    left: Val::Px(0.0),
+                    ..default()
+                },
+                ..default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                    font_size: 16.0,
+                    color: Color::WHITE,
+                },
+                default(),
+            ),
+            ..default()
+        })
+        .insert(DebugText);
 }
 
 // remove all entities that are not a camera
@@ -698,14 +730,11 @@ fn teardown(mut commands: Commands, entities: Query<Entity, Without<Camera>>) {
 fn display_score(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    mut faction_actor_count: ResMut<FactionActorCount>,
-    naming: ResMut<TeamNaming>,
+    registry: Res<FactionRegistry>,
+    seat: Res<LocalSeat>,
 ) {
-    let mut ordered_fac_to_count = faction_actor_count
-        .faction_id_to_count
-        .drain()
-        .collect::<Vec<(i32, i32)>>();
-    ordered_fac_to_count.sort_by(|(_, count1), (_, count2)| count2.cmp(&count1));
+    let ordered_fac_to_count = registry.ranking();
+    let player = Faction::Player(seat.0);
 
     commands
         .spawn_bundle(NodeBundle {
@@ -719,7 +748,7 @@ fn display_score(
             ..default()
         })
         .with_children(|parent| {
-            if ordered_fac_to_count[0].0 == 0 {
+            if ordered_fac_to_count[0].0 == player {
                 parent.spawn_bundle(TextBundle {
                     style: Style {
                         margin: Rect::all(Val::Px(20.0)),
@@ -761,14 +790,19 @@ fn display_score(
                 });
             }
 
-            for (fac, score) in ordered_fac_to_count {
+            for (rank, fac, score) in scoreboard_entries(&ordered_fac_to_count, player) {
                 parent.spawn_bundle(TextBundle {
                     style: Style {
                         margin: Rect::all(Val::Px(10.0)),
                         ..default()
                     },
                     text: Text::with_section(
-                        format!("{0}: {1}\n", naming.names.get(fac as usize).unwrap(), score),
+                        format!(
+                            "#{0} {1}: {2}\n",
+                            rank + 1,
+                            faction_name(&registry, fac),
+                            score
+                        ),
                         TextStyle {
                             font: asset_server.load("fonts/FiraMono-Medium.ttf"),
                             font_size: 20.0,
@@ -812,17 +846,21 @@ fn display_score(
         });
 }
 
-pub struct Countdown {
-    pub main_timer: Timer,
-}
-
-fn countdown(
-    time: Res<Time>,
-    mut countdown: ResMut<Countdown>,
+// system: the match is over when the player's pawn is eliminated or time is up
+fn match_event_system(
+    mut match_events: EventReader<MatchEvent>,
+    seat: Res<LocalSeat>,
     mut state: ResMut<State<GameState>>,
 ) {
-    if countdown.main_timer.tick(time.delta()).just_finished() {
-        state.set(GameState::GameOver).unwrap();
+    for event in match_events.iter() {
+        match event {
+            MatchEvent::PawnEliminated { faction } if *faction != Faction::Player(seat.0) => {}
+            MatchEvent::PawnEliminated { .. } | MatchEvent::TimeUp => {
+                // player gameover
+                state.set(GameState::GameOver).unwrap();
+                break;
+            }
+        }
     }
 }
 
@@ -835,11 +873,116 @@ fn replay_button_system(
         (&Interaction, &mut UiColor, &Children),
         (Changed<Interaction>, With<Button>),
     >,
+    link: Option<NonSend<ServerLink>>,
     mut state: ResMut<State<GameState>>,
 ) {
     for (interaction, mut color, _) in interaction_query.iter_mut() {
         match *interaction {
             Interaction::Clicked => {
+                // online we wait for the server to start the next match
+                let next = match link {
+                    Some(_) => GameState::Loading,
+                    None => GameState::Menu,
+                };
+                state.set(next).unwrap();
+                *color = PRESSED_BUTTON.into();
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *color = NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+// the start menu, a button per game mode
+fn setup_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                margin: Rect::all(Val::Auto),
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: UiColor(Color::rgba(0.0, 0.0, 0.0, 0.0)),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect::all(Val::Px(20.0)),
+                    ..default()
+                },
+                text: Text::with_section(
+                    "Crowded Plaza",
+                    TextStyle {
+                        font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                        font_size: 40.0,
+                        color: Color::WHITE,
+                    },
+                    TextAlignment {
+                        horizontal: HorizontalAlign::Center,
+                        ..default()
+                    },
+                ),
+                ..default()
+            });
+
+            for (mode, label) in [
+                (GameMode::Classic, "Classic"),
+                (GameMode::BattleRoyale, "Battle Royale"),
+            ] {
+                parent
+                    .spawn_bundle(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(260.0), Val::Px(65.0)),
+                            margin: Rect::all(Val::Px(10.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        color: NORMAL_BUTTON.into(),
+                        ..default()
+                    })
+                    .insert(ModeButton(mode))
+                    .with_children(|parent| {
+                        parent.spawn_bundle(TextBundle {
+                            text: Text::with_section(
+                                label,
+                                TextStyle {
+                                    font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                                    font_size: 30.0,
+                                    color: Color::WHITE,
+                                },
+                                Default::default(),
+                            ),
+                            ..default()
+                        });
+                    });
+            }
+        });
+}
+
+// system: start a match in the mode picked, kept as a config override so hot reloads keep it too
+fn menu_button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut UiColor, &ModeButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut config: ResMut<GameConfig>,
+    mut overrides: ResMut<GameConfigOverrides>,
+    mut state: ResMut<State<GameState>>,
+) {
+    for (interaction, mut color, button) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Clicked => {
+                // the match is set up before the overrides are applied again
+                config.mode = button.0;
+                overrides.0.retain(|(field, _)| field != "mode");
+                overrides.0.push(("mode".to_string(), button.0.to_string()));
                 state.set(GameState::Playing).unwrap();
                 *color = PRESSED_BUTTON.into();
             }
@@ -863,3 +1006,88 @@ fn text_update_system(diagnostics: Res<Diagnostics>, mut query: Query<&mut Text,
         }
     }
 }
+
+// system: F3 shows or hides the debug overlay, which averages the simulation's diagnostics
+// over the last ticks
+fn debug_overlay_system(
+    keys: Res<Input<KeyCode>>,
+    diagnostics: Res<Diagnostics>,
+    registry: Res<FactionRegistry>,
+    mut overlay: ResMut<DebugOverlay>,
+    mut query: Query<(&mut Text, &mut Visibility), With<DebugText>>,
+) {
+    if keys.just_pressed(KeyCode::F3) {
+        overlay.0 = !overlay.0;
+    }
+    let average = |id| match diagnostics
+        .get(id)
+        .and_then(|diagnostic| diagnostic.average())
+    {
+        Some(average) => format!("{:.0}", average),
+        // online the server ticks
+        None => "-".to_string(),
+    };
+    for (mut text, mut visibility) in query.iter_mut() {
+        visibility.is_visible = overlay.0;
+        if !overlay.0 {
+            continue;
+        }
+        let mut lines = vec![
+            format!(
+                "neighbor queries: {}",
+                average(SimDiagnosticsPlugin::NEIGHBOR_QUERIES)
+            ),
+            format!(
+                "conversions: {}",
+                average(SimDiagnosticsPlugin::CONVERSIONS)
+            ),
+            format!(
+                "repulsion pairs: {}",
+                average(SimDiagnosticsPlugin::REPULSION_PAIRS)
+            ),
+        ];
+        for (faction, _) in registry.iter() {
+            lines.push(format!(
+                "{}: {}",
+                faction_name(&registry, faction),
+                average(SimDiagnosticsPlugin::faction_actors(faction))
+            ));
+        }
+        text.sections[0].value = lines.join("\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::asset::HandleId;
+
+    #[test]
+    fn neutralized_actors_get_the_neutral_material_back() {
+        let mut world = World::new();
+        let mut registry = FactionRegistry::new(10, 1, 0);
+        for (_, info) in registry.iter_mut() {
+            info.material = Some(HandleUntyped::weak(HandleId::random::<StandardMaterial>()));
+        }
+        let player = faction_material(&registry, Faction::PLAYER).unwrap();
+        let neutral = faction_material(&registry, Faction::Neutral).unwrap();
+        world.insert_resource(registry);
+        let actor = world
+            .spawn()
+            .insert(Actor {
+                faction: Faction::PLAYER,
+                velocity: Vec3::ZERO,
+                accleration: Vec3::ZERO,
+            })
+            .insert(neutral.clone())
+            .id();
+
+        let mut stage = SystemStage::single_threaded().with_system(update_actor_material_system);
+        stage.run(&mut world);
+        assert_eq!(world.get::<Handle<StandardMaterial>>(actor), Some(&player));
+
+        world.get_mut::<Actor>(actor).unwrap().faction = Faction::Neutral;
+        stage.run(&mut world);
+        assert_eq!(world.get::<Handle<StandardMaterial>>(actor), Some(&neutral));
+    }
+}
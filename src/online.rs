@@ -0,0 +1,277 @@
+use bevy::{prelude::*, utils::HashMap};
+use crowded_plaza_net::{ClientMessage, Connection, PawnPredictor, ServerMessage, SnapshotBuffer};
+use crowded_plaza_sim::{
+    Actor, Faction, FactionRegistry, GameConfig, MatchEvent, Pawn, PlayerController, PlayerInput,
+    PreviousTranslation, SafeZone, SimClock,
+};
+
+use crate::{launch_args::launch_arg, GameState, LocalSeat};
+
+// a match the server set up, waiting for us to show it
+struct MatchStart {
+    config: Box<GameConfig>,
+    names: Vec<(Faction, String)>,
+}
+
+// seconds the other actors are drawn behind the latest snapshot: two snapshots at the default
+// interval, and some jitter
+const INTERPOLATION_DELAY: f32 = 0.1;
+
+/// The connection to the server given with `--server ws://host:port` or `?server=` in the page
+/// url, and what it sent that was not shown yet.
+pub struct ServerLink {
+    connection: Connection,
+    match_start: Option<MatchStart>,
+    snapshots: SnapshotBuffer,
+    // the own pawn, from the first match on
+    predictor: Option<PawnPredictor>,
+    // the ticks of the two snapshots shown now, and the entity of each actor id
+    shown: Option<(u64, u64)>,
+    entities: HashMap<u32, Entity>,
+    fraction: f32,
+    connected: bool,
+}
+
+impl ServerLink {
+    /// How far rendering is from the earlier snapshot shown to the later one, from 0 to 1.
+    pub fn overstep_fraction(&self) -> f32 {
+        self.fraction
+    }
+}
+
+// the server to play on, if one was given
+pub fn connect_to_server() -> Option<ServerLink> {
+    let url = launch_arg("server")?;
+    let mut connection = match Connection::connect(&url) {
+        Ok(connection) => connection,
+        Err(e) => {
+            warn!("Cannot play on {0}: {1}", url, e);
+            return None;
+        }
+    };
+    connection.send(&ClientMessage::Join {
+        name: launch_arg("name").unwrap_or_default(),
+    });
+    Some(ServerLink {
+        connection,
+        match_start: None,
+        snapshots: SnapshotBuffer::new(INTERPOLATION_DELAY),
+        predictor: None,
+        shown: None,
+        entities: HashMap::default(),
+        fraction: 1.0,
+        connected: true,
+    })
+}
+
+// system: take in what the server sent, match events go to the game like from a local match
+pub fn receive_server_messages_system(
+    mut link: NonSendMut<ServerLink>,
+    mut seat: ResMut<LocalSeat>,
+    mut registry: ResMut<FactionRegistry>,
+    mut match_events: EventWriter<MatchEvent>,
+) {
+    for message in link.connection.receive() {
+        match message {
+            ServerMessage::Welcome { seat: given } => seat.0 = given,
+            ServerMessage::Full => warn!("Every seat on the server is taken"),
+            ServerMessage::MatchStart { config, names, .. } => {
+                link.predictor = Some(PawnPredictor::new(
+                    Faction::Player(seat.0),
+                    config.pawn_speed,
+                ));
+                link.match_start = Some(MatchStart { config, names });
+                link.snapshots.clear();
+            }
+            ServerMessage::Roster { names } => {
+                for (faction, name) in names {
+                    if let Some(info) = registry.get_mut(faction) {
+                        info.name = name;
+                    }
+                }
+            }
+            ServerMessage::Snapshot(snapshot) => {
+                if let Some(predictor) = link.predictor.as_mut() {
+                    predictor.reconcile(&snapshot);
+                }
+                link.snapshots.push(snapshot);
+            }
+            ServerMessage::Event { event } => match_events.send(event),
+        }
+    }
+    if link.connected && !link.connection.is_open() {
+        warn!("Lost the connection to the server");
+        link.connected = false;
+    }
+}
+
+// system: show the match once the server set one up
+pub fn wait_for_match_system(link: NonSend<ServerLink>, mut state: ResMut<State<GameState>>) {
+    if link.match_start.is_some() {
+        state.set(GameState::Online).unwrap();
+    }
+}
+
+// system: the server started the next match while we were still showing this one
+pub fn next_match_system(link: NonSend<ServerLink>, mut state: ResMut<State<GameState>>) {
+    if link.match_start.is_some() {
+        state.restart().unwrap();
+    }
+}
+
+// system: set up the factions of the match the server started, the server simulates it so the
+// local clock stays paused
+pub fn setup_online_match(
+    mut commands: Commands,
+    mut link: NonSendMut<ServerLink>,
+    mut config: ResMut<GameConfig>,
+    mut registry: ResMut<FactionRegistry>,
+    mut clock: ResMut<SimClock>,
+) {
+    let start = match link.match_start.take() {
+        Some(start) => start,
+        None => return,
+    };
+    *config = *start.config;
+    *registry = FactionRegistry::new(
+        config.actor_count,
+        config.player_count,
+        config.opponent_count,
+    );
+    for (faction, name) in start.names {
+        if let Some(info) = registry.get_mut(faction) {
+            info.name = name;
+        }
+    }
+    // the server shrinks the zone, we follow it with the snapshots
+    match SafeZone::for_config(&config) {
+        Some(zone) => commands.insert_resource(zone),
+        None => commands.remove_resource::<SafeZone>(),
+    }
+    clock.paused = true;
+    link.entities.clear();
+    link.shown = None;
+}
+
+// system: play back the buffered snapshots, actors move from the earlier snapshot shown to the
+// later one and are spawned and despawned as needed
+pub fn apply_snapshot_system(
+    mut commands: Commands,
+    mut link: NonSendMut<ServerLink>,
+    time: Res<Time>,
+    seat: Res<LocalSeat>,
+    mut registry: ResMut<FactionRegistry>,
+    zone: Option<ResMut<SafeZone>>,
+    mut actor_query: Query<(
+        &mut Transform,
+        &mut PreviousTranslation,
+        &mut Actor,
+        Option<&Pawn>,
+    )>,
+) {
+    let link = &mut *link;
+    link.snapshots.advance(time.delta_seconds());
+    let (from, snapshot, fraction) = match link.snapshots.sample() {
+        Some(sample) => sample,
+        None => return,
+    };
+    link.fraction = fraction;
+    if link.shown == Some((from.tick, snapshot.tick)) {
+        return;
+    }
+    link.shown = Some((from.tick, snapshot.tick));
+    if let Some(mut zone) = zone {
+        zone.shrink_to(snapshot.tick);
+    }
+
+    let own_faction = Faction::Player(seat.0);
+    let mut entities = HashMap::default();
+    for state in snapshot.actors.iter() {
+        let position = Vec3::new(state.position[0], state.position[1], 0.0);
+        let previous_position = match from.actors.binary_search_by_key(&state.id, |from| from.id) {
+            Ok(index) => Vec3::new(
+                from.actors[index].position[0],
+                from.actors[index].position[1],
+                0.0,
+            ),
+            Err(_) => position,
+        };
+        let entity = match link.entities.remove(&state.id) {
+            Some(entity) => {
+                if let Ok((mut tr, mut previous, mut actor, pawn)) = actor_query.get_mut(entity) {
+                    previous.0 = previous_position;
+                    tr.translation = position;
+                    // only on change, a changed actor gets its material updated
+                    if actor.faction != state.faction {
+                        actor.faction = state.faction;
+                    }
+                    // the pawn was eliminated
+                    if pawn.is_some() && !state.pawn {
+                        commands
+                            .entity(entity)
+                            .remove::<Pawn>()
+                            .remove::<PlayerController>();
+                    }
+                }
+                entity
+            }
+            None => {
+                let mut entity = commands.spawn_bundle(TransformBundle::from_transform(
+                    Transform::from_translation(position),
+                ));
+                entity
+                    .insert(Actor {
+                        faction: state.faction,
+                        velocity: Vec3::ZERO,
+                        accleration: Vec3::ZERO,
+                    })
+                    .insert(PreviousTranslation(previous_position));
+                if state.pawn {
+                    entity.insert(Pawn);
+                    if state.faction == own_faction {
+                        entity.insert(PlayerController(seat.0));
+                    }
+                }
+                entity.id()
+            }
+        };
+        entities.insert(state.id, entity);
+    }
+    // actors the server no longer has
+    for (_, entity) in link.entities.drain() {
+        commands.entity(entity).despawn_recursive();
+    }
+    link.entities = entities;
+    snapshot.count_factions(&mut registry);
+}
+
+// system: move the own pawn as the player steers it, and send the steering to the server instead
+// of the local simulation
+pub fn predict_pawn_system(
+    mut link: NonSendMut<ServerLink>,
+    time: Res<Time>,
+    seat: Res<LocalSeat>,
+    mut input: ResMut<PlayerInput>,
+    mut pawn_query: Query<(&mut Transform, &mut PreviousTranslation), With<PlayerController>>,
+) {
+    let heading = input.steer.remove(&seat.0);
+    input.steer.clear();
+    let link = &mut *link;
+    let predictor = match link.predictor.as_mut() {
+        Some(predictor) => predictor,
+        None => return,
+    };
+    if let Some(heading) = heading {
+        predictor.steer(heading);
+    }
+    if let Some(message) = predictor.advance(time.delta_seconds()) {
+        link.connection.send(&message);
+    }
+    // until the player steers it, the pawn is drawn from the snapshots like everyone else
+    if let (Some(position), Ok((mut tr, mut previous))) =
+        (predictor.position(), pawn_query.get_single_mut())
+    {
+        tr.translation = position.extend(0.0);
+        previous.0 = tr.translation;
+    }
+}
@@ -0,0 +1,227 @@
+use bevy::prelude::*;
+use crowded_plaza_sim::{
+    GameConfig, Replay, ReplayPlayback, ReplayRecorder, SimClock, SimRng, TICK_SECONDS,
+};
+
+use crate::{launch_args::launch_arg, GameState};
+
+// how far the arrow keys jump
+const SEEK_STEP_SECONDS: f32 = 5.0;
+// clicks this close to the bottom of the window seek on the progress bar
+const PROGRESS_BAR_CLICK_HEIGHT: f32 = 30.0;
+
+#[derive(Component)]
+pub struct ReplayText;
+
+#[derive(Component)]
+pub struct ReplayProgress;
+
+// the replay given with `--replay <file>`, native only since wasm can't read local files
+pub fn load_replay() -> Option<Replay> {
+    let path = launch_arg("replay")?;
+    let replay = std::fs::read(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| Replay::from_bytes(&bytes).map_err(|e| e.to_string()));
+    match replay {
+        Ok(replay) => Some(replay),
+        Err(e) => {
+            warn!("Cannot play replay {0}: {1}", path, e);
+            None
+        }
+    }
+}
+
+// system: record the player's steering from the start of the match
+pub fn start_recording_system(mut commands: Commands, config: Res<GameConfig>, rng: Res<SimRng>) {
+    commands.insert_resource(ReplayRecorder::new(rng.seed(), config.clone()));
+}
+
+// system: start a replay from its recorded config, seeking back plays it again after changes
+pub fn restore_replay_config_system(mut config: ResMut<GameConfig>, playback: Res<ReplayPlayback>) {
+    if *config != playback.0.config {
+        *config = playback.0.config.clone();
+    }
+}
+
+// system: write the match that just ended to `replays/<seed>.replay`
+pub fn save_replay_system(
+    mut commands: Commands,
+    clock: Res<SimClock>,
+    recorder: Option<ResMut<ReplayRecorder>>,
+) {
+    let mut recorder = match recorder {
+        Some(recorder) => recorder,
+        None => return,
+    };
+    recorder.0.end_tick = clock.tick();
+    save_replay(&recorder.0);
+    commands.remove_resource::<ReplayRecorder>();
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn save_replay(replay: &Replay) {
+    let path = format!("replays/{0}.replay", replay.seed);
+    match std::fs::create_dir_all("replays").and_then(|_| std::fs::write(&path, replay.to_bytes()))
+    {
+        Ok(_) => info!("Saved replay, watch it with `--replay {0}`", path),
+        Err(e) => warn!("Cannot save replay {0}: {1}", path, e),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn save_replay(_replay: &Replay) {}
+
+pub fn setup_replay_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Val::Px(20.0),
+                    left: Val::Px(15.0),
+                    ..default()
+                },
+                ..default()
+            },
+            text: Text {
+                sections: vec![
+                    TextSection {
+                        value: "".to_string(),
+                        style: TextStyle {
+                            font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                            font_size: 30.0,
+                            color: Color::WHITE,
+                        },
+                    },
+                    TextSection {
+                        value: "\nspace pause, 1 2 4 speed, arrows seek, click the bar to jump"
+                            .to_string(),
+                        style: TextStyle {
+                            font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                            font_size: 16.0,
+                            color: Color::GRAY,
+                        },
+                    },
+                ],
+                ..default()
+            },
+            ..default()
+        })
+        .insert(ReplayText);
+
+    // progress bar along the bottom of the window
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Val::Px(0.0),
+                    left: Val::Px(0.0),
+                    ..default()
+                },
+                size: Size::new(Val::Percent(100.0), Val::Px(8.0)),
+                ..default()
+            },
+            color: UiColor(Color::rgba(1.0, 1.0, 1.0, 0.2)),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(0.0), Val::Percent(100.0)),
+                        ..default()
+                    },
+                    color: UiColor(Color::RED),
+                    ..default()
+                })
+                .insert(ReplayProgress);
+        });
+}
+
+// system: pause, change speed and seek with keyboard and mouse
+pub fn replay_controls_system(
+    keys: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    playback: Res<ReplayPlayback>,
+    mut clock: ResMut<SimClock>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if keys.just_pressed(KeyCode::Space) {
+        clock.paused = !clock.paused;
+    }
+    for (key, speed) in [
+        (KeyCode::Key1, 1.0),
+        (KeyCode::Key2, 2.0),
+        (KeyCode::Key4, 4.0),
+    ] {
+        if keys.just_pressed(key) {
+            clock.speed = speed;
+        }
+    }
+
+    let end_tick = playback.0.end_tick;
+    let seek_step = (SEEK_STEP_SECONDS / TICK_SECONDS) as u64;
+    let mut target = None;
+    if keys.just_pressed(KeyCode::Left) {
+        target = Some(clock.tick().saturating_sub(seek_step));
+    }
+    if keys.just_pressed(KeyCode::Right) {
+        target = Some(clock.tick() + seek_step);
+    }
+    if keys.just_pressed(KeyCode::Home) {
+        target = Some(0);
+    }
+    if mouse_input.just_pressed(MouseButton::Left) {
+        let window = windows.primary();
+        if let Some(cursor) = window.cursor_position() {
+            // window coordinates start at the bottom left
+            if cursor.y < PROGRESS_BAR_CLICK_HEIGHT {
+                let fraction = (cursor.x / window.width()).clamp(0.0, 1.0);
+                target = Some((fraction * end_tick as f32) as u64);
+            }
+        }
+    }
+
+    if let Some(target) = target {
+        let target = target.min(end_tick);
+        clock.seek(target);
+        if target < clock.tick() {
+            // the simulation only runs forward, play the match again up to the target
+            state.restart().unwrap();
+        }
+    }
+}
+
+// system: show where in the replay we are
+pub fn update_replay_ui_system(
+    clock: Res<SimClock>,
+    playback: Res<ReplayPlayback>,
+    mut text_query: Query<&mut Text, With<ReplayText>>,
+    mut progress_query: Query<&mut Style, With<ReplayProgress>>,
+) {
+    let end_tick = playback.0.end_tick;
+    let status = if clock.is_seeking() {
+        " seeking"
+    } else if clock.tick() >= end_tick {
+        " end"
+    } else if clock.paused {
+        " paused"
+    } else {
+        ""
+    };
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = format!(
+            "Replay {0:.1} / {1:.1}s x{2}{3}",
+            clock.tick() as f32 * TICK_SECONDS,
+            end_tick as f32 * TICK_SECONDS,
+            clock.speed,
+            status
+        );
+    }
+    for mut style in progress_query.iter_mut() {
+        let progress = clock.tick() as f32 / end_tick.max(1) as f32;
+        style.size.width = Val::Percent(progress.min(1.0) * 100.0);
+    }
+}
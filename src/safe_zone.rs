@@ -0,0 +1,121 @@
+use bevy::{
+    pbr::NotShadowCaster,
+    prelude::*,
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+};
+use crowded_plaza_sim::{GameConfig, SafeZone};
+
+use crate::ground_size;
+
+// segments of the circle, enough not to see corners on the largest plazas
+const ZONE_SEGMENTS: usize = 128;
+// width of the line on the edge of the zone, in units
+const ZONE_EDGE_WIDTH: f32 = 0.15;
+// just above the ground, so it isn't hidden by it
+const ZONE_HEIGHT: f32 = 0.01;
+
+// the red ring drawn on the edge of the safe zone, and the tint outside of it up to `outer`
+#[derive(Component)]
+pub struct ZoneRing {
+    outer: f32,
+}
+
+// helper: a flat ring on the ground between `inner` and `outer`
+fn annulus(inner: f32, outer: f32) -> Mesh {
+    let mut positions = Vec::with_capacity(ZONE_SEGMENTS * 2);
+    let mut indices = Vec::with_capacity(ZONE_SEGMENTS * 6);
+    for i in 0..ZONE_SEGMENTS {
+        let (sin, cos) = (i as f32 / ZONE_SEGMENTS as f32 * std::f32::consts::TAU).sin_cos();
+        positions.push([cos * inner, sin * inner, 0.0]);
+        positions.push([cos * outer, sin * outer, 0.0]);
+        let [a, b] = [i as u32 * 2, i as u32 * 2 + 1];
+        let [c, d] = [
+            (a + 2) % (ZONE_SEGMENTS as u32 * 2),
+            (b + 2) % (ZONE_SEGMENTS as u32 * 2),
+        ];
+        indices.extend([a, b, d, a, d, c]);
+    }
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(
+        Mesh::ATTRIBUTE_NORMAL,
+        vec![[0.0, 0.0, 1.0]; positions.len()],
+    );
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0, 0.0]; positions.len()]);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+// helper: the edge of the zone and the tint outside of it, up to the edge of the ground
+fn spawn_zone_ring(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    zone: &SafeZone,
+    outer: f32,
+) {
+    let edge = materials.add(StandardMaterial {
+        base_color: Color::rgb(0.9, 0.1, 0.1),
+        unlit: true,
+        ..default()
+    });
+    let outside = materials.add(StandardMaterial {
+        base_color: Color::rgba(0.9, 0.1, 0.1, 0.2),
+        alpha_mode: AlphaMode::Blend,
+        unlit: true,
+        ..default()
+    });
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: meshes.add(annulus(zone.radius, zone.radius + ZONE_EDGE_WIDTH)),
+            material: edge,
+            transform: Transform::from_xyz(0.0, 0.0, ZONE_HEIGHT),
+            ..default()
+        })
+        .insert(NotShadowCaster)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(PbrBundle {
+                    mesh: meshes.add(annulus(zone.radius + ZONE_EDGE_WIDTH, outer)),
+                    material: outside,
+                    ..default()
+                })
+                .insert(NotShadowCaster);
+        })
+        .insert(ZoneRing { outer });
+}
+
+// system: draw the safe zone of a battle royale once the match has one, and shrink it with
+// the zone
+pub fn update_zone_ring_system(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    zone: Option<Res<SafeZone>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    ring_query: Query<(&ZoneRing, &Handle<Mesh>, &Children)>,
+    tint_query: Query<&Handle<Mesh>, Without<ZoneRing>>,
+) {
+    let zone = match zone {
+        Some(zone) => zone,
+        None => return,
+    };
+    if ring_query.is_empty() {
+        let outer = (ground_size(&config) * 0.5).max(zone.start_radius + ZONE_EDGE_WIDTH);
+        spawn_zone_ring(&mut commands, &mut meshes, &mut materials, &zone, outer);
+        return;
+    }
+    if !zone.is_changed() {
+        return;
+    }
+    for (ring, edge, children) in ring_query.iter() {
+        if let Some(mesh) = meshes.get_mut(edge) {
+            *mesh = annulus(zone.radius, zone.radius + ZONE_EDGE_WIDTH);
+        }
+        for child in children.iter() {
+            if let Some(mesh) = tint_query.get(*child).ok().and_then(|h| meshes.get_mut(h)) {
+                *mesh = annulus(zone.radius + ZONE_EDGE_WIDTH, ring.outer);
+            }
+        }
+    }
+}